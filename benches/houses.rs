@@ -0,0 +1,170 @@
+//! Compares the house storages on a large world. Each repairman repeatedly
+//! reads the status of the house it occupies, repairs it if needed, exchanges
+//! notes and moves to a random neighbour, just like [`Repairman::work`] but
+//! without waiting for the others. Every iteration starts from fresh houses.
+//!
+//! The storages are also compared on whole runs of a smaller world, through
+//! [`Repairman::work`] on the threads engine.
+//!
+//! [`Repairman::work`]: cdn_systems_challenge::repairman::Repairman::work
+
+use cdn_systems_challenge::{
+  engine::Engine,
+  grid::Layout,
+  houses::{Houses, Storage},
+  position::Position,
+  repairman::Id,
+  world::{HouseStatus, Notes, World, WorldConfig},
+  Options,
+};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+  hint::black_box,
+  thread::{self, available_parallelism},
+};
+
+struct Large;
+impl WorldConfig for Large {
+  const MAX_LEN_X: usize = 1000;
+  const MAX_LEN_Y: usize = 1000;
+  const REPAIRMEN: usize = 4000;
+  const HOUSES_NEEDING_REPAIR: usize = 100_000;
+}
+
+struct Busy;
+impl WorldConfig for Busy {
+  const MAX_LEN_X: usize = 40;
+  const MAX_LEN_Y: usize = 40;
+  const REPAIRMEN: usize = 32;
+  const HOUSES_NEEDING_REPAIR: usize = 200;
+}
+
+const TICKS: usize = 10;
+const SEEDS: u64 = 4;
+
+struct Agent {
+  id: Id,
+  position: Position<Large>,
+  notebook: Notes,
+  rng: StdRng,
+}
+
+fn new_houses(storage: Storage) -> Houses {
+  let houses = Houses::new::<Large>(storage, Layout::Dense);
+  let rng = &mut StdRng::seed_from_u64(0);
+  for pos in Position::<Large>::new_random_set(rng, Large::HOUSES_NEEDING_REPAIR) {
+    houses
+      .get(&pos)
+      .set_status(HouseStatus::NeedsRepair)
+      .unwrap();
+  }
+  houses
+}
+
+// Repairmen are spawned inside a `spread` x `spread` square to control how
+// often they meet.
+fn new_agents(repairmen: usize, spread: usize) -> Vec<Agent> {
+  let rng = &mut StdRng::seed_from_u64(0);
+  (0..repairmen)
+    .map(|id| Agent {
+      id: id.into(),
+      position: Position::new(rng.gen_range(0..spread), rng.gen_range(0..spread)).unwrap(),
+      notebook: Notes::default(),
+      rng: StdRng::seed_from_u64(id as u64),
+    })
+    .collect()
+}
+
+fn tick(houses: &Houses, agent: &mut Agent) -> usize {
+  let house = houses.get(&agent.position);
+  let Some(status) = house.try_status().unwrap() else {
+    return 1;
+  };
+
+  let num = agent.notebook.as_mut().entry(agent.id).or_default();
+  if status == HouseStatus::NeedsRepair && house.try_repair().unwrap() {
+    *num += 1;
+  }
+  house.write_note(agent.id, *num).unwrap();
+  house.read_notes(&mut agent.notebook).unwrap();
+  let _ = agent.position.r#move(agent.rng.gen());
+  0
+}
+
+fn bench_storage(c: &mut Criterion) {
+  let threads = available_parallelism().map_or(4, |n| n.get());
+  let mut group = c.benchmark_group("houses");
+  group.sample_size(10);
+
+  for (name, spread) in [("scattered", Large::MAX_LEN_X), ("crowded", 64)] {
+    for repairmen in [1000, Large::REPAIRMEN] {
+      group.throughput(Throughput::Elements((repairmen * TICKS) as u64));
+      for storage in [Storage::Locked, Storage::LockFree] {
+        let id = BenchmarkId::new(format!("{storage:?}/{name}"), repairmen);
+        group.bench_function(id, |b| {
+          let setup = || (new_houses(storage), new_agents(repairmen, spread));
+          let routine = |(houses, mut agents): (Houses, Vec<Agent>)| {
+            let chunk_len = agents.len().div_ceil(threads);
+            let idle = thread::scope(|s| {
+              let handles: Vec<_> = agents
+                .chunks_mut(chunk_len)
+                .map(|chunk| {
+                  let houses = &houses;
+                  s.spawn(move || {
+                    let mut idle = 0;
+                    for _ in 0..TICKS {
+                      idle += chunk.iter_mut().map(|a| tick(houses, a)).sum::<usize>();
+                    }
+                    idle
+                  })
+                })
+                .collect();
+              handles
+                .into_iter()
+                .map(|h| h.join().unwrap())
+                .sum::<usize>()
+            });
+            // The houses are dropped outside of the measurement
+            black_box((houses, idle))
+          };
+          b.iter_batched(setup, routine, BatchSize::PerIteration)
+        });
+      }
+    }
+  }
+  group.finish();
+}
+
+fn bench_work(c: &mut Criterion) {
+  let mut group = c.benchmark_group("work");
+  group.sample_size(10);
+
+  for storage in [Storage::Locked, Storage::LockFree] {
+    let id = BenchmarkId::new(format!("{storage:?}"), SEEDS);
+    group.bench_function(id, |b| {
+      let options = |seed| Options {
+        seed: Some(seed),
+        storage,
+        engine: Engine::Threads,
+        ..Default::default()
+      };
+      let setup = || {
+        (0..SEEDS)
+          .map(|seed| World::<Busy>::with_options(&options(seed)).unwrap())
+          .collect::<Vec<_>>()
+      };
+      let routine = |worlds: Vec<World<Busy>>| {
+        for (seed, world) in (0..).zip(&worlds) {
+          black_box(world.run(&options(seed)).unwrap());
+        }
+        worlds
+      };
+      b.iter_batched(setup, routine, BatchSize::PerIteration)
+    });
+  }
+  group.finish();
+}
+
+criterion_group!(benches, bench_storage, bench_work);
+criterion_main!(benches);
@@ -0,0 +1,85 @@
+//! Compares the exploration strategies on a medium sized world. Besides the
+//! time criterion measures, the average number of ticks and moves of each
+//! strategy is printed, since those are independent of the machine. The tick
+//! of the last repair is compared with the bounds of the offline solver.
+
+use cdn_systems_challenge::{
+  engine::Engine,
+  solver::solve_world,
+  strategy::Strategy,
+  world::{World, WorldConfig},
+  List, Options,
+};
+use clap::ValueEnum;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::hint::black_box;
+
+struct Medium;
+impl WorldConfig for Medium {
+  const MAX_LEN_X: usize = 40;
+  const MAX_LEN_Y: usize = 40;
+  const REPAIRMEN: usize = 8;
+  const HOUSES_NEEDING_REPAIR: usize = 40;
+}
+
+const SEEDS: u64 = 10;
+
+fn options(strategy: Strategy, seed: u64) -> Options {
+  Options {
+    seed: Some(seed),
+    strategy,
+    engine: Engine::Async,
+    ..Default::default()
+  }
+}
+
+fn run(strategy: Strategy, seed: u64) -> List {
+  let options = options(strategy, seed);
+  World::<Medium>::with_options(&options)
+    .unwrap()
+    .run(&options)
+    .unwrap()
+}
+
+/// Prints the averages of a strategy over all the seeds.
+fn report(strategy: Strategy) {
+  let (mut ticks, mut moves, mut last_repair) = (0, 0, 0);
+  let (mut lower_bound, mut upper_bound) = (0.0, 0.0);
+  for seed in 0..SEEDS {
+    let options = options(strategy, seed);
+    let world = World::<Medium>::with_options(&options).unwrap();
+    let solution = solve_world(&world);
+    let metrics = world.run(&options).unwrap().metrics;
+    ticks += metrics.ticks;
+    moves += metrics.moves;
+    last_repair += metrics.last_repair;
+    lower_bound += metrics.last_repair as f64 / solution.lower_bound as f64;
+    upper_bound += metrics.last_repair as f64 / solution.upper_bound as f64;
+  }
+
+  let seeds = SEEDS as usize;
+  let (ticks, moves, last_repair) = (ticks / seeds, moves / seeds, last_repair / seeds);
+  let (lower_bound, upper_bound) = (lower_bound / SEEDS as f64, upper_bound / SEEDS as f64);
+  println!(
+    "strategies/{strategy:?}: Ticks({ticks}), Moves({moves}), LastRepair({last_repair}), \
+     RatioToLowerBound({lower_bound:.2}), RatioToHeuristic({upper_bound:.2})"
+  );
+}
+
+fn bench_strategies(c: &mut Criterion) {
+  let mut group = c.benchmark_group("strategies");
+  group.sample_size(10);
+
+  for &strategy in Strategy::value_variants() {
+    report(strategy);
+
+    let id = BenchmarkId::new(format!("{strategy:?}"), SEEDS);
+    group.bench_function(id, |b| {
+      b.iter(|| (0..SEEDS).for_each(|seed| drop(black_box(run(strategy, seed)))))
+    });
+  }
+  group.finish();
+}
+
+criterion_group!(benches, bench_strategies);
+criterion_main!(benches);
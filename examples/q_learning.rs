@@ -0,0 +1,186 @@
+//! Trains a tabular Q-learning policy on [`Env`] without any visualization,
+//! then compares it with the nearest strategy and a random policy.
+//!
+//! A repairman only sees the house they occupy and their own map, so the
+//! state is the previous action, whether the house needs repair and, for each
+//! direction, whether the neighbour is outside of the world, explored or
+//! unexplored. All the repairmen share the same table. Since such a state
+//! can't tell where the unexplored houses are once every neighbour is
+//! explored, the trained policy keeps acting randomly now and then, or it
+//! would walk back and forth forever.
+//!
+//! ```sh
+//! cargo run --release --example q_learning
+//! ```
+
+use cdn_systems_challenge::{
+  env::{Env, Observation},
+  position::MoveDirection::{self, *},
+  repairman::{Action, MapStatus},
+  strategy::{Explore, Nearest},
+  world::{HouseStatus, WorldConfig},
+  Options,
+};
+use rand::{seq::IteratorRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+struct City;
+impl WorldConfig for City {}
+
+const DIRECTIONS: [MoveDirection; 4] = [Right, Left, Up, Down];
+const ACTIONS: [Action; 5] = [
+  Action::Repair,
+  Action::Move(Right),
+  Action::Move(Left),
+  Action::Move(Up),
+  Action::Move(Down),
+];
+const STATES: usize = ACTIONS.len() * 2 * 3 * 3 * 3 * 3;
+
+const EPISODES: u64 = 2000;
+const EVALUATIONS: u64 = 100;
+const MAX_TICKS: usize = 1000;
+const ALPHA: f64 = 0.1;
+const GAMMA: f64 = 0.95;
+const EPSILON: f64 = 0.2;
+
+type Table = Vec<[f64; ACTIONS.len()]>;
+
+/// The state of a repairman and the actions they may take in it. Moving
+/// outside of the world and repairing a repaired house are never chosen,
+/// since they are the same as idling.
+#[derive(Clone)]
+struct State {
+  index: usize,
+  actions: Vec<usize>,
+}
+
+impl State {
+  fn new(o: &Observation<'_, City>, previous: usize) -> Self {
+    let needs_repair = (o.house_status == HouseStatus::NeedsRepair) as usize;
+    let mut actions = if needs_repair == 1 { vec![0] } else { vec![] };
+    let mut index = previous * 2 + needs_repair;
+    for (i, &dir) in DIRECTIONS.iter().enumerate() {
+      let mut pos = o.position.clone();
+      let neighbour = match pos.r#move(dir) {
+        Err(_) => 0,
+        Ok(()) if o.world_map.peek(&pos) == Some(&MapStatus::Explored) => 1,
+        Ok(()) => 2,
+      };
+      if neighbour != 0 {
+        actions.push(i + 1);
+      }
+      index = index * 3 + neighbour;
+    }
+    Self { index, actions }
+  }
+
+  fn greedy(&self, table: &Table) -> usize {
+    let values = &table[self.index];
+    let best = self
+      .actions
+      .iter()
+      .max_by(|&&a, &&b| values[a].total_cmp(&values[b]));
+    best.copied().unwrap_or_default()
+  }
+
+  fn epsilon_greedy(&self, table: &Table, rng: &mut ChaCha8Rng) -> usize {
+    match rng.gen_bool(EPSILON) {
+      true => self.actions.iter().copied().choose(rng).unwrap_or_default(),
+      false => self.greedy(table),
+    }
+  }
+}
+
+fn train(env: &mut Env<City>, rng: &mut ChaCha8Rng) -> Table {
+  let mut table: Table = vec![[0.0; ACTIONS.len()]; STATES];
+  for episode in 0..EPISODES {
+    let observations = env.reset(episode).unwrap();
+    let mut states: Vec<_> = observations.iter().map(|o| State::new(o, 0)).collect();
+    for _ in 0..MAX_TICKS {
+      let chosen: Vec<_> = states
+        .iter()
+        .map(|s| s.epsilon_greedy(&table, rng))
+        .collect();
+      let actions: Vec<_> = chosen.iter().map(|&a| ACTIONS[a]).collect();
+
+      let (observations, rewards, done) = env.step(&actions).unwrap();
+      for ((o, reward), (state, &a)) in observations
+        .iter()
+        .zip(rewards)
+        .zip(states.iter_mut().zip(&chosen))
+      {
+        let next = State::new(o, a);
+        let future = match o.done {
+          true => 0.0,
+          false => table[next.index][next.greedy(&table)],
+        };
+        let q = &mut table[state.index][a];
+        *q += ALPHA * (reward + GAMMA * future - *q);
+        *state = next;
+      }
+      if done {
+        break;
+      }
+    }
+  }
+  table
+}
+
+/// Returns the average number of ticks and the number of unfinished episodes.
+fn evaluate(
+  env: &mut Env<City>,
+  mut policy: impl FnMut(&Observation<'_, City>, usize) -> usize,
+) -> (usize, usize) {
+  let (mut ticks, mut unfinished) = (0, 0);
+  for seed in EPISODES..EPISODES + EVALUATIONS {
+    let observations = env.reset(seed).unwrap();
+    let mut chosen: Vec<_> = observations.iter().map(|o| policy(o, 0)).collect();
+    for tick in 1..=MAX_TICKS {
+      let actions: Vec<_> = chosen.iter().map(|&a| ACTIONS[a]).collect();
+      let (observations, _, done) = env.step(&actions).unwrap();
+      chosen = observations
+        .iter()
+        .zip(&chosen)
+        .map(|(o, &a)| policy(o, a))
+        .collect();
+      if done || tick == MAX_TICKS {
+        ticks += tick;
+        unfinished += !done as usize;
+        break;
+      }
+    }
+  }
+  (ticks / EVALUATIONS as usize, unfinished)
+}
+
+fn main() {
+  let rng = &mut ChaCha8Rng::seed_from_u64(0);
+  let mut env = Env::<City>::new(&Options::default());
+  let table = train(&mut env, rng);
+
+  let q_learning = evaluate(&mut env, |o, previous| {
+    State::new(o, previous).epsilon_greedy(&table, rng)
+  });
+  let random = evaluate(&mut env, |_, _| rng.gen_range(0..ACTIONS.len()));
+  let nearest = evaluate(&mut env, |o, _| match o.house_status {
+    HouseStatus::NeedsRepair => 0,
+    HouseStatus::Repaired => {
+      let dir = Nearest.next_move(&o.view(), rng).unwrap();
+      let action = dir.map(Action::Move).unwrap_or(Action::Repair);
+      ACTIONS
+        .iter()
+        .position(|&a| a == action)
+        .unwrap_or_default()
+    }
+  });
+
+  println!("Policy     AverageTicks  Unfinished({EVALUATIONS})");
+  for (name, (ticks, unfinished)) in [
+    ("q-learning", q_learning),
+    ("random", random),
+    ("nearest", nearest),
+  ] {
+    println!("{name:10} {ticks:12}  {unfinished:10}");
+  }
+}
@@ -0,0 +1,97 @@
+//! Writes arbitrary notes onto a house and reads them back into a notebook
+//! after every write. Merging must never lose a bigger number or the
+//! announcement, no matter the storage or the order of the writes.
+
+#![no_main]
+
+use cdn_systems_challenge::{
+  grid::Layout,
+  houses::{Houses, Storage},
+  position::Position,
+  world::{Notes, WorldConfig},
+};
+use libfuzzer_sys::fuzz_target;
+use std::collections::BTreeMap;
+
+struct Fuzz;
+impl WorldConfig for Fuzz {
+  const MAX_LEN_X: usize = 1;
+  const MAX_LEN_Y: usize = 1;
+  const REPAIRMEN: usize = 1;
+  const HOUSES_NEEDING_REPAIR: usize = 1;
+}
+
+// Each map of notes is written onto the house, then announced if set
+type Input = Vec<(BTreeMap<u8, usize>, bool)>;
+
+// Whether `a` knows at least as much as `b`
+fn covers(a: &Notes, b: &Notes) -> bool {
+  let (repaired_a, repaired_b) = (a.as_ref(), b.as_ref());
+  let covered = |(id, num)| repaired_a.get(id).is_some_and(|n| n >= num);
+  repaired_b.iter().all(covered) && (a.is_job_complete() || !b.is_job_complete())
+}
+
+fuzz_target!(|input: Input| {
+  // The biggest number of each `Id` and whether anyone announced
+  let mut expected = Notes::default();
+  for (notes, announce) in &input {
+    for (&id, &num) in notes {
+      expected.merge_one((id as usize).into(), num);
+    }
+    if *announce {
+      expected.announce_job_complete();
+    }
+  }
+
+  for storage in [Storage::Locked, Storage::LockFree] {
+    let houses = Houses::new::<Fuzz>(storage, Layout::Dense);
+    let house = houses.get(&Position::<Fuzz>::new(0, 0).unwrap());
+    let mut notebook = Notes::default();
+    for (notes, announce) in &input {
+      for (&id, &num) in notes {
+        house.write_note((id as usize).into(), num).unwrap();
+      }
+      if *announce {
+        house.announce_job_complete().unwrap();
+      }
+
+      let before = notebook.clone();
+      house.read_notes(&mut notebook).unwrap();
+      assert!(
+        covers(&notebook, &before),
+        "{storage:?}: {notebook:?} lost {before:?}"
+      );
+      let house_notes = house.notes().unwrap();
+      assert!(
+        covers(&notebook, &house_notes),
+        "{storage:?}: {notebook:?} missed {house_notes:?}"
+      );
+
+      // Reading the same notes again changes nothing
+      let read = notebook.clone();
+      house.read_notes(&mut notebook).unwrap();
+      assert_eq!(read, notebook, "{storage:?}");
+    }
+    assert_eq!(expected, house.notes().unwrap(), "{storage:?}");
+    assert_eq!(expected, notebook, "{storage:?}");
+  }
+
+  // Merging is commutative
+  let notes: Vec<_> = input
+    .iter()
+    .map(|(repaired, announce)| {
+      let mut notes = Notes::default();
+      repaired
+        .iter()
+        .for_each(|(&id, &num)| notes.merge_one((id as usize).into(), num));
+      if *announce {
+        notes.announce_job_complete();
+      }
+      notes
+    })
+    .collect();
+  let (mut forward, mut backward) = (Notes::default(), Notes::default());
+  notes.iter().for_each(|n| forward.merge(n));
+  notes.iter().rev().for_each(|n| backward.merge(n));
+  assert_eq!(forward, backward);
+});
@@ -0,0 +1,39 @@
+//! Parses arbitrary scenario files, and loads the ones that are valid.
+
+#![no_main]
+
+use cdn_systems_challenge::{
+  houses::Storage,
+  scenario::Scenario,
+  world::{World, WorldConfig},
+  Options,
+};
+use libfuzzer_sys::fuzz_target;
+
+struct Fuzz;
+impl WorldConfig for Fuzz {
+  const MAX_LEN_X: usize = 4;
+  const MAX_LEN_Y: usize = 3;
+  const REPAIRMEN: usize = 2;
+  const HOUSES_NEEDING_REPAIR: usize = 2;
+}
+
+fuzz_target!(|s: &str| {
+  let Ok(scenario) = s.parse::<Scenario>() else {
+    return;
+  };
+  let parsed = scenario.to_string().parse::<Scenario>();
+  assert_eq!(Some(&scenario), parsed.as_ref().ok(), "{scenario}");
+
+  for storage in [Storage::Locked, Storage::LockFree] {
+    let options = Options {
+      storage,
+      seed: Some(0),
+      ..Default::default()
+    };
+    if let Ok(world) = World::<Fuzz>::with_scenario(&scenario, &options) {
+      assert_eq!(scenario.spawns.len(), world.spawns().len());
+      assert_eq!(scenario.obstacles.len(), world.obstacles().len());
+    }
+  }
+});
@@ -0,0 +1,38 @@
+//! Parses arbitrary snapshot files, and restores the worlds of the ones that
+//! are valid.
+
+#![no_main]
+
+use cdn_systems_challenge::{
+  houses::Storage,
+  snapshot::Snapshot,
+  world::{World, WorldConfig},
+  Options,
+};
+use libfuzzer_sys::fuzz_target;
+
+struct Fuzz;
+impl WorldConfig for Fuzz {
+  const MAX_LEN_X: usize = 4;
+  const MAX_LEN_Y: usize = 3;
+  const REPAIRMEN: usize = 2;
+  const HOUSES_NEEDING_REPAIR: usize = 2;
+}
+
+fuzz_target!(|s: &str| {
+  let Ok(snapshot) = s.parse::<Snapshot>() else {
+    return;
+  };
+  let parsed = snapshot.to_string().parse::<Snapshot>();
+  assert_eq!(Some(&snapshot), parsed.as_ref().ok(), "{snapshot}");
+
+  for storage in [Storage::Locked, Storage::LockFree] {
+    let options = Options {
+      storage,
+      ..Default::default()
+    };
+    if let Ok(world) = World::<Fuzz>::with_snapshot(&snapshot, &options) {
+      assert_eq!(snapshot.seed, world.seed());
+    }
+  }
+});
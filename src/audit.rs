@@ -0,0 +1,340 @@
+//! Checks that the repairmen follow the rules of the challenge while the
+//! world runs: a repairman only reads and writes the house they stand in, no
+//! two repairmen write onto a house at the same time and a repairman only
+//! moves themselves. Enabled by `Options::audit`.
+//!
+//! Lock-free houses let writes overlap by design, since every note is written
+//! atomically on its own. Overlapping writes onto them don't fail the run, but
+//! are counted in `Metrics::concurrent_writes`.
+
+use crate::{
+  error::{CdnErrorKind, CdnResult},
+  grid::{Grid, Layout},
+  position::Position,
+  repairman::Id,
+  world::WorldConfig,
+};
+use std::{
+  fmt::{Display, Formatter, Result as FmtResult},
+  sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A broken rule, which fails the run with
+/// [`CdnErrorKind::RuleViolation`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Violation {
+  /// The repairman accessed the house at `(x, y)` without standing in it.
+  ForeignHouse { id: Id, x: usize, y: usize },
+  /// The repairman wrote onto the house at `(x, y)` while `other` was
+  /// writing onto it.
+  ConcurrentWrite {
+    id: Id,
+    other: Id,
+    x: usize,
+    y: usize,
+  },
+  /// The repairman moved `other`.
+  ForeignMove { id: Id, other: Id },
+}
+
+/// Observes the writes onto every house. See [`crate::world::World`] for
+/// the other checks.
+#[derive(Debug)]
+pub struct Auditor {
+  // The `Id` plus one of the repairman writing onto each house, or zero
+  writers: Grid<AtomicUsize>,
+  concurrent_writes: AtomicUsize,
+}
+
+/// Marks a house as being written onto until it is dropped. See
+/// [`Auditor::enter`].
+#[derive(Debug)]
+pub struct WriteGuard<'a>(Option<&'a AtomicUsize>);
+
+impl Auditor {
+  pub fn new<C: WorldConfig>(layout: Layout) -> Self {
+    Self {
+      writers: Grid::new::<C>(layout),
+      concurrent_writes: AtomicUsize::new(0),
+    }
+  }
+
+  /// Marks the house at `pos` as being written onto by `id`. Fails if
+  /// someone else is writing onto it.
+  pub fn enter<C: WorldConfig>(&self, id: Id, pos: &Position<C>) -> CdnResult<WriteGuard<'_>> {
+    let writer = self.writers.get(pos);
+    let entered = writer.compare_exchange(0, *id.as_ref() + 1, Ordering::AcqRel, Ordering::Acquire);
+    match entered {
+      Ok(_) => Ok(WriteGuard(Some(writer))),
+      Err(other) => {
+        let (x, y, other) = (pos.x(), pos.y(), (other - 1).into());
+        let violation = Violation::ConcurrentWrite { id, other, x, y };
+        Err(CdnErrorKind::RuleViolation(violation).into())
+      }
+    }
+  }
+
+  /// Same as [`Self::enter`], but if someone else is writing onto the house,
+  /// the write is counted as concurrent instead of failing.
+  pub fn enter_shared<C: WorldConfig>(&self, id: Id, pos: &Position<C>) -> WriteGuard<'_> {
+    self.enter(id, pos).unwrap_or_else(|_| {
+      self.concurrent_writes.fetch_add(1, Ordering::Relaxed);
+      WriteGuard(None)
+    })
+  }
+
+  /// The number of writes counted by [`Self::enter_shared`] so far.
+  pub fn concurrent_writes(&self) -> usize {
+    self.concurrent_writes.load(Ordering::Relaxed)
+  }
+}
+
+impl Drop for WriteGuard<'_> {
+  fn drop(&mut self) {
+    if let Some(writer) = self.0 {
+      writer.store(0, Ordering::Release);
+    }
+  }
+}
+
+impl Display for Violation {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    match *self {
+      Self::ForeignHouse { id, x, y } => {
+        let id = id.as_ref();
+        write!(
+          f,
+          "repairman {id} accessed ({x}, {y}) without standing in it"
+        )
+      }
+      Self::ConcurrentWrite { id, other, x, y } => {
+        let (id, other) = (id.as_ref(), other.as_ref());
+        write!(
+          f,
+          "repairman {id} wrote onto ({x}, {y}) while {other} was writing onto it"
+        )
+      }
+      Self::ForeignMove { id, other } => {
+        write!(
+          f,
+          "repairman {} moved repairman {}",
+          id.as_ref(),
+          other.as_ref()
+        )
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Auditor, Violation};
+  use crate::{
+    engine::Engine,
+    error::{CdnErrorKind, CdnResult},
+    grid::Layout,
+    houses::{HouseRef, Storage},
+    position::{MoveDirection, Position},
+    repairman::{Id, Repairman},
+    strategy::{Explore, View},
+    world::{
+      test::{runs, Tst},
+      World, WorldConfig,
+    },
+    List, Options,
+  };
+  use rand_chacha::ChaCha8Rng;
+  use std::mem;
+
+  /// A rule the faulty repairman `0` breaks before every move, so that real
+  /// runs can be checked to fail.
+  #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+  enum Fault {
+    /// Accesses the house of their spawn after leaving it.
+    ForeignHouse,
+    /// Moves the repairman `1` instead of themselves.
+    ForeignMove,
+    /// Never finishes writing onto a house before leaving it.
+    UnfinishedWrite,
+  }
+
+  /// Explores like `inner`, but breaks the rule of `fault`.
+  struct Faulty<'a, C: WorldConfig> {
+    fault: Fault,
+    world: &'a World<C>,
+    inner: Box<dyn Explore<C> + 'a>,
+    spawn: Option<HouseRef<'a>>,
+  }
+
+  impl<C: WorldConfig> Explore<C> for Faulty<'_, C> {
+    fn next_move(
+      &mut self,
+      view: &View<'_, C>,
+      rng: &mut ChaCha8Rng,
+    ) -> CdnResult<Option<MoveDirection>> {
+      let (world, id) = (self.world, view.id);
+      match self.fault {
+        Fault::ForeignHouse => {
+          // SAFETY: Only the repairman moves themselves
+          let spawn = *self
+            .spawn
+            .get_or_insert_with(|| unsafe { world.get_repairman_house(id) });
+          world.audit_access(id, spawn)?;
+        }
+        Fault::ForeignMove => {
+          let dir = [MoveDirection::Right, MoveDirection::Left][usize::from(view.position.x() > 0)];
+          // SAFETY: The move fails before the other repairman is touched
+          unsafe { world.move_repairman_as(id, 1.into(), dir)? };
+        }
+        Fault::UnfinishedWrite => {
+          if let Some(Ok(guard)) = world.auditor.as_ref().map(|a| a.enter(id, view.position)) {
+            mem::forget(guard);
+          }
+        }
+      }
+      self.inner.next_move(view, rng)
+    }
+  }
+
+  fn violation<T>(result: CdnResult<T>) -> Violation {
+    match result.err().as_ref().map(|e| e.kind()) {
+      Some(CdnErrorKind::RuleViolation(violation)) => *violation,
+      _ => panic!("no violation"),
+    }
+  }
+
+  #[test]
+  fn test_audited_runs() {
+    // Auditing a run that follows the rules doesn't change it
+    for options in runs(8) {
+      let options = Options {
+        announce: true,
+        deterministic: true,
+        ..options
+      };
+      let audited = Options {
+        audit: true,
+        ..options.clone()
+      };
+      let list = World::<Tst>::with_options(&options)
+        .unwrap()
+        .run(&options)
+        .unwrap();
+      let world = World::<Tst>::with_options(&audited).unwrap();
+      assert_eq!(list, world.run(&audited).unwrap(), "{options:?}");
+    }
+  }
+
+  // A run of `Tst` in which the repairman `0` is faulty, and every
+  // repairman visits every house
+  fn faulty_run(fault: Fault, engine: Engine, storage: Storage) -> CdnResult<List> {
+    let options = Options {
+      seed: Some(0),
+      engine,
+      storage,
+      hidden_total: true,
+      audit: true,
+      ..Default::default()
+    };
+    let world = World::<Tst>::with_options(&options)?;
+    let hire = |id: Id| {
+      let spawn = &world.spawns()[*id.as_ref()];
+      let mut explorer = options.strategy.explorer(spawn);
+      if id == 0.into() {
+        let world = &world;
+        let (inner, spawn) = (explorer, None);
+        explorer = Box::new(Faulty {
+          fault,
+          world,
+          inner,
+          spawn,
+        });
+      }
+      // SAFETY: The engines hire every repairman once
+      unsafe { Repairman::with_explorer(id, &world, &options, explorer) }
+    };
+    world.run_with(&options, &hire)
+  }
+
+  #[test]
+  fn test_faulty_runs() {
+    for engine in [Engine::Threads, Engine::Async] {
+      for storage in [Storage::Locked, Storage::LockFree] {
+        let result = faulty_run(Fault::ForeignHouse, engine, storage);
+        assert!(
+          matches!(violation(result), Violation::ForeignHouse { id, .. } if id == 0.into()),
+          "{engine:?} {storage:?}"
+        );
+        let result = faulty_run(Fault::ForeignMove, engine, storage);
+        let (id, other) = (0.into(), 1.into());
+        assert_eq!(Violation::ForeignMove { id, other }, violation(result));
+      }
+
+      let result = faulty_run(Fault::UnfinishedWrite, engine, Storage::Locked);
+      assert!(
+        matches!(violation(result), Violation::ConcurrentWrite { other, .. } if other == 0.into()),
+        "{engine:?}"
+      );
+      let list = faulty_run(Fault::UnfinishedWrite, engine, Storage::LockFree).unwrap();
+      assert!(list.metrics.concurrent_writes > 0, "{engine:?}");
+      assert!(list.to_string().contains("ConcurrentWrites"));
+    }
+  }
+
+  #[test]
+  fn test_concurrent_write() {
+    let auditor = Auditor::new::<Tst>(Layout::Sparse);
+    let pos = Position::<Tst>::new(3, 2).unwrap();
+    let guard = auditor.enter(0.into(), &pos).unwrap();
+    let other = Position::<Tst>::new(2, 1).unwrap();
+    drop(auditor.enter(1.into(), &other).unwrap());
+    assert_eq!(
+      Violation::ConcurrentWrite {
+        id: 1.into(),
+        other: 0.into(),
+        x: 3,
+        y: 2
+      },
+      violation(auditor.enter(1.into(), &pos))
+    );
+    drop(guard);
+    assert!(auditor.enter(1.into(), &pos).is_ok());
+  }
+
+  #[test]
+  fn test_foreign_access() {
+    let options = Options {
+      seed: Some(0),
+      audit: true,
+      ..Default::default()
+    };
+    let world = World::<Tst>::with_options(&options).unwrap();
+    let (a, b) = (0.into(), 1.into());
+    // SAFETY: Nothing else accesses the world
+    unsafe {
+      let house = world.get_repairman_house(b);
+      assert!(world.audit_access(b, house).is_ok());
+      let pos = world.get_repairman_position(b);
+      let (x, y) = (pos.x(), pos.y());
+      assert_ne!(world.get_repairman_position(a), pos);
+      assert_eq!(
+        Violation::ForeignHouse { id: a, x, y },
+        violation(world.audit_access(a, house))
+      );
+
+      let dir = [MoveDirection::Right, MoveDirection::Left][usize::from(x + 1 == Tst::MAX_LEN_X)];
+      assert_eq!(
+        Violation::ForeignMove { id: a, other: b },
+        violation(world.move_repairman_as(a, b, dir))
+      );
+      assert_eq!((x, y), (pos.x(), pos.y()));
+      assert!(world.move_repairman_as(b, b, dir).is_ok());
+    }
+
+    // Nothing is checked without auditing
+    let world = World::<Tst>::with_options(&Options::default()).unwrap();
+    // SAFETY: Nothing else accesses the world
+    let house = unsafe { world.get_repairman_house(1.into()) };
+    assert!(world.audit_access(0.into(), house).is_ok());
+  }
+}
@@ -1,12 +1,25 @@
+use crate::error::{CdnErrorKind, CdnResult};
+#[cfg(loom)]
+use loom::sync::{Arc, Condvar, Mutex};
+#[cfg(not(loom))]
+use std::sync::{Arc, Condvar, Mutex};
 use std::{
   fmt,
-  sync::{Arc, Condvar, Mutex},
+  future::Future,
+  mem,
+  pin::Pin,
+  sync::PoisonError,
+  task::{Context, Poll, Waker},
+  time::{Duration, Instant},
 };
 
 /// A combination of [`std::sync::Barrier`] and `crossbeam::sync::WaitGroup`.
 /// It behaves exactly like [`std::sync::Barrier`], but the `num_threads`
 /// is dynamic and can be reduced or increased depending on the number of alive
 /// references, similar to `crossbeam::sync::WaitGroup`.
+///
+/// Async tasks can rendezvous with [`Barrier::wait_async()`] instead of
+/// blocking the thread.
 pub struct Barrier {
   inner: Arc<Inner>,
 }
@@ -14,13 +27,36 @@ pub struct Barrier {
 struct Inner {
   lock: Mutex<BarrierState>,
   cvar: Condvar,
-  num_threads: Mutex<usize>,
 }
 
-// The inner state of a double barrier
+// The inner state of a double barrier. `num_threads` lives behind the same
+// lock as `count`, otherwise a reference dropped while the others are waiting
+// could miss the rendezvous and leave them blocked forever.
 struct BarrierState {
   count: usize,
   generation_id: usize,
+  num_threads: usize,
+  cancelled: bool,
+  // The tasks waiting in `Barrier::wait_async()`, in the order of arrival
+  wakers: Vec<Waker>,
+}
+
+/// Cancels every current and future [`Barrier::wait()`] of the [`Barrier`] it
+/// was created from. Holding a token does not count as a reference to the
+/// barrier.
+#[derive(Clone)]
+pub struct CancellationToken {
+  inner: Arc<Inner>,
+}
+
+/// The future returned by [`Barrier::wait_async()`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct BarrierWait<'a> {
+  barrier: &'a Barrier,
+  // The generation this task arrived in, `None` if it has not arrived yet
+  generation_id: Option<usize>,
+  is_leader: bool,
+  done: bool,
 }
 
 /// A `BarrierWaitResult` is returned by [`Barrier::wait()`] when all threads
@@ -29,10 +65,10 @@ struct BarrierState {
 /// # Examples
 ///
 /// ```
-/// use std::sync::Barrier;
+/// use cdn_systems_challenge::barrier::Barrier;
 ///
-/// let barrier = Barrier::new(1);
-/// let barrier_wait_result = barrier.wait();
+/// let barrier = Barrier::new();
+/// let barrier_wait_result = barrier.wait().unwrap();
 /// ```
 pub struct BarrierWaitResult(bool);
 
@@ -42,7 +78,7 @@ impl Barrier {
   /// # Examples
   ///
   /// ```
-  /// use std::sync::Barrier;
+  /// use cdn_systems_challenge::barrier::Barrier;
   ///
   /// let barrier = Barrier::new();
   /// ```
@@ -61,46 +97,179 @@ impl Barrier {
   /// from this function, and all other threads will receive a result that
   /// will return `false` from [`BarrierWaitResult::is_leader()`].
   ///
+  /// Returns [`CdnErrorKind::Cancelled`] if the barrier is cancelled by a
+  /// [`CancellationToken`], and [`CdnErrorKind::PoisonError`] if a thread
+  /// panicked while holding the internal lock.
+  ///
   /// # Examples
   ///
   /// ```
-  /// use std::sync::{Arc, Barrier};
+  /// use cdn_systems_challenge::barrier::Barrier;
   /// use std::thread;
   ///
   /// let n = 10;
   /// let mut handles = Vec::with_capacity(n);
-  /// let barrier = Arc::new(Barrier::new(n));
+  /// let barrier = Barrier::new();
   /// for _ in 0..n {
-  ///     let c = Arc::clone(&barrier);
+  ///     let c = barrier.clone();
   ///     // The same messages will be printed together.
   ///     // You will NOT see any interleaving.
   ///     handles.push(thread::spawn(move|| {
   ///         println!("before wait");
-  ///         c.wait();
+  ///         c.wait().unwrap();
   ///         println!("after wait");
   ///     }));
   /// }
+  /// // The threads do not wait for this reference anymore.
+  /// drop(barrier);
   /// // Wait for other threads to finish.
   /// for handle in handles {
   ///     handle.join().unwrap();
   /// }
   /// ```
-  pub fn wait(&self) -> BarrierWaitResult {
-    let mut lock = self.inner.lock.lock().unwrap();
+  pub fn wait(&self) -> CdnResult<BarrierWaitResult> {
+    self.wait_until(None)
+  }
+
+  /// Same as [`Barrier::wait()`], but gives up after `timeout` and returns
+  /// [`CdnErrorKind::TimedOut`]. A thread that timed out no longer counts
+  /// towards the current rendezvous.
+  pub fn wait_timeout(&self, timeout: Duration) -> CdnResult<BarrierWaitResult> {
+    self.wait_until(Some(Instant::now() + timeout))
+  }
+
+  /// Same as [`Barrier::wait()`], but for async tasks. It counts as a thread
+  /// of the barrier as well.
+  ///
+  /// The waiting tasks are woken up in the order of their arrival, including
+  /// the leader, which makes the order of a single threaded executor
+  /// deterministic.
+  pub fn wait_async(&self) -> BarrierWait<'_> {
+    BarrierWait {
+      barrier: self,
+      generation_id: None,
+      is_leader: false,
+      done: false,
+    }
+  }
+
+  /// Returns a new [`CancellationToken`] for this barrier.
+  #[must_use]
+  pub fn cancellation_token(&self) -> CancellationToken {
+    CancellationToken {
+      inner: self.inner.clone(),
+    }
+  }
+
+  fn wait_until(&self, deadline: Option<Instant>) -> CdnResult<BarrierWaitResult> {
+    let mut lock = self.inner.lock.lock()?;
+    if lock.cancelled {
+      return Err(CdnErrorKind::Cancelled.into());
+    }
+
     let local_gen = lock.generation_id;
     lock.count += 1;
-    if lock.count < *self.inner.num_threads.lock().unwrap() {
-      let _guard = self
-        .inner
-        .cvar
-        .wait_while(lock, |state| local_gen == state.generation_id)
-        .unwrap();
-      BarrierWaitResult(false)
-    } else {
-      lock.count = 0;
-      lock.generation_id = lock.generation_id.wrapping_add(1);
+    if lock.count >= lock.num_threads {
+      lock.release();
       self.inner.cvar.notify_all();
-      BarrierWaitResult(true)
+      return Ok(BarrierWaitResult(true));
+    }
+
+    // `Condvar::wait_while` is not available in loom, hence the loop.
+    while local_gen == lock.generation_id {
+      if lock.cancelled {
+        return Err(CdnErrorKind::Cancelled.into());
+      }
+
+      lock = match deadline {
+        None => self.inner.cvar.wait(lock)?,
+        Some(deadline) => {
+          let timeout = deadline.saturating_duration_since(Instant::now());
+          if timeout.is_zero() {
+            lock.count -= 1;
+            return Err(CdnErrorKind::TimedOut.into());
+          }
+          self.inner.cvar.wait_timeout(lock, timeout)?.0
+        }
+      };
+    }
+    Ok(BarrierWaitResult(false))
+  }
+}
+
+impl BarrierState {
+  // Starts a new generation and lets the waiting threads go.
+  fn release(&mut self) {
+    self.count = 0;
+    self.generation_id = self.generation_id.wrapping_add(1);
+    self.wake_all();
+  }
+
+  fn wake_all(&mut self) {
+    mem::take(&mut self.wakers)
+      .into_iter()
+      .for_each(Waker::wake);
+  }
+}
+
+impl Future for BarrierWait<'_> {
+  type Output = CdnResult<BarrierWaitResult>;
+
+  fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    let barrier = self.barrier;
+    let inner = &barrier.inner;
+    let mut lock = match inner.lock.lock() {
+      Ok(lock) => lock,
+      Err(e) => return Poll::Ready(Err(e.into())),
+    };
+
+    match self.generation_id {
+      Some(gen) if gen != lock.generation_id => {
+        drop(lock);
+        self.done = true;
+        return Poll::Ready(Ok(BarrierWaitResult(self.is_leader)));
+      }
+      _ if lock.cancelled => {
+        drop(lock);
+        self.done = true;
+        return Poll::Ready(Err(CdnErrorKind::Cancelled.into()));
+      }
+      Some(_) => {
+        if !lock.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+          lock.wakers.push(cx.waker().clone());
+        }
+      }
+      None => {
+        let gen = lock.generation_id;
+        lock.count += 1;
+        lock.wakers.push(cx.waker().clone());
+        if lock.count >= lock.num_threads {
+          lock.release();
+          inner.cvar.notify_all();
+          drop(lock);
+          self.is_leader = true;
+        }
+        self.generation_id = Some(gen);
+      }
+    }
+    Poll::Pending
+  }
+}
+
+impl Drop for BarrierWait<'_> {
+  // A task that stops waiting no longer counts towards the rendezvous.
+  fn drop(&mut self) {
+    let Some(gen) = self.generation_id.filter(|_| !self.done) else {
+      return;
+    };
+    let mut lock = self
+      .barrier
+      .inner
+      .lock
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner);
+    if gen == lock.generation_id {
+      lock.count -= 1;
     }
   }
 }
@@ -112,9 +281,11 @@ impl Default for Barrier {
         lock: Mutex::new(BarrierState {
           count: 0,
           generation_id: 0,
+          num_threads: 1,
+          cancelled: false,
+          wakers: Vec::new(),
         }),
         cvar: Condvar::new(),
-        num_threads: Mutex::new(1),
       }),
     }
   }
@@ -122,19 +293,32 @@ impl Default for Barrier {
 
 impl fmt::Debug for Barrier {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    let num_threads: &usize = &self.inner.num_threads.lock().unwrap();
+    let lock = self
+      .inner
+      .lock
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner);
+    let num_threads = lock.num_threads;
     f.debug_struct("Barrier")
-      .field("num_threads", num_threads)
+      .field("num_threads", &num_threads)
+      .field("cancelled", &lock.cancelled)
       .finish_non_exhaustive()
   }
 }
 
 impl Drop for Barrier {
   fn drop(&mut self) {
-    let mut count = self.inner.num_threads.lock().unwrap();
-    *count -= 1;
+    let mut lock = self
+      .inner
+      .lock
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner);
+    lock.num_threads -= 1;
 
-    if *count == 0 {
+    // The dropped reference may have been the last one the waiting threads
+    // were waiting for. None of them is the leader of this generation.
+    if lock.count > 0 && lock.count >= lock.num_threads {
+      lock.release();
       self.inner.cvar.notify_all();
     }
   }
@@ -142,8 +326,13 @@ impl Drop for Barrier {
 
 impl Clone for Barrier {
   fn clone(&self) -> Self {
-    let mut count = self.inner.num_threads.lock().unwrap();
-    *count += 1;
+    let mut lock = self
+      .inner
+      .lock
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner);
+    lock.num_threads += 1;
+    drop(lock);
 
     Self {
       inner: self.inner.clone(),
@@ -151,6 +340,39 @@ impl Clone for Barrier {
   }
 }
 
+impl CancellationToken {
+  /// Cancels the barrier and wakes up all the waiting threads.
+  pub fn cancel(&self) {
+    let mut lock = self
+      .inner
+      .lock
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner);
+    lock.cancelled = true;
+    lock.wake_all();
+    self.inner.cvar.notify_all();
+  }
+
+  /// Returns `true` if [`CancellationToken::cancel()`] has been called.
+  #[must_use]
+  pub fn is_cancelled(&self) -> bool {
+    let lock = self
+      .inner
+      .lock
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner);
+    lock.cancelled
+  }
+}
+
+impl fmt::Debug for CancellationToken {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("CancellationToken")
+      .field("is_cancelled", &self.is_cancelled())
+      .finish()
+  }
+}
+
 impl fmt::Debug for BarrierWaitResult {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     f.debug_struct("BarrierWaitResult")
@@ -169,10 +391,10 @@ impl BarrierWaitResult {
   /// # Examples
   ///
   /// ```
-  /// use std::sync::Barrier;
+  /// use cdn_systems_challenge::barrier::Barrier;
   ///
-  /// let barrier = Barrier::new(1);
-  /// let barrier_wait_result = barrier.wait();
+  /// let barrier = Barrier::new();
+  /// let barrier_wait_result = barrier.wait().unwrap();
   /// println!("{:?}", barrier_wait_result.is_leader());
   /// ```
   #[must_use]
@@ -180,3 +402,130 @@ impl BarrierWaitResult {
     self.0
   }
 }
+
+// Run with `RUSTFLAGS="--cfg loom" cargo test --release loom_test`
+#[cfg(all(test, loom))]
+mod loom_test {
+  use super::Barrier;
+  use crate::error::CdnErrorKind;
+  use loom::thread;
+
+  #[test]
+  fn test_single_leader() {
+    loom::model(|| {
+      let barrier = Barrier::new();
+      let handles: Vec<_> = (0..2)
+        .map(|_| {
+          let bar = barrier.clone();
+          thread::spawn(move || bar.wait().unwrap().is_leader())
+        })
+        .collect();
+
+      let mut leaders = barrier.wait().unwrap().is_leader() as usize;
+      for h in handles {
+        leaders += h.join().unwrap() as usize;
+      }
+      assert_eq!(1, leaders);
+    });
+  }
+
+  #[test]
+  fn test_reuse() {
+    loom::model(|| {
+      let barrier = Barrier::new();
+      let bar = barrier.clone();
+      let h = thread::spawn(move || {
+        (0..2)
+          .map(|_| bar.wait().unwrap().is_leader() as usize)
+          .sum::<usize>()
+      });
+
+      let leaders: usize = (0..2)
+        .map(|_| barrier.wait().unwrap().is_leader() as usize)
+        .sum();
+      assert_eq!(2, leaders + h.join().unwrap());
+    });
+  }
+
+  #[test]
+  fn test_drop_while_waiting() {
+    loom::model(|| {
+      let barrier = Barrier::new();
+      let bar1 = barrier.clone();
+      let bar2 = barrier.clone();
+      let h1 = thread::spawn(move || {
+        bar1.wait().unwrap();
+      });
+      let h2 = thread::spawn(move || drop(bar2));
+
+      barrier.wait().unwrap();
+      h1.join().unwrap();
+      h2.join().unwrap();
+    });
+  }
+
+  #[test]
+  fn test_clone_while_waiting() {
+    loom::model(|| {
+      let barrier = Barrier::new();
+      let bar1 = barrier.clone();
+      let h1 = thread::spawn(move || {
+        bar1.wait().unwrap();
+      });
+
+      let bar2 = barrier.clone();
+      let h2 = thread::spawn(move || {
+        bar2.wait().unwrap();
+      });
+      drop(barrier);
+
+      h1.join().unwrap();
+      h2.join().unwrap();
+    });
+  }
+  #[test]
+  fn test_cancel_while_waiting() {
+    loom::model(|| {
+      let barrier = Barrier::new();
+      let token = barrier.cancellation_token();
+      let bar = barrier.clone();
+      let h = thread::spawn(move || bar.wait().unwrap_err());
+
+      token.cancel();
+      assert!(matches!(h.join().unwrap().kind(), CdnErrorKind::Cancelled));
+      barrier.wait().unwrap_err();
+    });
+  }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+  use super::Barrier;
+  use crate::error::CdnErrorKind;
+  use std::{thread, time::Duration};
+
+  #[test]
+  fn test_wait_timeout() {
+    let barrier = Barrier::new();
+    let bar = barrier.clone();
+    let e = barrier.wait_timeout(Duration::from_millis(10)).unwrap_err();
+    assert!(matches!(e.kind(), CdnErrorKind::TimedOut));
+
+    // The timed out thread must not be counted in the next rendezvous
+    let h = thread::spawn(move || bar.wait().unwrap().is_leader());
+    let is_leader = barrier.wait().unwrap().is_leader();
+    assert!(is_leader ^ h.join().unwrap());
+  }
+
+  #[test]
+  fn test_cancel() {
+    let barrier = Barrier::new();
+    let bar = barrier.clone();
+    let h = thread::spawn(move || bar.wait().unwrap_err());
+    barrier.cancellation_token().cancel();
+
+    assert!(matches!(h.join().unwrap().kind(), CdnErrorKind::Cancelled));
+    let e = barrier.wait().unwrap_err();
+    assert!(matches!(e.kind(), CdnErrorKind::Cancelled));
+  }
+}
@@ -0,0 +1,589 @@
+use self::{executor::Executor, turnstile::Turnstile};
+use crate::{
+  barrier::Barrier,
+  error::{CdnError, CdnErrorKind, CdnResult, RepairmanContext},
+  repairman::{Id, Repairman},
+  screen::Screen,
+  world::{World, WorldConfig},
+  List, Options,
+};
+use clap::ValueEnum;
+use std::{
+  cell::{Cell, RefCell},
+  mem, thread,
+  time::Instant,
+};
+
+/// The way repairmen are executed by [`World::run`].
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Engine {
+  /// Every repairman runs on their own OS thread.
+  #[default]
+  Threads,
+  /// Repairmen run as async tasks on a single threaded executor. This scales
+  /// to a lot more repairmen than threads.
+  Async,
+}
+
+/// This function spawns new threads for each [`Repairman`] in the world to
+/// execute their tasks, while the current thread displays the world. Each
+/// repairman is created by `hire`, with their `Id`.
+pub(crate) fn run_threads<'a, C, H>(
+  world: &'a World<C>,
+  options: &Options,
+  hire: &H,
+) -> CdnResult<List>
+where
+  C: WorldConfig + Sync,
+  H: Fn(Id) -> Repairman<'a, C> + Sync,
+{
+  let mut deadline = options.time_limit.map(|t| Instant::now() + t);
+  let turnstile = options
+    .deterministic
+    .then(|| Turnstile::new(world.get_repairmen_ids()));
+
+  thread::scope(|s| {
+    let mut handles = Vec::new();
+    let barrier = Barrier::new();
+    let token = barrier.cancellation_token();
+    for id in world.get_repairmen_ids() {
+      let bar = barrier.clone();
+      let turnstile = turnstile.as_ref();
+      let h = s.spawn(move || {
+        let man = hire(id);
+        match turnstile {
+          None => man.work(&bar),
+          Some(turnstile) => turnstile.work(man, &bar),
+        }
+      });
+      handles.push((id, h));
+    }
+
+    let mut list = List::default();
+    // The ticks played so far, to tell when a repairman thread panicked
+    let mut tick = 0;
+    let res = (|| {
+      let mut screen = Screen::new(options)?;
+      while !handles.is_empty() {
+        screen.draw(world)?;
+
+        let (finished, rest) = mem::take(&mut handles)
+          .into_iter()
+          .partition(|(_, h)| h.is_finished());
+        handles = rest;
+        for (id, h) in finished {
+          let report = h.join().map_err(|e| {
+            // SAFETY: The thread of the repairman is finished
+            let pos = unsafe { world.get_repairman_position(id) };
+            let (x, y) = (pos.x(), pos.y());
+            CdnError::from(e).with_repairman(RepairmanContext { id, tick, x, y })
+          });
+          list.insert(report??);
+        }
+
+        match deadline {
+          Some(d) if Instant::now() >= d => return Err(CdnErrorKind::TimedOut.into()),
+          Some(d) => barrier.wait_timeout(d.saturating_duration_since(Instant::now()))?,
+          None => barrier.wait()?,
+        };
+        tick += 1;
+        let paused = screen.wait(world)?;
+        deadline = deadline.map(|d| d + paused);
+      }
+      Ok(())
+    })();
+
+    if let Err(e) = res {
+      // The remaining repairmen are stopped. Their errors are irrelevant.
+      token.cancel();
+      handles.into_iter().for_each(|(_, h)| drop(h.join()));
+      return Err(e);
+    }
+    Ok(list)
+  })
+}
+
+/// This function spawns an async task for each [`Repairman`] in the world
+/// and runs them on the current thread, alongside a task that displays the
+/// world. Since the tasks are polled in the order of their arrival at the
+/// barrier, the result is always deterministic. See [`run_threads`] for
+/// `hire`.
+pub(crate) fn run_async<'a, C, H>(
+  world: &'a World<C>,
+  options: &Options,
+  hire: &H,
+) -> CdnResult<List>
+where
+  C: WorldConfig + Sync,
+  H: Fn(Id) -> Repairman<'a, C>,
+{
+  let deadline = options.time_limit.map(|t| Instant::now() + t);
+  let barrier = Barrier::new();
+  let token = barrier.cancellation_token();
+  let list = RefCell::new(List::default());
+  let alive = Cell::new(world.get_repairmen_ids().count());
+
+  let mut executor = Executor::default();
+  for id in world.get_repairmen_ids() {
+    let bar = barrier.clone();
+    let (list, alive, token) = (&list, &alive, &token);
+    executor.spawn(async move {
+      let mut man = hire(id);
+      let res = async {
+        while let Some(action) = man.next_action()? {
+          bar.wait_async().await?;
+          man.act(action)?;
+        }
+        Ok(())
+      }
+      .await;
+
+      drop(bar);
+      alive.set(alive.get() - 1);
+      if let Err(e) = res {
+        token.cancel();
+        return Err(e);
+      }
+      list.borrow_mut().insert(man.into_result());
+      Ok(())
+    });
+  }
+
+  // This task mirrors the main thread of `run_threads`
+  let (alive, token) = (&alive, &token);
+  executor.spawn(async move {
+    let mut deadline = deadline;
+    let mut screen = Screen::new(options)?;
+    loop {
+      screen.draw(world)?;
+      if alive.get() == 0 {
+        return Ok(());
+      }
+      if deadline.is_some_and(|d| Instant::now() >= d) {
+        token.cancel();
+        return Err(CdnErrorKind::TimedOut.into());
+      }
+      barrier.wait_async().await?;
+      let paused = screen.wait(world)?;
+      deadline = deadline.map(|d| d + paused);
+    }
+  });
+
+  let mut errors: Vec<CdnError> = executor
+    .run()?
+    .into_iter()
+    .filter_map(Result::err)
+    .collect();
+  // Cancellations are caused by the other errors, so they are reported last
+  errors.sort_by_key(|e| matches!(e.kind(), CdnErrorKind::Cancelled));
+  match errors.into_iter().next() {
+    Some(e) => Err(e),
+    None => Ok(list.into_inner()),
+  }
+}
+
+//
+//  Turnstile
+//
+
+mod turnstile {
+  use crate::{
+    barrier::Barrier,
+    error::CdnResult,
+    repairman::{Id, Repairman, Report},
+    world::WorldConfig,
+  };
+  use std::{
+    collections::BTreeSet,
+    ops::Bound::{Excluded, Unbounded},
+    sync::{Condvar, Mutex, PoisonError},
+  };
+
+  /// Lets the threads of a tick run one at a time, in the order of their
+  /// `Id`s. Combined with a [`Barrier`], every tick plays out the same way
+  /// no matter how the threads are scheduled.
+  pub struct Turnstile {
+    state: Mutex<State>,
+    cvar: Condvar,
+  }
+
+  struct State {
+    ids: BTreeSet<Id>,
+    turn: Option<Id>,
+  }
+
+  impl Turnstile {
+    pub fn new(ids: impl Iterator<Item = Id>) -> Self {
+      let ids: BTreeSet<_> = ids.collect();
+      Self {
+        state: Mutex::new(State {
+          turn: ids.first().copied(),
+          ids,
+        }),
+        cvar: Condvar::new(),
+      }
+    }
+
+    /// Same as [`Repairman::work`], but the repairman only acts and decides
+    /// in their turn.
+    pub fn work<C: WorldConfig + Sync>(
+      &self,
+      mut man: Repairman<'_, C>,
+      barrier: &Barrier,
+    ) -> CdnResult<Report> {
+      let id = man.id();
+      let res = (|| {
+        let mut action = None;
+        while let Some(next) = self.take_turn(id, || {
+          if let Some(action) = action {
+            man.act(action)?;
+          }
+          man.next_action()
+        })? {
+          action = Some(next);
+          barrier.wait()?;
+        }
+        Ok(())
+      })();
+
+      self.leave(id);
+      res.map(|_| man.into_result())
+    }
+
+    fn take_turn<T>(&self, id: Id, f: impl FnOnce() -> CdnResult<T>) -> CdnResult<T> {
+      let mut lock = self.state.lock()?;
+      while lock.turn != Some(id) {
+        lock = self.cvar.wait(lock)?;
+      }
+
+      let res = f();
+      lock.pass(id);
+      self.cvar.notify_all();
+      res
+    }
+
+    fn leave(&self, id: Id) {
+      let mut lock = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+      lock.ids.remove(&id);
+      if lock.turn == Some(id) {
+        lock.pass(id);
+      }
+      self.cvar.notify_all();
+    }
+  }
+
+  impl State {
+    // The turn goes to the next `Id`, or to the first one in the next tick.
+    fn pass(&mut self, id: Id) {
+      let mut next = self.ids.range((Excluded(id), Unbounded));
+      self.turn = next.next().or_else(|| self.ids.first()).copied();
+    }
+  }
+}
+
+//
+//  Executor
+//
+
+mod executor {
+  use crate::error::{CdnErrorKind, CdnResult};
+  use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    sync::{
+      atomic::{AtomicBool, Ordering},
+      Arc, Mutex, PoisonError,
+    },
+    task::{Context, Poll, Wake, Waker},
+  };
+
+  type Task<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+  /// A minimal single threaded executor. Woken tasks are polled in the order
+  /// they were woken up.
+  pub struct Executor<'a, T> {
+    tasks: Vec<Option<Task<'a, T>>>,
+    wakers: Vec<(Waker, Arc<TaskWaker>)>,
+    queue: Arc<Mutex<VecDeque<usize>>>,
+  }
+
+  struct TaskWaker {
+    id: usize,
+    queued: AtomicBool,
+    queue: Arc<Mutex<VecDeque<usize>>>,
+  }
+
+  impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+      self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+      if !self.queued.swap(true, Ordering::AcqRel) {
+        let mut queue = self.queue.lock().unwrap_or_else(PoisonError::into_inner);
+        queue.push_back(self.id);
+      }
+    }
+  }
+
+  impl<'a, T> Executor<'a, T> {
+    pub fn spawn(&mut self, future: impl Future<Output = T> + 'a) {
+      let task_waker = Arc::new(TaskWaker {
+        id: self.tasks.len(),
+        queued: AtomicBool::new(false),
+        queue: self.queue.clone(),
+      });
+      task_waker.wake_by_ref();
+      self.wakers.push((task_waker.clone().into(), task_waker));
+      self.tasks.push(Some(Box::pin(future)));
+    }
+
+    /// Runs all the tasks to completion and returns their outputs in the
+    /// order they were spawned.
+    pub fn run(mut self) -> CdnResult<Vec<T>> {
+      let mut outputs: Vec<Option<T>> = self.tasks.iter().map(|_| None).collect();
+      let mut pending = self.tasks.len();
+      while pending > 0 {
+        let Some(id) = self.queue.lock()?.pop_front() else {
+          return Err(CdnErrorKind::Deadlock.into());
+        };
+
+        let (waker, task_waker) = &self.wakers[id];
+        task_waker.queued.store(false, Ordering::Release);
+        let Some(task) = self.tasks[id].as_mut() else {
+          continue;
+        };
+        if let Poll::Ready(output) = task.as_mut().poll(&mut Context::from_waker(waker)) {
+          self.tasks[id] = None;
+          outputs[id] = Some(output);
+          pending -= 1;
+        }
+      }
+      Ok(outputs.into_iter().flatten().collect())
+    }
+  }
+
+  impl<T> Default for Executor<'_, T> {
+    fn default() -> Self {
+      Self {
+        tasks: Vec::new(),
+        wakers: Vec::new(),
+        queue: Default::default(),
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::Engine;
+  use crate::{
+    error::{CdnError, CdnErrorKind, CdnResult},
+    position::MoveDirection,
+    repairman::{Id, Repairman},
+    strategy::{Explore, Strategy, View},
+    world::{
+      test::{runs, Tst},
+      World, WorldConfig,
+    },
+    List, Options,
+  };
+  use clap::ValueEnum;
+  use rand_chacha::ChaCha8Rng;
+  use std::{thread, time::Duration};
+
+  fn run(options: &Options) -> (List, Vec<String>) {
+    let world = World::<Tst>::with_options(options).unwrap();
+    let list = world.run(options).unwrap();
+    let positions = world
+      .get_repairmen_ids()
+      .map(|id| format!("{:?}", unsafe { world.get_repairman_position(id) }))
+      .collect();
+    (list, positions)
+  }
+
+  #[test]
+  fn test_deterministic_engines() {
+    for options in runs(20) {
+      let options = Options {
+        engine: Engine::Threads,
+        deterministic: true,
+        ..options
+      };
+      let threads = run(&options);
+      let threads_again = run(&options);
+      let engine = Engine::Async;
+      let tasks = run(&Options { engine, ..options });
+      assert_eq!(threads, threads_again);
+      assert_eq!(threads, tasks);
+    }
+  }
+
+  #[test]
+  fn test_strategies() {
+    for &strategy in Strategy::value_variants() {
+      for seed in 0..10 {
+        let options = Options {
+          seed: Some(seed),
+          strategy,
+          ..Default::default()
+        };
+        let (list, _) = run(&options);
+        let total: usize = list
+          .notes
+          .iter()
+          .filter_map(|(id, notes)| notes.as_ref().get(id))
+          .sum();
+        assert_eq!(Tst::HOUSES_NEEDING_REPAIR, total, "{strategy:?}");
+      }
+    }
+  }
+
+  #[test]
+  fn test_announce() {
+    let (mut lags, mut announced_lags) = (0, 0);
+    for seed in 0..20 {
+      let options = Options {
+        seed: Some(seed),
+        engine: Engine::Async,
+        ..Default::default()
+      };
+      let (list, _) = run(&options);
+      let announce = true;
+      let (announced, _) = run(&Options {
+        announce,
+        ..options
+      });
+      assert_eq!(list.metrics.last_repair, announced.metrics.last_repair);
+      lags += list.metrics.exit_lags().map(|(_, l)| l).sum::<usize>();
+      announced_lags += announced.metrics.exit_lags().map(|(_, l)| l).sum::<usize>();
+    }
+    assert!(announced_lags < lags, "{announced_lags} < {lags}");
+  }
+
+  #[test]
+  fn test_hidden_total() {
+    for &strategy in Strategy::value_variants() {
+      for (seed, announce) in (0..10).zip([false, true].into_iter().cycle()) {
+        let options = Options {
+          seed: Some(seed),
+          strategy,
+          announce,
+          engine: Engine::Async,
+          ..Default::default()
+        };
+        let (known, _) = run(&options);
+        let hidden_total = true;
+        let (hidden, _) = run(&Options {
+          hidden_total,
+          ..options
+        });
+        assert_eq!(0, hidden.metrics.unrepaired, "{strategy:?}");
+        assert!(known.metrics.wasted_ticks() <= hidden.metrics.wasted_ticks());
+      }
+    }
+  }
+
+  #[test]
+  fn test_many_tasks() {
+    struct Crowded;
+    impl WorldConfig for Crowded {
+      const MAX_LEN_X: usize = 20;
+      const MAX_LEN_Y: usize = 20;
+      const REPAIRMEN: usize = 2000;
+      const HOUSES_NEEDING_REPAIR: usize = 50;
+    }
+
+    let options = Options {
+      engine: Engine::Async,
+      ..Default::default()
+    };
+    let list = World::<Crowded>::with_options(&options)
+      .unwrap()
+      .run(&options)
+      .unwrap();
+    assert_eq!(Crowded::REPAIRMEN, list.notes.len());
+  }
+
+  #[test]
+  fn test_time_limit() {
+    for engine in [Engine::Threads, Engine::Async] {
+      let options = Options {
+        engine,
+        seed: Some(7),
+        time_limit: Some(Duration::ZERO),
+        ..Default::default()
+      };
+      let e = World::<Tst>::with_options(&options)
+        .unwrap()
+        .run(&options)
+        .unwrap_err();
+      assert!(matches!(e.kind(), CdnErrorKind::TimedOut));
+      assert_eq!(Some(7), e.seed());
+      assert_eq!("run with seed 7: the run timed out", e.to_string());
+    }
+  }
+
+  #[test]
+  fn test_panic_message() {
+    let panics: [fn(); 2] = [
+      || panic!("a literal"),
+      || panic!("a {}", "formatted string"),
+    ];
+    for (f, message) in panics.into_iter().zip(["a literal", "a formatted string"]) {
+      let e = CdnError::from(thread::spawn(f).join().unwrap_err());
+      assert_eq!(Some(message), e.kind().panic_message());
+      assert_eq!(format!("panicked: {message}"), e.to_string());
+    }
+  }
+
+  #[test]
+  fn test_strategy_panic() {
+    struct Panicking;
+    impl<C: WorldConfig> Explore<C> for Panicking {
+      fn next_move(
+        &mut self,
+        _: &View<'_, C>,
+        _: &mut ChaCha8Rng,
+      ) -> CdnResult<Option<MoveDirection>> {
+        panic!("the strategy of repairman 0 is faulty")
+      }
+    }
+
+    let mut contexts = Vec::new();
+    for engine in Engine::value_variants().iter().copied() {
+      let options = Options {
+        engine,
+        seed: Some(3),
+        ..Default::default()
+      };
+      let world = World::<Tst>::with_options(&options).unwrap();
+      let hire = |id: Id| {
+        let explorer = match id == 0.into() {
+          true => Box::new(Panicking),
+          false => options.strategy.explorer(&world.spawns()[*id.as_ref()]),
+        };
+        // SAFETY: The engines hire every repairman once
+        unsafe { Repairman::with_explorer(id, &world, &options, explorer) }
+      };
+      let e = world.run_with(&options, &hire).unwrap_err();
+      let message = "the strategy of repairman 0 is faulty";
+      assert_eq!(Some(message), e.kind().panic_message());
+      let context = *e.repairman().unwrap();
+      assert_eq!(0, *context.id.as_ref());
+      // The repairman panics before their first move
+      let spawn = &world.spawns()[0];
+      assert_eq!((spawn.x(), spawn.y()), (context.x, context.y));
+      assert_eq!(
+        format!(
+          "run with seed 3: repairman 0 at ({}, {}) in tick {}: panicked: {message}",
+          context.x, context.y, context.tick
+        ),
+        e.to_string()
+      );
+      contexts.push(context);
+    }
+    assert_eq!(contexts[0], contexts[1]);
+  }
+}
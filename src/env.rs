@@ -0,0 +1,305 @@
+use crate::{
+  error::{CdnErrorKind, CdnResult, Mismatch},
+  grid::Grid,
+  houses::{HouseRef, HouseWrite},
+  position::Position,
+  repairman::{Action, Id, Knowledge, MapStatus},
+  strategy::{Frontier, View},
+  world::{HouseStatus, Notes, World, WorldConfig},
+  Options,
+};
+
+/// The reward for repairing a house.
+pub const REPAIR_REWARD: f64 = 1.0;
+/// The reward for exploring a house for the first time.
+pub const EXPLORE_REWARD: f64 = 0.1;
+/// The cost of every tick, until the repairman is done.
+pub const TICK_COST: f64 = 0.01;
+
+/// A step-based environment for training the policies of repairmen. Every
+/// [`Self::step`] is one tick of the world, in which the repairmen act one at
+/// a time in the order of their `Id`s. Repairmen follow the same rules as in
+/// [`World::run`]: they only learn about the world through the house they
+/// occupy.
+pub struct Env<C: WorldConfig> {
+  options: Options,
+  world: World<C>,
+  agents: Vec<Agent>,
+}
+
+struct Agent {
+  knowledge: Knowledge,
+  frontier: Frontier,
+  done: bool,
+}
+
+/// The observations, the rewards and whether every repairman is done.
+pub type Step<'a, C> = (Vec<Observation<'a, C>>, Vec<f64>, bool);
+
+/// What a repairman observes after a step.
+#[derive(Debug)]
+pub struct Observation<'a, C: WorldConfig> {
+  pub id: Id,
+  pub position: &'a Position<C>,
+  pub house_status: HouseStatus,
+  pub house_notes: Notes,
+  pub world_map: &'a Grid<MapStatus>,
+  /// The number of explored houses inside `world_map`.
+  pub explored: usize,
+  pub notebook: &'a Notes,
+  /// The repairman is done, for the same reasons as a
+  /// [`crate::repairman::Repairman`]: they know that every house is repaired,
+  /// or no unexplored house can be reached. Their actions are ignored from
+  /// now on.
+  pub done: bool,
+}
+
+impl<C: WorldConfig> Env<C> {
+  /// Creates an environment whose worlds are generated with `options`.
+  /// `options.seed` is overwritten by [`Self::reset`], which must be called
+  /// before the first step.
+  pub fn new(options: &Options) -> Self {
+    Self {
+      options: options.clone(),
+      world: World::default(),
+      agents: Vec::new(),
+    }
+  }
+
+  /// Generates a new world from `seed` and returns the first observations.
+  pub fn reset(&mut self, seed: u64) -> CdnResult<Vec<Observation<'_, C>>> {
+    self.options.seed = Some(seed);
+    self.world = World::with_options(&self.options)?;
+    let layout = self.world.layout();
+    self.agents = (0..C::REPAIRMEN)
+      .map(|_| Agent {
+        knowledge: Knowledge::new::<C>(layout),
+        frontier: Frontier::default(),
+        done: false,
+      })
+      .collect();
+
+    let ids: Vec<Id> = self.world.get_repairmen_ids().collect();
+    for id in ids {
+      self.observe(id)?;
+    }
+    self.observations()
+  }
+
+  /// Performs one action for each repairman, in the order of their `Id`s.
+  /// Moving outside of the world and repairing a repaired house are the same
+  /// as idling. Returns the new observations, the reward of each repairman
+  /// and whether every repairman is done.
+  ///
+  /// Fails with [`CdnErrorKind::ConfigMismatch`] if the number of actions is
+  /// not the number of repairmen.
+  pub fn step(&mut self, actions: &[Action]) -> CdnResult<Step<'_, C>> {
+    if actions.len() != C::REPAIRMEN {
+      let mismatch = Mismatch::Actions {
+        expected: C::REPAIRMEN,
+        actual: actions.len(),
+      };
+      return Err(CdnErrorKind::ConfigMismatch(mismatch).into());
+    }
+
+    let ids: Vec<Id> = self.world.get_repairmen_ids().collect();
+    let mut rewards = vec![0.0; ids.len()];
+    for (&id, &action) in ids.iter().zip(actions) {
+      if self.is_done(id) {
+        continue;
+      }
+
+      rewards[id] -= TICK_COST;
+      match action {
+        Action::Idle => (),
+        Action::Repair => {
+          let write = HouseWrite::Repair(Some(self.agents[id].knowledge.note(id)));
+          if self.world.write_house(id, self.house(id), write)? {
+            let knowledge = &mut self.agents[id].knowledge;
+            *knowledge.notebook.as_mut().entry(id).or_default() += 1;
+            rewards[id] += REPAIR_REWARD;
+          }
+        }
+        Action::Move(dir) => {
+          // SAFETY: the environment is the only one moving repairmen
+          let _ = unsafe { self.world.move_repairman_as(id, id, dir) };
+        }
+      }
+      if self.observe(id)? {
+        rewards[id] += EXPLORE_REWARD;
+      }
+    }
+
+    let done = ids.iter().all(|&id| self.is_done(id));
+    Ok((self.observations()?, rewards, done))
+  }
+
+  pub fn world(&self) -> &World<C> {
+    &self.world
+  }
+
+  // Does what a repairman does when standing in a house, see
+  // `Repairman::decide`. Returns whether the house was explored for the
+  // first time.
+  fn observe(&mut self, id: Id) -> CdnResult<bool> {
+    // SAFETY: the environment is the only one moving repairmen
+    let (house, position) = unsafe {
+      (
+        self.world.get_repairman_house(id),
+        self.world.get_repairman_position(id),
+      )
+    };
+    if house.status()? == HouseStatus::NeedsRepair {
+      return Ok(false);
+    }
+
+    let (world, options) = (&self.world, &self.options);
+    let agent = &mut self.agents[id];
+    let knowledge = &mut agent.knowledge;
+    if let Some(&num) = knowledge.notebook.as_ref().get(&id) {
+      world.write_house(id, house, HouseWrite::Note(id, num))?;
+    }
+    house.read_notes(&mut knowledge.notebook)?;
+    let mut is_new = false;
+    agent.done = knowledge.is_job_complete::<C>(options.hidden_total) || {
+      is_new = knowledge.explore(position, world.obstacles());
+      agent.frontier.is_explored(&knowledge.view(id, position))
+    };
+    if agent.done && options.announce {
+      knowledge.notebook.announce_job_complete();
+      world.write_house(id, house, HouseWrite::AnnounceJobComplete)?;
+    }
+    Ok(is_new)
+  }
+
+  fn observations(&self) -> CdnResult<Vec<Observation<'_, C>>> {
+    self
+      .world
+      .get_repairmen_ids()
+      .map(|id| {
+        let house = self.house(id);
+        let agent = &self.agents[id];
+        Ok(Observation {
+          id,
+          // SAFETY: the environment is the only one moving repairmen
+          position: unsafe { self.world.get_repairman_position(id) },
+          house_status: house.status()?,
+          house_notes: house.notes()?,
+          world_map: &agent.knowledge.world_map,
+          explored: agent.knowledge.explored,
+          notebook: &agent.knowledge.notebook,
+          done: self.is_done(id),
+        })
+      })
+      .collect()
+  }
+
+  fn house(&self, id: Id) -> HouseRef<'_> {
+    // SAFETY: the environment is the only one moving repairmen
+    unsafe { self.world.get_repairman_house(id) }
+  }
+
+  fn is_done(&self, id: Id) -> bool {
+    self.agents[id].done
+  }
+}
+
+impl<C: WorldConfig> Observation<'_, C> {
+  /// The observation as seen by a [`crate::strategy::Explore`]r, so that the
+  /// built-in strategies can be used as policies.
+  pub fn view(&self) -> View<'_, C> {
+    View {
+      id: self.id,
+      position: self.position,
+      world_map: self.world_map,
+      explored: self.explored,
+      notebook: self.notebook,
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::Env;
+  use crate::{
+    repairman::Action,
+    strategy::Strategy,
+    world::{test::Tst, HouseStatus, WorldConfig},
+    Options,
+  };
+  use rand::SeedableRng;
+  use rand_chacha::ChaCha8Rng;
+
+  // Plays an episode with the nearest strategy and returns the sum of the
+  // rewards of each repairman.
+  fn play(env: &mut Env<Tst>, seed: u64) -> Vec<f64> {
+    let rng = &mut ChaCha8Rng::seed_from_u64(seed);
+    let mut explorers: Vec<_> = env
+      .reset(seed)
+      .unwrap()
+      .iter()
+      .map(|o| Strategy::Nearest.explorer(o.position))
+      .collect();
+    let mut actions = vec![Action::Idle; Tst::REPAIRMEN];
+    let mut returns = vec![0.0; Tst::REPAIRMEN];
+    loop {
+      let (observations, rewards, done) = env.step(&actions).unwrap();
+      returns.iter_mut().zip(rewards).for_each(|(r, n)| *r += n);
+      if done {
+        return returns;
+      }
+      for (action, o) in actions.iter_mut().zip(&observations) {
+        *action = match o.house_status {
+          HouseStatus::NeedsRepair => Action::Repair,
+          HouseStatus::Repaired => match explorers[o.id].next_move(&o.view(), rng).unwrap() {
+            Some(dir) => Action::Move(dir),
+            None => Action::Idle,
+          },
+        };
+      }
+    }
+  }
+
+  #[test]
+  fn test_episode() {
+    let mut env = Env::<Tst>::new(&Options::default());
+    for seed in 0..10 {
+      let returns = play(&mut env, seed);
+      assert_eq!(returns, play(&mut env, seed));
+      assert!(returns.iter().sum::<f64>() > 0.0);
+      // No house needs repair anymore
+      assert!(env.world().to_string().chars().all(|c| c != '\u{1b}'));
+    }
+
+    let e = env.step(&[Action::Idle]).err().unwrap();
+    assert_eq!(
+      "config mismatch: expected 3 actions, one per repairman, found 1",
+      e.to_string()
+    );
+  }
+
+  #[test]
+  fn test_hidden_total() {
+    for announce in [false, true] {
+      let options = Options {
+        hidden_total: true,
+        announce,
+        ..Default::default()
+      };
+      let mut env = Env::<Tst>::new(&options);
+      for seed in 0..10 {
+        play(&mut env, seed);
+        assert!(env.world().to_string().chars().all(|c| c != '\u{1b}'));
+        // Without the total, the repairmen are done once the world is
+        // explored, unless someone announced that the job is complete
+        let observations = env.observations().unwrap();
+        let announced = observations.iter().filter(|o| o.notebook.is_job_complete());
+        assert_eq!(announce, announced.count() > 0);
+        for o in &observations {
+          let explored = o.explored == Tst::MAX_LEN_X * Tst::MAX_LEN_Y;
+          assert!(o.done && (explored || o.notebook.is_job_complete()));
+        }
+      }
+    }
+  }
+}
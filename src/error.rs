@@ -1,3 +1,4 @@
+use crate::{audit::Violation, position::MoveDirection, repairman::Id, verify::Discrepancy};
 use std::{
   any::Any,
   error::Error,
@@ -11,29 +12,263 @@ pub type CdnResult<T> = Result<T, CdnError>;
 // Boxing [CdnErrorKind] reduces the size of [CdnResult] and enhances the
 // overall performance of the program.
 #[derive(Debug)]
-pub struct CdnError(Box<CdnErrorKind>);
+pub struct CdnError(Box<Inner>);
+
+#[derive(Debug)]
+struct Inner {
+  kind: CdnErrorKind,
+  repairman: Option<RepairmanContext>,
+  seed: Option<u64>,
+}
+
+/// Where and when a repairman was when their error happened.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RepairmanContext {
+  pub id: Id,
+  /// The tick the repairman was deciding or acting in.
+  pub tick: usize,
+  pub x: usize,
+  pub y: usize,
+}
+
+/// What doesn't fit the world in a [`CdnErrorKind::ConfigMismatch`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mismatch {
+  /// The world is `expected` houses big, as `(len_x, len_y)`.
+  Size {
+    expected: (usize, usize),
+    actual: (usize, usize),
+  },
+  Repairmen {
+    expected: usize,
+    actual: usize,
+  },
+  BrokenHouses {
+    expected: usize,
+    actual: usize,
+  },
+  /// A note written before the run is about the repairman `id` of the world.
+  Note {
+    id: Id,
+  },
+  /// A step needs one action per repairman.
+  Actions {
+    expected: usize,
+    actual: usize,
+  },
+  /// The repairman `id` is not one of the `repairmen` of the world.
+  Id {
+    id: Id,
+    repairmen: usize,
+  },
+}
 
 // This type is copied from the error part of `std::thread::Result`
 type ThreadError = Box<dyn Any + Send + 'static>;
 
 #[derive(Debug)]
 pub enum CdnErrorKind {
-  InvalidMoveDirection,
+  /// Moving from `(x, y)` leaves the world or enters an obstacle.
+  InvalidMoveDirection {
+    x: usize,
+    y: usize,
+    direction: MoveDirection,
+  },
+  /// `(x, y)` is outside of a world of `len_x` by `len_y` houses.
+  OutOfBounds {
+    x: usize,
+    y: usize,
+    len_x: usize,
+    len_y: usize,
+  },
+  /// There is no single move from `from` to `to`.
+  NotAdjacent {
+    from: (usize, usize),
+    to: (usize, usize),
+  },
+  /// A world of `len_x` by `len_y` houses can't have `houses` houses needing
+  /// repair.
+  TooManyBrokenHouses {
+    houses: usize,
+    len_x: usize,
+    len_y: usize,
+  },
   PoisonError,
+  Cancelled,
+  TimedOut,
+  Deadlock,
+  /// The line of an [`crate::events::EventLog`] that couldn't be parsed.
+  InvalidEventLog(usize),
+  /// The line of a [`crate::scenario::Scenario`] that couldn't be parsed.
+  InvalidScenario(usize),
+  /// The line of a [`crate::snapshot::Snapshot`] that couldn't be parsed.
+  InvalidSnapshot(usize),
+  /// A scenario, a snapshot, an event log or an input doesn't fit the
+  /// `WorldConfig` of the world.
+  ConfigMismatch(Mismatch),
+  /// A repairman broke a rule of the challenge, see [`crate::audit`].
+  RuleViolation(Violation),
+  /// A finished run doesn't match the world, see [`crate::verify`].
+  Verification(Vec<Discrepancy>),
+  /// The extension of the path is not one of [`crate::export::Format`].
+  UnknownExportFormat,
   IoError(IoError),
   ThreadError(ThreadError),
+  EncodingError(Box<dyn Error + Send + Sync>),
+}
+
+impl CdnError {
+  pub fn kind(&self) -> &CdnErrorKind {
+    &self.0.kind
+  }
+
+  /// The repairman whose error this is, if it happened inside a repairman.
+  pub fn repairman(&self) -> Option<&RepairmanContext> {
+    self.0.repairman.as_ref()
+  }
+
+  /// The seed of the world the error happened in, if it happened in a run.
+  pub fn seed(&self) -> Option<u64> {
+    self.0.seed
+  }
+
+  /// Attaches the repairman the error happened in. The first one attached is
+  /// kept, since errors are passed on from where they happened.
+  pub fn with_repairman(mut self, context: RepairmanContext) -> Self {
+    self.0.repairman.get_or_insert(context);
+    self
+  }
+
+  /// Attaches the seed of the world the error happened in. The first one
+  /// attached is kept.
+  pub fn with_seed(mut self, seed: u64) -> Self {
+    self.0.seed.get_or_insert(seed);
+    self
+  }
 }
 
+impl CdnErrorKind {
+  /// The message a repairman thread panicked with, if the payload is a
+  /// string, which is the case for `panic!` and friends.
+  pub fn panic_message(&self) -> Option<&str> {
+    let Self::ThreadError(payload) = self else {
+      return None;
+    };
+    match payload.downcast_ref::<&str>() {
+      Some(s) => Some(s),
+      None => payload.downcast_ref::<String>().map(String::as_str),
+    }
+  }
+}
+
+/// The messages of the inner errors are part of [`Display`], so there is no
+/// source to report again.
 impl Error for CdnError {}
+
+/// The seed and the repairman come first, followed by the message of the
+/// kind: `run with seed 7: repairman 2 at (3, 1) in tick 15: the run timed
+/// out`.
 impl Display for CdnError {
   fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-    write!(f, "{:?}", self.0)
+    if let Some(seed) = self.seed() {
+      write!(f, "run with seed {seed}: ")?;
+    }
+    if let Some(&RepairmanContext { id, tick, x, y }) = self.repairman() {
+      write!(
+        f,
+        "repairman {} at ({x}, {y}) in tick {tick}: ",
+        id.as_ref()
+      )?;
+    }
+    Display::fmt(self.kind(), f)
+  }
+}
+
+impl Display for CdnErrorKind {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    use CdnErrorKind::*;
+    match self {
+      InvalidMoveDirection { x, y, direction } => {
+        write!(f, "can't move {direction:?} from ({x}, {y})")
+      }
+      OutOfBounds { x, y, len_x, len_y } => {
+        write!(f, "({x}, {y}) is outside of the {len_x}x{len_y} world")
+      }
+      NotAdjacent { from, to } => write!(f, "{from:?} and {to:?} are not adjacent"),
+      TooManyBrokenHouses {
+        houses,
+        len_x,
+        len_y,
+      } => write!(
+        f,
+        "{houses} houses needing repair don't fit in the {len_x}x{len_y} world"
+      ),
+      PoisonError => write!(f, "a lock was poisoned by a panicking repairman"),
+      Cancelled => write!(f, "the run was cancelled"),
+      TimedOut => write!(f, "the run timed out"),
+      Deadlock => write!(f, "every repairman is waiting for another"),
+      InvalidEventLog(line) => write!(f, "invalid event log at line {line}"),
+      InvalidScenario(line) => write!(f, "invalid scenario at line {line}"),
+      InvalidSnapshot(line) => write!(f, "invalid snapshot at line {line}"),
+      ConfigMismatch(mismatch) => write!(f, "config mismatch: {mismatch}"),
+      RuleViolation(violation) => write!(f, "rule violation: {violation}"),
+      Verification(discrepancies) => {
+        write!(f, "the run doesn't match the world:")?;
+        discrepancies.iter().try_for_each(|d| write!(f, "\n  {d}"))
+      }
+      UnknownExportFormat => write!(f, "unknown export format, expected gif, png or svg"),
+      IoError(e) => write!(f, "io error: {e}"),
+      ThreadError(_) => match self.panic_message() {
+        Some(message) => write!(f, "panicked: {message}"),
+        None => write!(f, "panicked"),
+      },
+      EncodingError(e) => write!(f, "encoding error: {e}"),
+    }
+  }
+}
+
+impl Display for Mismatch {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    match *self {
+      Self::Size { expected, actual } => write!(
+        f,
+        "expected a {}x{} world, found {}x{}",
+        expected.0, expected.1, actual.0, actual.1
+      ),
+      Self::Repairmen { expected, actual } => {
+        write!(f, "expected {expected} repairmen, found {actual}")
+      }
+      Self::BrokenHouses { expected, actual } => {
+        write!(
+          f,
+          "expected {expected} houses needing repair, found {actual}"
+        )
+      }
+      Self::Note { id } => {
+        write!(f, "a note is about repairman {} of the world", id.as_ref())
+      }
+      Self::Actions { expected, actual } => {
+        write!(
+          f,
+          "expected {expected} actions, one per repairman, found {actual}"
+        )
+      }
+      Self::Id { id, repairmen } => write!(
+        f,
+        "repairman {} is not one of the {repairmen} of the world",
+        id.as_ref()
+      ),
+    }
   }
 }
 
 impl From<CdnErrorKind> for CdnError {
   fn from(value: CdnErrorKind) -> Self {
-    CdnError(Box::new(value))
+    CdnError(Box::new(Inner {
+      kind: value,
+      repairman: None,
+      seed: None,
+    }))
   }
 }
 
@@ -49,6 +284,18 @@ impl From<IoError> for CdnError {
   }
 }
 
+impl From<gif::EncodingError> for CdnError {
+  fn from(e: gif::EncodingError) -> Self {
+    CdnErrorKind::EncodingError(e.into()).into()
+  }
+}
+
+impl From<png::EncodingError> for CdnError {
+  fn from(e: png::EncodingError) -> Self {
+    CdnErrorKind::EncodingError(e.into()).into()
+  }
+}
+
 impl From<ThreadError> for CdnError {
   fn from(e: ThreadError) -> Self {
     CdnErrorKind::ThreadError(e).into()
@@ -0,0 +1,299 @@
+//! A log of what every repairman did in every tick, which is recorded if
+//! `Options::record` is set. Alongside the initial state of the world, it is
+//! enough to replay a run without running it again, for example to export it
+//! with [`crate::export`].
+
+use crate::{
+  error::{CdnError, CdnErrorKind, CdnResult},
+  position::Position,
+  repairman::Id,
+  world::{World, WorldConfig},
+};
+use std::{
+  collections::HashSet,
+  fmt::{Display, Formatter, Result as FmtResult},
+  str::FromStr,
+};
+
+/// Something a repairman did in a tick, at the position `(x, y)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+  /// Starts from one, same as the ticks of [`crate::Metrics`].
+  pub tick: usize,
+  pub id: Id,
+  pub kind: EventKind,
+  pub x: usize,
+  pub y: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+  /// Moved into `(x, y)`.
+  Move,
+  /// Repaired the house at `(x, y)`.
+  Repair,
+  /// Waited at `(x, y)`, or tried to repair a house which was already
+  /// repaired.
+  Idle,
+  /// Couldn't read the house at `(x, y)`, because another repairman was
+  /// using it. The repairman idles in the same tick.
+  Contend,
+}
+
+/// The initial state of a world and the events of a run, sorted by tick and
+/// `Id`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EventLog {
+  pub len_x: usize,
+  pub len_y: usize,
+  /// The initial position of each repairman, in the order of their `Id`s.
+  pub spawns: Vec<(usize, usize)>,
+  pub broken_houses: Vec<(usize, usize)>,
+  /// Sorted by row, then by column.
+  pub obstacles: Vec<(usize, usize)>,
+  pub events: Vec<Event>,
+}
+
+/// The state of a replayed world after a tick. See [`EventLog::replay`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Frame {
+  pub tick: usize,
+  /// The position of each repairman, in the order of their `Id`s.
+  pub positions: Vec<(usize, usize)>,
+  pub needs_repair: HashSet<(usize, usize)>,
+}
+
+impl EventLog {
+  pub fn new<C: WorldConfig>(world: &World<C>, mut events: Vec<Event>) -> Self {
+    events.sort_by_key(|e| (e.tick, e.id));
+    let xy = |pos: &Position<C>| (pos.x(), pos.y());
+    let mut obstacles: Vec<_> = world.obstacles().iter().map(xy).collect();
+    obstacles.sort_by_key(|&(x, y)| (y, x));
+    Self {
+      len_x: C::MAX_LEN_X,
+      len_y: C::MAX_LEN_Y,
+      spawns: world.spawns().iter().map(xy).collect(),
+      broken_houses: world.broken_houses().iter().map(xy).collect(),
+      obstacles,
+      events,
+    }
+  }
+
+  /// The tick of the last event.
+  pub fn ticks(&self) -> usize {
+    self.events.last().map_or(0, |e| e.tick)
+  }
+
+  /// Calls `f` with the initial state of the world, then with its state
+  /// after every tick.
+  pub fn replay(&self, mut f: impl FnMut(&Frame) -> CdnResult<()>) -> CdnResult<()> {
+    let mut frame = Frame {
+      tick: 0,
+      positions: self.spawns.clone(),
+      needs_repair: self.broken_houses.iter().copied().collect(),
+    };
+    f(&frame)?;
+
+    let mut events = self.events.iter().peekable();
+    for tick in 1..=self.ticks() {
+      frame.tick = tick;
+      while let Some(e) = events.next_if(|e| e.tick == tick) {
+        match e.kind {
+          EventKind::Move => frame.positions[e.id] = (e.x, e.y),
+          EventKind::Repair => {
+            frame.needs_repair.remove(&(e.x, e.y));
+          }
+          EventKind::Idle | EventKind::Contend => (),
+        }
+      }
+      f(&frame)?;
+    }
+    Ok(())
+  }
+}
+
+//
+// text format
+//
+
+impl EventKind {
+  fn as_str(&self) -> &'static str {
+    match self {
+      Self::Move => "move",
+      Self::Repair => "repair",
+      Self::Idle => "idle",
+      Self::Contend => "contend",
+    }
+  }
+}
+
+/// One line per item:
+/// ```text
+/// size 7 7
+/// spawn 3 4
+/// broken 1 2
+/// obstacle 0 6
+/// 1 0 move 3 5
+/// ```
+/// Events are written as `tick id kind x y`.
+impl Display for EventLog {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    writeln!(f, "size {} {}", self.len_x, self.len_y)?;
+    for (x, y) in &self.spawns {
+      writeln!(f, "spawn {x} {y}")?;
+    }
+    for (x, y) in &self.broken_houses {
+      writeln!(f, "broken {x} {y}")?;
+    }
+    for (x, y) in &self.obstacles {
+      writeln!(f, "obstacle {x} {y}")?;
+    }
+    for e in &self.events {
+      let (tick, id, kind) = (e.tick, e.id.as_ref(), e.kind.as_str());
+      writeln!(f, "{tick} {id} {kind} {} {}", e.x, e.y)?;
+    }
+    Ok(())
+  }
+}
+
+impl FromStr for EventLog {
+  type Err = CdnError;
+
+  /// Parses the format written by [`Display`]. Events must be sorted by tick
+  /// and every position must be inside the world.
+  fn from_str(s: &str) -> CdnResult<Self> {
+    let mut log = Self::default();
+    for (i, line) in s.lines().enumerate() {
+      let invalid = || CdnError::from(CdnErrorKind::InvalidEventLog(i + 1));
+      let words: Vec<_> = line.split_whitespace().collect();
+      let num = |j: usize| -> CdnResult<usize> {
+        let word = words.get(j).ok_or_else(invalid)?;
+        word.parse().map_err(|_| invalid())
+      };
+      let xy = |j: usize| -> CdnResult<(usize, usize)> {
+        let (x, y) = (num(j)?, num(j + 1)?);
+        match x < log.len_x && y < log.len_y {
+          true => Ok((x, y)),
+          false => Err(invalid()),
+        }
+      };
+
+      match words.as_slice() {
+        [] => (),
+        ["size", _, _] if i == 0 => (log.len_x, log.len_y) = (num(1)?, num(2)?),
+        ["spawn", _, _] => log.spawns.push(xy(1)?),
+        ["broken", _, _] => log.broken_houses.push(xy(1)?),
+        ["obstacle", _, _] => log.obstacles.push(xy(1)?),
+        [_, _, kind, _, _] => {
+          let kind = match *kind {
+            "move" => EventKind::Move,
+            "repair" => EventKind::Repair,
+            "idle" => EventKind::Idle,
+            "contend" => EventKind::Contend,
+            _ => return Err(invalid()),
+          };
+          let (tick, id, (x, y)) = (num(0)?, num(1)?, xy(3)?);
+          if id >= log.spawns.len() || tick < log.ticks() {
+            return Err(invalid());
+          }
+          let id = id.into();
+          log.events.push(Event {
+            tick,
+            id,
+            kind,
+            x,
+            y,
+          });
+        }
+        _ => return Err(invalid()),
+      }
+    }
+    Ok(log)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{EventKind, EventLog};
+  use crate::{
+    engine::Engine,
+    error::CdnErrorKind,
+    scenario::Scenario,
+    world::{
+      test::{Corridor, Tst, CORRIDOR},
+      World,
+    },
+    Options,
+  };
+
+  #[test]
+  fn test_replay() {
+    let options = Options {
+      seed: Some(3),
+      engine: Engine::Async,
+      record: true,
+      ..Default::default()
+    };
+    let world = World::<Tst>::with_options(&options).unwrap();
+    let list = world.run(&options).unwrap();
+    let log = &list.log;
+    let moves = log.events.iter().filter(|e| e.kind == EventKind::Move);
+    assert_eq!(list.metrics.moves, moves.count());
+    assert_eq!(list.metrics.ticks, log.ticks());
+
+    let mut frames = 0;
+    let mut last = None;
+    log
+      .replay(|frame| {
+        frames += 1;
+        last = Some(frame.clone());
+        Ok(())
+      })
+      .unwrap();
+    let last = last.unwrap();
+    assert_eq!(log.ticks() + 1, frames);
+    assert!(last.needs_repair.is_empty());
+    for (id, &(x, y)) in last.positions.iter().enumerate() {
+      let pos = unsafe { world.get_repairman_position(id.into()) };
+      assert_eq!((pos.x(), pos.y()), (x, y));
+    }
+
+    let parsed: EventLog = log.to_string().parse().unwrap();
+    assert_eq!(log, &parsed);
+  }
+
+  #[test]
+  fn test_scenario_log() {
+    let scenario: Scenario = CORRIDOR.parse().unwrap();
+    let options = Options {
+      seed: Some(0),
+      engine: Engine::Async,
+      record: true,
+      ..Default::default()
+    };
+    let world = World::<Corridor>::with_scenario(&scenario, &options).unwrap();
+    let log = world.run(&options).unwrap().log;
+    let mut obstacles = scenario.obstacles.clone();
+    obstacles.sort_by_key(|&(x, y)| (y, x));
+    assert_eq!(obstacles, log.obstacles);
+    assert!(log.to_string().contains("\nobstacle "));
+    assert_eq!(log, log.to_string().parse().unwrap());
+  }
+
+  #[test]
+  fn test_invalid_log() {
+    for (s, line) in [
+      ("size 7 7\nspawn 7 0", 2),
+      ("size 7 7\nspawn 0 0\n1 1 move 0 1", 3),
+      ("size 7 7\nspawn 0 0\n2 0 move 0 1\n1 0 idle 0 1", 4),
+      ("size 7 7\n\nfly 0 0", 3),
+      ("size 7 7\nobstacle 0 7", 2),
+    ] {
+      let e = s.parse::<EventLog>().unwrap_err();
+      assert!(
+        matches!(e.kind(), CdnErrorKind::InvalidEventLog(l) if *l == line),
+        "{s}"
+      );
+    }
+  }
+}
@@ -0,0 +1,312 @@
+//! Renders every tick of an [`EventLog`] into an image, entirely offline. A
+//! house is a square coloured by its status, or as an obstacle, same as the
+//! plain frontend, and a repairman is a smaller square in their own colour,
+//! same as the dashboard.
+
+use crate::{
+  error::{CdnErrorKind, CdnResult},
+  events::{EventLog, Frame},
+  repairman::Id,
+  screen::{colour, Rgb, FOREGROUND, NEEDS_REPAIR, OBSTACLE, REPAIRED},
+};
+use std::{
+  borrow::Cow,
+  fmt::Write as FmtWrite,
+  fs::{self, File},
+  io::BufWriter,
+  path::Path,
+  time::Duration,
+};
+
+/// The side of a house in pixels, unless the world doesn't fit.
+const CELL: usize = 16;
+/// The longest side of an image in pixels. Bigger worlds are cropped to
+/// their top left part, same as the dashboard.
+const MAX_SIDE: usize = 1024;
+const GRID: Rgb = (30, 30, 60);
+// The palette starts with the colours of the houses, followed by the colours
+// of the repairmen. Indexed images can have up to 256 colours.
+const HOUSE_COLOURS: [Rgb; 5] = [REPAIRED, NEEDS_REPAIR, GRID, FOREGROUND, OBSTACLE];
+const REPAIRMAN_COLOURS: usize = 256 - HOUSE_COLOURS.len();
+
+/// The image format of [`export`], chosen by the extension of the path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+  /// An animated GIF.
+  Gif,
+  /// An animated PNG.
+  Png,
+  /// One SVG file per tick, named after the path with the tick appended.
+  Svg,
+}
+
+impl Format {
+  pub fn from_path(path: &Path) -> CdnResult<Self> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+      Some("gif") => Ok(Self::Gif),
+      Some("png") => Ok(Self::Png),
+      Some("svg") => Ok(Self::Svg),
+      _ => Err(CdnErrorKind::UnknownExportFormat.into()),
+    }
+  }
+}
+
+/// Renders every tick of `log` to `path`, showing each one of them for
+/// `frame_duration`. See [`Format`].
+pub fn export(log: &EventLog, path: &Path, frame_duration: Duration) -> CdnResult<()> {
+  match Format::from_path(path)? {
+    Format::Gif => export_gif(log, path, frame_duration),
+    Format::Png => export_png(log, path, frame_duration),
+    Format::Svg => export_svg(log, path),
+  }
+}
+
+fn export_gif(log: &EventLog, path: &Path, frame_duration: Duration) -> CdnResult<()> {
+  let canvas = Canvas::new(log);
+  let (width, height) = (canvas.width as u16, canvas.height as u16);
+  let file = BufWriter::new(File::create(path)?);
+  let mut encoder = gif::Encoder::new(file, width, height, &canvas.palette())?;
+  encoder.set_repeat(gif::Repeat::Infinite)?;
+  // The delay of GIF frames is in hundredths of a second
+  let delay = (frame_duration.as_millis() / 10).min(u16::MAX as u128) as u16;
+  log.replay(|frame| {
+    let frame = gif::Frame {
+      width,
+      height,
+      delay,
+      buffer: Cow::Owned(canvas.render(frame)),
+      ..Default::default()
+    };
+    Ok(encoder.write_frame(&frame)?)
+  })
+}
+
+fn export_png(log: &EventLog, path: &Path, frame_duration: Duration) -> CdnResult<()> {
+  let canvas = Canvas::new(log);
+  let file = BufWriter::new(File::create(path)?);
+  let mut encoder = png::Encoder::new(file, canvas.width as u32, canvas.height as u32);
+  encoder.set_color(png::ColorType::Indexed);
+  encoder.set_depth(png::BitDepth::Eight);
+  encoder.set_palette(canvas.palette());
+  encoder.set_animated(log.ticks() as u32 + 1, 0)?;
+  let delay = frame_duration.as_millis().min(u16::MAX as u128) as u16;
+  encoder.set_frame_delay(delay, 1000)?;
+
+  let mut writer = encoder.write_header()?;
+  log.replay(|frame| Ok(writer.write_image_data(&canvas.render(frame))?))?;
+  Ok(writer.finish()?)
+}
+
+fn export_svg(log: &EventLog, path: &Path) -> CdnResult<()> {
+  let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+  let digits = log.ticks().to_string().len();
+  log.replay(|frame| {
+    let name = format!("{stem}-{:0digits$}.svg", frame.tick);
+    Ok(fs::write(path.with_file_name(name), svg(log, frame))?)
+  })
+}
+
+/// Renders a frame as an SVG image, in which every house is one unit wide.
+pub fn svg(log: &EventLog, frame: &Frame) -> String {
+  let (len_x, len_y) = (log.len_x, log.len_y);
+  let (width, height) = (len_x * CELL, len_y * CELL);
+  let mut s = format!(
+    "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+     viewBox=\"0 0 {len_x} {len_y}\" shape-rendering=\"crispEdges\">\n"
+  );
+  let fill = hex(REPAIRED);
+  let _ = writeln!(
+    s,
+    "<rect width=\"{len_x}\" height=\"{len_y}\" fill=\"{fill}\"/>"
+  );
+  let mut houses: Vec<_> = frame.needs_repair.iter().collect();
+  houses.sort();
+  let obstacles = log.obstacles.iter().map(|xy| (xy, OBSTACLE));
+  for ((x, y), colour) in obstacles.chain(houses.into_iter().map(|xy| (xy, NEEDS_REPAIR))) {
+    let fill = hex(colour);
+    let _ = writeln!(
+      s,
+      "<rect x=\"{x}\" y=\"{y}\" width=\"1\" height=\"1\" fill=\"{fill}\"/>"
+    );
+  }
+  for (id, (x, y)) in frame.positions.iter().enumerate() {
+    let id = Id::from(id);
+    let fill = hex(colour(id));
+    let _ = writeln!(
+      s,
+      "<rect x=\"{x}.25\" y=\"{y}.25\" width=\"0.5\" height=\"0.5\" fill=\"{fill}\">\
+       <title>{id:?}</title></rect>"
+    );
+  }
+  s.push_str("</svg>\n");
+  s
+}
+
+fn hex((r, g, b): Rgb) -> String {
+  format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+// Draws frames as indexed pixels into the palette.
+struct Canvas {
+  cell: usize,
+  len_x: usize,
+  len_y: usize,
+  width: usize,
+  height: usize,
+  repairmen: usize,
+  obstacles: Vec<(usize, usize)>,
+}
+
+impl Canvas {
+  fn new(log: &EventLog) -> Self {
+    let longest = log.len_x.max(log.len_y).max(1);
+    let cell = (MAX_SIDE / longest).clamp(1, CELL);
+    let (len_x, len_y) = (log.len_x.min(MAX_SIDE), log.len_y.min(MAX_SIDE));
+    Self {
+      cell,
+      len_x,
+      len_y,
+      width: len_x * cell,
+      height: len_y * cell,
+      repairmen: log.spawns.len().min(REPAIRMAN_COLOURS),
+      obstacles: log.obstacles.clone(),
+    }
+  }
+
+  // The colours as RGB triplets.
+  fn palette(&self) -> Vec<u8> {
+    let repairmen = (0..self.repairmen).map(|id| colour(id.into()));
+    let colours = HOUSE_COLOURS.into_iter().chain(repairmen);
+    colours.flat_map(|(r, g, b)| [r, g, b]).collect()
+  }
+
+  fn render(&self, frame: &Frame) -> Vec<u8> {
+    let mut pixels = vec![0; self.width * self.height];
+    let mut fill = |x: usize, y: usize, inset: usize, index: u8| {
+      let (left, top) = (x * self.cell + inset, y * self.cell + inset);
+      let side = self.cell - 2 * inset;
+      for row in pixels.chunks_exact_mut(self.width).skip(top).take(side) {
+        row[left..left + side].fill(index);
+      }
+    };
+
+    let obstacles = self.obstacles.iter().map(|&xy| (xy, 4));
+    for ((x, y), index) in obstacles.chain(frame.needs_repair.iter().map(|&xy| (xy, 1))) {
+      if x < self.len_x && y < self.len_y {
+        fill(x, y, 0, index);
+      }
+    }
+    // Lower `Id`s are drawn on top
+    let inset = self.cell / 4;
+    for (id, &(x, y)) in frame.positions.iter().enumerate().rev() {
+      if x < self.len_x && y < self.len_y {
+        let index = HOUSE_COLOURS.len() + id % REPAIRMAN_COLOURS;
+        fill(x, y, inset, index as u8);
+      }
+    }
+
+    // The grid is only drawn if the houses are big enough to be told apart
+    if self.cell >= 4 {
+      for (y, row) in pixels.chunks_exact_mut(self.width).enumerate() {
+        match y % self.cell == self.cell - 1 {
+          true => row.fill(2),
+          false => row
+            .iter_mut()
+            .skip(self.cell - 1)
+            .step_by(self.cell)
+            .for_each(|p| *p = 2),
+        }
+      }
+    }
+    pixels
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{export, hex, svg, Canvas, Format, HOUSE_COLOURS};
+  use crate::{
+    engine::Engine,
+    error::CdnErrorKind,
+    events::{EventLog, Frame},
+    screen::OBSTACLE,
+    world::{test::Tst, World},
+    Options,
+  };
+  use std::{env, fs, fs::File, path::Path, process, time::Duration};
+
+  #[test]
+  fn test_export() {
+    let options = Options {
+      seed: Some(1),
+      engine: Engine::Async,
+      record: true,
+      ..Default::default()
+    };
+    let log = World::<Tst>::with_options(&options)
+      .unwrap()
+      .run(&options)
+      .unwrap()
+      .log;
+    let frames = log.ticks() + 1;
+    let dir = env::temp_dir().join(format!("cdn-export-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let frame_duration = Duration::from_millis(100);
+
+    let path = dir.join("run.gif");
+    export(&log, &path, frame_duration).unwrap();
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options.read_info(File::open(&path).unwrap()).unwrap();
+    let mut decoded = 0;
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+      assert_eq!(10, frame.delay);
+      decoded += 1;
+    }
+    assert_eq!(frames, decoded);
+
+    let path = dir.join("run.png");
+    export(&log, &path, frame_duration).unwrap();
+    let reader = png::Decoder::new(File::open(&path).unwrap())
+      .read_info()
+      .unwrap();
+    let control = reader.info().animation_control.unwrap();
+    assert_eq!(frames as u32, control.num_frames);
+
+    export(&log, &dir.join("run.svg"), frame_duration).unwrap();
+    let svgs = fs::read_dir(&dir).unwrap().flatten();
+    let svgs = svgs.filter(|e| e.path().extension().is_some_and(|ext| ext == "svg"));
+    assert_eq!(frames, svgs.count());
+
+    fs::remove_dir_all(&dir).unwrap();
+    let e = Format::from_path(Path::new("run.bmp")).unwrap_err();
+    assert!(matches!(e.kind(), CdnErrorKind::UnknownExportFormat));
+  }
+
+  #[test]
+  fn test_obstacles() {
+    let log = EventLog {
+      len_x: 2,
+      len_y: 1,
+      spawns: vec![(0, 0)],
+      obstacles: vec![(1, 0)],
+      ..Default::default()
+    };
+    let frame = Frame {
+      positions: log.spawns.clone(),
+      ..Default::default()
+    };
+    let fill = format!(
+      "<rect x=\"1\" y=\"0\" width=\"1\" height=\"1\" fill=\"{}\"/>",
+      hex(OBSTACLE)
+    );
+    assert!(svg(&log, &frame).contains(&fill));
+
+    let canvas = Canvas::new(&log);
+    let obstacle = HOUSE_COLOURS.iter().position(|&c| c == OBSTACLE).unwrap();
+    // The middle of the obstacle, away from the grid
+    let pixels = canvas.render(&frame);
+    let (x, y) = (canvas.cell * 3 / 2, canvas.cell / 2);
+    assert_eq!(obstacle as u8, pixels[y * canvas.width + x]);
+  }
+}
@@ -0,0 +1,299 @@
+use self::sparse::SparseGrid;
+use crate::{position::Position, world::WorldConfig};
+use clap::ValueEnum;
+use ndarray::Array2;
+use std::mem::size_of;
+
+/// The way the houses of the [`crate::world::World`] and the map of each
+/// [`crate::repairman::Repairman`] are laid out in memory. It can be selected
+/// at runtime.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Layout {
+  /// Every cell is allocated up front.
+  #[default]
+  Dense,
+  /// Cells are allocated in chunks, the first time a cell of the chunk is
+  /// accessed. Suitable for very large worlds with few repairmen.
+  Sparse,
+}
+
+/// A two dimensional grid of cells with the size of the world.
+#[derive(Debug)]
+pub enum Grid<T> {
+  Dense(Array2<T>),
+  Sparse(SparseGrid<T>),
+}
+
+impl<T: Default> Grid<T> {
+  pub fn new<C: WorldConfig>(layout: Layout) -> Self {
+    match layout {
+      Layout::Dense => Self::Dense(Array2::default((C::MAX_LEN_Y, C::MAX_LEN_X))),
+      Layout::Sparse => Self::Sparse(SparseGrid::new(C::MAX_LEN_X, C::MAX_LEN_Y)),
+    }
+  }
+
+  /// Returns the cell, allocating its chunk if needed.
+  pub fn get<C: WorldConfig>(&self, pos: &Position<C>) -> &T {
+    match self {
+      Self::Dense(grid) => &grid[pos],
+      Self::Sparse(grid) => grid.get(pos.x(), pos.y()),
+    }
+  }
+
+  /// Returns the cell, allocating its chunk if needed.
+  pub fn get_mut<C: WorldConfig>(&mut self, pos: &Position<C>) -> &mut T {
+    match self {
+      Self::Dense(grid) => &mut grid[pos],
+      Self::Sparse(grid) => grid.get_mut(pos.x(), pos.y()),
+    }
+  }
+
+  /// Returns the cell without allocating anything. `None` means the cell has
+  /// never been accessed and still has its default value.
+  pub fn peek<C: WorldConfig>(&self, pos: &Position<C>) -> Option<&T> {
+    match self {
+      Self::Dense(grid) => Some(&grid[pos]),
+      Self::Sparse(grid) => grid.peek(pos.x(), pos.y()),
+    }
+  }
+
+  /// The number of bytes allocated for the cells. Heap memory owned by the
+  /// cells themselves is not included.
+  pub fn memory_usage(&self) -> usize {
+    match self {
+      Self::Dense(grid) => grid.len() * size_of::<T>(),
+      Self::Sparse(grid) => grid.memory_usage(),
+    }
+  }
+
+  /// The positions of the allocated cells, row by row inside each chunk. The
+  /// other cells still have their default value.
+  pub fn positions<C: WorldConfig>(&self) -> Box<dyn Iterator<Item = Position<C>> + '_> {
+    match self {
+      Self::Dense(_) => {
+        let (len_x, len_y) = (C::MAX_LEN_X, C::MAX_LEN_Y);
+        let positions = 0..len_x * len_y;
+        Box::new(positions.filter_map(move |i| Position::new(i % len_x, i / len_x).ok()))
+      }
+      Self::Sparse(grid) => Box::new(
+        grid
+          .allocated()
+          .filter_map(|(x, y)| Position::new(x, y).ok()),
+      ),
+    }
+  }
+}
+
+//
+//  SparseGrid
+//
+
+mod sparse {
+  use std::{
+    fmt::{Debug, Formatter, Result as FmtResult},
+    mem::size_of,
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+  };
+
+  const CHUNK_SIDE: usize = 64;
+  const CHUNK_LEN: usize = CHUNK_SIDE * CHUNK_SIDE;
+
+  type Chunk<T> = [T; CHUNK_LEN];
+
+  /// A grid of lazily allocated square chunks. Chunks can be allocated
+  /// through a shared reference, so the grid can be shared between threads
+  /// the same way as [`ndarray::Array2`]. Chunks are only freed when the grid
+  /// is dropped.
+  pub struct SparseGrid<T> {
+    len_x: usize,
+    len_y: usize,
+    chunks_x: usize,
+    chunks: Box<[AtomicPtr<Chunk<T>>]>,
+  }
+
+  impl<T: Default> SparseGrid<T> {
+    pub fn new(len_x: usize, len_y: usize) -> Self {
+      let chunks_x = len_x.div_ceil(CHUNK_SIDE);
+      let chunks_y = len_y.div_ceil(CHUNK_SIDE);
+      let chunks = (0..chunks_x * chunks_y)
+        .map(|_| AtomicPtr::new(ptr::null_mut()))
+        .collect();
+      Self {
+        len_x,
+        len_y,
+        chunks_x,
+        chunks,
+      }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> &T {
+      let (chunk, i) = self.index(x, y);
+      let link = &self.chunks[chunk];
+      let mut chunk = link.load(Ordering::Acquire);
+      if chunk.is_null() {
+        let new = Self::new_chunk();
+        chunk =
+          match link.compare_exchange(ptr::null_mut(), new, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => new,
+            Err(current) => {
+              // SAFETY: `new` was never shared
+              drop(unsafe { Box::from_raw(new) });
+              current
+            }
+          }
+      }
+      // SAFETY: chunks are never freed while `self` is borrowed
+      unsafe { &(*chunk)[i] }
+    }
+
+    pub fn get_mut(&mut self, x: usize, y: usize) -> &mut T {
+      let (chunk, i) = self.index(x, y);
+      let chunk = self.chunks[chunk].get_mut();
+      if chunk.is_null() {
+        *chunk = Self::new_chunk();
+      }
+      // SAFETY: `&mut self` guarantees exclusive access
+      unsafe { &mut (**chunk)[i] }
+    }
+
+    pub fn peek(&self, x: usize, y: usize) -> Option<&T> {
+      let (chunk, i) = self.index(x, y);
+      let chunk = self.chunks[chunk].load(Ordering::Acquire);
+      // SAFETY: chunks are never freed while `self` is borrowed
+      unsafe { chunk.as_ref() }.map(|chunk| &chunk[i])
+    }
+
+    pub fn memory_usage(&self) -> usize {
+      let allocated = self
+        .chunks
+        .iter()
+        .filter(|c| !c.load(Ordering::Acquire).is_null())
+        .count();
+      self.chunks.len() * size_of::<AtomicPtr<Chunk<T>>>() + allocated * size_of::<Chunk<T>>()
+    }
+
+    // The cells of the allocated chunks which are inside the grid
+    pub fn allocated(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+      let chunks = self.chunks.iter().enumerate();
+      let chunks = chunks.filter(|(_, c)| !c.load(Ordering::Acquire).is_null());
+      chunks.flat_map(move |(i, _)| {
+        let (left, top) = (
+          (i % self.chunks_x) * CHUNK_SIDE,
+          (i / self.chunks_x) * CHUNK_SIDE,
+        );
+        let (right, bottom) = (
+          (left + CHUNK_SIDE).min(self.len_x),
+          (top + CHUNK_SIDE).min(self.len_y),
+        );
+        (top..bottom).flat_map(move |y| (left..right).map(move |x| (x, y)))
+      })
+    }
+
+    fn new_chunk() -> *mut Chunk<T> {
+      let chunk: Box<[T]> = (0..CHUNK_LEN).map(|_| T::default()).collect();
+      let chunk: Box<Chunk<T>> = chunk.try_into().unwrap_or_else(|_| unreachable!());
+      Box::into_raw(chunk)
+    }
+
+    // Returns the index of the chunk and the index of the cell in it
+    fn index(&self, x: usize, y: usize) -> (usize, usize) {
+      let chunk = (y / CHUNK_SIDE) * self.chunks_x + x / CHUNK_SIDE;
+      let cell = (y % CHUNK_SIDE) * CHUNK_SIDE + x % CHUNK_SIDE;
+      (chunk, cell)
+    }
+  }
+
+  // SAFETY: the chunks are owned by the grid and are only accessed through
+  // its references, just like the elements of a `Vec<T>`.
+  unsafe impl<T: Send> Send for SparseGrid<T> {}
+  unsafe impl<T: Send + Sync> Sync for SparseGrid<T> {}
+
+  impl<T> Drop for SparseGrid<T> {
+    fn drop(&mut self) {
+      for chunk in self.chunks.iter_mut() {
+        let chunk = *chunk.get_mut();
+        if !chunk.is_null() {
+          // SAFETY: `&mut self` guarantees that nobody else is reading
+          drop(unsafe { Box::from_raw(chunk) });
+        }
+      }
+    }
+  }
+
+  impl<T> Debug for SparseGrid<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+      let allocated = self
+        .chunks
+        .iter()
+        .filter(|c| !c.load(Ordering::Acquire).is_null())
+        .count();
+      f.debug_struct("SparseGrid")
+        .field("chunks", &self.chunks.len())
+        .field("allocated", &allocated)
+        .finish()
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Grid, Layout};
+  use crate::{
+    position::Position,
+    world::{test::Tst, WorldConfig},
+  };
+
+  #[test]
+  fn test_sparse_grid() {
+    struct Huge;
+    impl WorldConfig for Huge {
+      const MAX_LEN_X: usize = 100_000;
+      const MAX_LEN_Y: usize = 100_000;
+    }
+
+    let mut grid = Grid::<u8>::new::<Huge>(Layout::Sparse);
+    let empty = grid.memory_usage();
+    let pos = Position::<Huge>::new(99_999, 50_000).unwrap();
+    assert_eq!(None, grid.peek(&pos));
+    assert_eq!(empty, grid.memory_usage());
+
+    *grid.get_mut(&pos) = 7;
+    assert_eq!(Some(&7), grid.peek(&pos));
+    assert_eq!(
+      &0,
+      grid.get(&Position::<Huge>::new(99_998, 50_001).unwrap())
+    );
+    assert_eq!(None, grid.peek(&Position::<Huge>::new(0, 0).unwrap()));
+    assert!(grid.memory_usage() > empty);
+    assert!(grid.memory_usage() < 100_000_000);
+
+    // Only the chunk of the two cells is allocated, and it is cut at the edge
+    let positions: Vec<_> = grid.positions::<Huge>().collect();
+    assert_eq!(32 * 64, positions.len());
+    assert!(positions.contains(&pos));
+  }
+
+  #[test]
+  fn test_layouts_are_equivalent() {
+    let mut dense = Grid::<usize>::new::<Tst>(Layout::Dense);
+    let mut sparse = Grid::<usize>::new::<Tst>(Layout::Sparse);
+    for y in 0..Tst::MAX_LEN_Y {
+      for x in 0..Tst::MAX_LEN_X {
+        let pos = Position::<Tst>::new(x, y).unwrap();
+        *dense.get_mut(&pos) = x * 10 + y;
+        *sparse.get_mut(&pos) = x * 10 + y;
+      }
+    }
+    for y in 0..Tst::MAX_LEN_Y {
+      for x in 0..Tst::MAX_LEN_X {
+        let pos = Position::<Tst>::new(x, y).unwrap();
+        assert_eq!(dense.get(&pos), sparse.get(&pos));
+      }
+    }
+    assert_eq!(
+      dense.positions::<Tst>().collect::<Vec<_>>(),
+      sparse.positions::<Tst>().collect::<Vec<_>>()
+    );
+  }
+}
@@ -0,0 +1,263 @@
+//! Aggregates the [`EventLog`]s of one or more runs per house, to see where
+//! the repairmen spend their time.
+
+use crate::{
+  error::{CdnErrorKind, CdnResult, Mismatch},
+  events::{EventKind, EventLog},
+  screen::{mix, Rgb, FOREGROUND, OBSTACLE, REPAIRED},
+};
+use clap::ValueEnum;
+use owo_colors::{OwoColorize, Style as OwoStyle};
+use std::{collections::HashSet, fmt::Write};
+
+// The colour of the highest value. The lowest one is the colour of repaired
+// houses.
+const HOT: Rgb = (240, 90, 50);
+
+/// What a heatmap shows. Every measure is the mean of all the runs.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Measure {
+  /// The number of times a repairman arrived at the house, including
+  /// spawning there.
+  #[default]
+  Visits,
+  /// The tick of the first visit, in the runs in which the house was visited.
+  FirstVisit,
+  /// The number of times a repairman couldn't read the house, because
+  /// another one was using it.
+  Contention,
+  /// The number of ticks repairmen spent idling in the house.
+  Idle,
+}
+
+/// The sums of every measure per house. See [`Measure`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Cell {
+  pub visits: usize,
+  /// The number of runs in which the house was visited.
+  pub visited_runs: usize,
+  pub first_visit: usize,
+  pub contention: usize,
+  pub idle: usize,
+}
+
+/// The [`Cell`]s of a world, row by row. Obstacles have no measures.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Heatmap {
+  pub len_x: usize,
+  pub len_y: usize,
+  pub runs: usize,
+  pub cells: Vec<Cell>,
+  /// The obstacles of every run.
+  pub obstacles: HashSet<(usize, usize)>,
+}
+
+impl Heatmap {
+  pub fn new(len_x: usize, len_y: usize) -> Self {
+    Self {
+      len_x,
+      len_y,
+      runs: 0,
+      cells: vec![Cell::default(); len_x * len_y],
+      obstacles: HashSet::new(),
+    }
+  }
+
+  /// Adds a run. Fails with [`CdnErrorKind::ConfigMismatch`] if the world of
+  /// the run is not as big as the heatmap.
+  pub fn add(&mut self, log: &EventLog) -> CdnResult<()> {
+    let (expected, actual) = ((self.len_x, self.len_y), (log.len_x, log.len_y));
+    if expected != actual {
+      return Err(CdnErrorKind::ConfigMismatch(Mismatch::Size { expected, actual }).into());
+    }
+
+    self.runs += 1;
+    self.obstacles.extend(log.obstacles.iter().copied());
+    let mut first_visits = vec![None; self.cells.len()];
+    let spawns = log.spawns.iter().map(|&(x, y)| (0, x, y, EventKind::Move));
+    let events = log.events.iter().map(|e| (e.tick, e.x, e.y, e.kind));
+    for (tick, x, y, kind) in spawns.chain(events) {
+      let i = y * self.len_x + x;
+      let cell = &mut self.cells[i];
+      match kind {
+        EventKind::Move => {
+          cell.visits += 1;
+          first_visits[i].get_or_insert(tick);
+        }
+        EventKind::Idle => cell.idle += 1,
+        EventKind::Contend => cell.contention += 1,
+        EventKind::Repair => (),
+      }
+    }
+
+    for (cell, first_visit) in self.cells.iter_mut().zip(first_visits) {
+      if let Some(tick) = first_visit {
+        cell.visited_runs += 1;
+        cell.first_visit += tick;
+      }
+    }
+    Ok(())
+  }
+
+  /// The value of a measure in the house at `(x, y)`, or `None` if it was
+  /// never visited, such as obstacles.
+  pub fn get(&self, x: usize, y: usize, measure: Measure) -> Option<f64> {
+    let cell = &self.cells[y * self.len_x + x];
+    let mean = |sum: usize| sum as f64 / self.runs as f64;
+    match measure {
+      _ if cell.visited_runs == 0 => None,
+      Measure::Visits => Some(mean(cell.visits)),
+      Measure::FirstVisit => Some(cell.first_visit as f64 / cell.visited_runs as f64),
+      Measure::Contention => Some(mean(cell.contention)),
+      Measure::Idle => Some(mean(cell.idle)),
+    }
+  }
+
+  /// One line per house, with a header:
+  /// `x,y,visits,first_visit,contention,idle`. Never visited houses have no
+  /// values, and obstacles are skipped.
+  pub fn to_csv(&self) -> String {
+    let measures = Measure::value_variants();
+    let names: Vec<_> = measures
+      .iter()
+      .filter_map(|m| m.to_possible_value())
+      .map(|v| v.get_name().replace('-', "_"))
+      .collect();
+    let mut s = format!("x,y,{}\n", names.join(","));
+    for y in 0..self.len_y {
+      for x in (0..self.len_x).filter(|&x| !self.obstacles.contains(&(x, y))) {
+        let _ = write!(s, "{x},{y}");
+        for &measure in measures {
+          let value = self.get(x, y, measure);
+          let _ = write!(s, ",{}", value.map(|v| v.to_string()).unwrap_or_default());
+        }
+        s.push('\n');
+      }
+    }
+    s
+  }
+
+  /// Renders a measure in colour for the terminal, rounded to integers.
+  /// Higher values are hotter, and obstacles are marked with `#`.
+  pub fn render(&self, measure: Measure) -> String {
+    let houses: Vec<_> = (0..self.len_y)
+      .flat_map(|y| (0..self.len_x).map(move |x| (x, y)))
+      .collect();
+    let values: Vec<_> = houses
+      .iter()
+      .map(|&(x, y)| self.get(x, y, measure))
+      .collect();
+    let max = values.iter().flatten().fold(0.0, |max: f64, v| max.max(*v));
+    let width = format!("{max:.0}").len();
+
+    let mut s = String::new();
+    let rows = houses.chunks(self.len_x.max(1));
+    for (row, values) in rows.zip(values.chunks(self.len_x.max(1))) {
+      for (xy, value) in row.iter().zip(values) {
+        let (text, bg) = match value {
+          _ if self.obstacles.contains(xy) => ("#".into(), OBSTACLE),
+          Some(v) if max > 0.0 => (format!("{v:.0}"), mix(REPAIRED, HOT, v / max)),
+          Some(v) => (format!("{v:.0}"), REPAIRED),
+          None => ("-".into(), REPAIRED),
+        };
+        let (r, g, b) = bg;
+        let (fr, fg, fb) = FOREGROUND;
+        let style = OwoStyle::new().truecolor(fr, fg, fb).on_truecolor(r, g, b);
+        let _ = write!(s, " {}", format!("{text:>width$}").style(style));
+      }
+      s.push('\n');
+    }
+    s
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Heatmap, Measure};
+  use crate::{
+    engine::Engine,
+    events::{Event, EventKind, EventLog},
+    world::{test::Tst, World, WorldConfig},
+    Options,
+  };
+
+  #[test]
+  fn test_heatmap() {
+    let log = EventLog {
+      len_x: 3,
+      len_y: 2,
+      spawns: vec![(0, 0), (2, 1)],
+      broken_houses: vec![(1, 0)],
+      obstacles: vec![(0, 1)],
+      events: [
+        (1, 0, EventKind::Move, 1, 0),
+        (1, 1, EventKind::Contend, 2, 1),
+        (1, 1, EventKind::Idle, 2, 1),
+        (2, 0, EventKind::Repair, 1, 0),
+        (2, 1, EventKind::Move, 1, 1),
+        (3, 1, EventKind::Move, 1, 0),
+      ]
+      .map(|(tick, id, kind, x, y)| Event {
+        tick,
+        id: id.into(),
+        kind,
+        x,
+        y,
+      })
+      .to_vec(),
+    };
+    let mut heatmap = Heatmap::new(3, 2);
+    heatmap.add(&log).unwrap();
+    heatmap.add(&log).unwrap();
+
+    assert_eq!(Some(2.0), heatmap.get(1, 0, Measure::Visits));
+    assert_eq!(Some(1.0), heatmap.get(1, 0, Measure::FirstVisit));
+    assert_eq!(Some(0.0), heatmap.get(0, 0, Measure::FirstVisit));
+    assert_eq!(Some(1.0), heatmap.get(2, 1, Measure::Contention));
+    assert_eq!(Some(1.0), heatmap.get(2, 1, Measure::Idle));
+    assert_eq!(None, heatmap.get(2, 0, Measure::Visits));
+
+    let csv = heatmap.to_csv();
+    let mut lines = csv.lines();
+    assert_eq!(Some("x,y,visits,first_visit,contention,idle"), lines.next());
+    assert_eq!(Some("0,0,1,0,0,0"), lines.next());
+    assert_eq!(Some("1,0,2,1,0,0"), lines.next());
+    assert_eq!(Some("2,0,,,,"), lines.next());
+    // The obstacle is skipped
+    assert_eq!(Some("1,1,1,2,0,0"), lines.next());
+    let render = heatmap.render(Measure::Visits);
+    assert_eq!(2, render.lines().count());
+    assert!(render.contains('#'));
+
+    let e = Heatmap::new(2, 3).add(&log).err().unwrap();
+    assert_eq!(
+      "config mismatch: expected a 2x3 world, found 3x2",
+      e.to_string()
+    );
+  }
+
+  #[test]
+  fn test_batch() {
+    let mut heatmap = Heatmap::new(Tst::MAX_LEN_X, Tst::MAX_LEN_Y);
+    let mut moves = 0;
+    for seed in 0..5 {
+      let options = Options {
+        seed: Some(seed),
+        engine: Engine::Async,
+        record: true,
+        ..Default::default()
+      };
+      let list = World::<Tst>::with_options(&options)
+        .unwrap()
+        .run(&options)
+        .unwrap();
+      moves += list.metrics.moves;
+      heatmap.add(&list.log).unwrap();
+    }
+
+    let visits: usize = heatmap.cells.iter().map(|c| c.visits).sum();
+    assert_eq!(moves + 5 * Tst::REPAIRMEN, visits);
+    // A single threaded executor never contends for a house
+    assert!(heatmap.cells.iter().all(|c| c.contention == 0));
+  }
+}
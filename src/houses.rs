@@ -0,0 +1,471 @@
+use self::atomic_house::AtomicHouse;
+use crate::{
+  error::CdnResult,
+  grid::{Grid, Layout},
+  position::Position,
+  repairman::Id,
+  world::{House, HouseStatus, Notes, WorldConfig},
+};
+use clap::ValueEnum;
+use std::{
+  ptr,
+  sync::{Mutex, TryLockError},
+};
+
+/// The way houses are stored in the [`crate::world::World`]. It can be
+/// selected at runtime.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Storage {
+  /// Each house is guarded by a `Mutex`. Repairmen idle if the house they
+  /// occupy is locked by someone else.
+  #[default]
+  Locked,
+  /// The status of each house is an atomic and notes are kept in a lock-free
+  /// append-only list. Repairmen never idle.
+  LockFree,
+}
+
+/// A grid of houses.
+#[derive(Debug)]
+pub enum Houses {
+  Locked(Grid<Mutex<House>>),
+  LockFree(Grid<AtomicHouse>),
+}
+
+/// A change of a house by a repairman. See [`HouseRef::write`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HouseWrite {
+  /// See [`HouseRef::try_repair`]. The note of the `Id`, who repaired
+  /// `usize` houses before, is written along, counting the repair if it
+  /// succeeded. Locked houses do both under the same lock, so that no one
+  /// reads a repaired house without the note.
+  Repair(Option<(Id, usize)>),
+  /// See [`HouseRef::write_note`].
+  Note(Id, usize),
+  /// See [`HouseRef::announce_job_complete`].
+  AnnounceJobComplete,
+}
+
+/// A shared reference to a house inside [`Houses`].
+#[derive(Clone, Copy, Debug)]
+pub enum HouseRef<'a> {
+  Locked(&'a Mutex<House>),
+  LockFree(&'a AtomicHouse),
+}
+
+impl Houses {
+  /// Creates a grid of repaired houses with no notes.
+  pub fn new<C: WorldConfig>(storage: Storage, layout: Layout) -> Self {
+    match storage {
+      Storage::Locked => Self::Locked(Grid::new::<C>(layout)),
+      Storage::LockFree => Self::LockFree(Grid::new::<C>(layout)),
+    }
+  }
+
+  pub fn get<C: WorldConfig>(&self, pos: &Position<C>) -> HouseRef<'_> {
+    match self {
+      Self::Locked(houses) => HouseRef::Locked(houses.get(pos)),
+      Self::LockFree(houses) => HouseRef::LockFree(houses.get(pos)),
+    }
+  }
+
+  /// Same as [`Self::get`], but returns `None` instead of allocating the
+  /// house if it has never been accessed. Such a house is repaired and has
+  /// no notes.
+  pub fn peek<C: WorldConfig>(&self, pos: &Position<C>) -> Option<HouseRef<'_>> {
+    match self {
+      Self::Locked(houses) => houses.peek(pos).map(HouseRef::Locked),
+      Self::LockFree(houses) => houses.peek(pos).map(HouseRef::LockFree),
+    }
+  }
+
+  /// The positions of the allocated houses. See [`Grid::positions`].
+  pub fn positions<C: WorldConfig>(&self) -> Box<dyn Iterator<Item = Position<C>> + '_> {
+    match self {
+      Self::Locked(houses) => houses.positions(),
+      Self::LockFree(houses) => houses.positions(),
+    }
+  }
+
+  /// The number of bytes allocated for the houses, excluding their notes.
+  pub fn memory_usage(&self) -> usize {
+    match self {
+      Self::Locked(houses) => houses.memory_usage(),
+      Self::LockFree(houses) => houses.memory_usage(),
+    }
+  }
+}
+
+impl<'a> HouseRef<'a> {
+  /// Returns the status of the house, or `None` if the house is currently
+  /// occupied by another repairman.
+  pub fn try_status(&self) -> CdnResult<Option<HouseStatus>> {
+    match self {
+      Self::Locked(house) => match house.try_lock() {
+        Ok(house) => Ok(Some(house.status)),
+        Err(TryLockError::WouldBlock) => Ok(None),
+        Err(TryLockError::Poisoned(e)) => Err(e.into()),
+      },
+      Self::LockFree(house) => Ok(Some(house.status())),
+    }
+  }
+
+  /// Blocks until the status of the house is available.
+  pub fn status(&self) -> CdnResult<HouseStatus> {
+    match self {
+      Self::Locked(house) => Ok(house.lock()?.status),
+      Self::LockFree(house) => Ok(house.status()),
+    }
+  }
+
+  pub fn set_status(&self, status: HouseStatus) -> CdnResult<()> {
+    match self {
+      Self::Locked(house) => house.lock()?.status = status,
+      Self::LockFree(house) => house.set_status(status),
+    }
+    Ok(())
+  }
+
+  /// Changes the status from [`HouseStatus::NeedsRepair`] to
+  /// [`HouseStatus::Repaired`]. Returns `false` if the house was already
+  /// repaired by someone else.
+  pub fn try_repair(&self) -> CdnResult<bool> {
+    self.write(HouseWrite::Repair(None), |_| Ok(()))
+  }
+
+  /// Writes the number of houses repaired by `id` onto the house.
+  pub fn write_note(&self, id: Id, num_repaired: usize) -> CdnResult<()> {
+    self.write(HouseWrite::Note(id, num_repaired), |_| Ok(()))?;
+    Ok(())
+  }
+
+  /// Applies a [`HouseWrite`] of a repairman. `enter` is called before the
+  /// write and what it returns is dropped after it, so that writes can be
+  /// observed. It is passed whether the write is exclusive: if the house is
+  /// guarded by a `Mutex`, `enter` is called once it is locked and no one
+  /// else can write. Lock-free writes are never exclusive. Returns `false`
+  /// only if a repair found the house already repaired.
+  pub fn write<G>(
+    &self,
+    write: HouseWrite,
+    enter: impl FnOnce(bool) -> CdnResult<G>,
+  ) -> CdnResult<bool> {
+    match self {
+      Self::Locked(house) => {
+        let mut house = house.lock()?;
+        let _guard = enter(true)?;
+        match write {
+          HouseWrite::Repair(note) => {
+            let needs_repair = house.status == HouseStatus::NeedsRepair;
+            house.status = HouseStatus::Repaired;
+            if let Some((id, num)) = note {
+              house.notes.merge_one(id, num + usize::from(needs_repair));
+            }
+            return Ok(needs_repair);
+          }
+          HouseWrite::Note(id, num) => house.notes.merge_one(id, num),
+          HouseWrite::AnnounceJobComplete => house.notes.announce_job_complete(),
+        }
+      }
+      Self::LockFree(house) => {
+        let _guard = enter(false)?;
+        match write {
+          HouseWrite::Repair(note) => {
+            let repaired = house.try_repair();
+            if let Some((id, num)) = note {
+              house.write_note(id, num + usize::from(repaired));
+            }
+            return Ok(repaired);
+          }
+          HouseWrite::Note(id, num) => house.write_note(id, num),
+          HouseWrite::AnnounceJobComplete => house.announce_job_complete(),
+        }
+      }
+    }
+    Ok(true)
+  }
+
+  /// Merges the notes inside the house into `notebook`. Only the bigger
+  /// numbers are kept, and the announcement is copied.
+  pub fn read_notes(&self, notebook: &mut Notes) -> CdnResult<()> {
+    match self {
+      Self::Locked(house) => notebook.merge(&house.lock()?.notes),
+      Self::LockFree(house) => {
+        house.for_each_note(|id, num| notebook.merge_one(id, num));
+        if house.is_job_complete() {
+          notebook.announce_job_complete();
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Leaves a note for everyone that every house is repaired.
+  pub fn announce_job_complete(&self) -> CdnResult<()> {
+    self.write(HouseWrite::AnnounceJobComplete, |_| Ok(()))?;
+    Ok(())
+  }
+
+  /// Whether both refer to the same house.
+  pub fn ptr_eq(&self, other: &HouseRef<'_>) -> bool {
+    match (self, other) {
+      (Self::Locked(a), HouseRef::Locked(b)) => ptr::eq(*a, *b),
+      (Self::LockFree(a), HouseRef::LockFree(b)) => ptr::eq(*a, *b),
+      _ => false,
+    }
+  }
+
+  /// Returns a copy of the notes inside the house.
+  pub fn notes(&self) -> CdnResult<Notes> {
+    let mut notes = Notes::default();
+    self.read_notes(&mut notes)?;
+    Ok(notes)
+  }
+}
+
+//
+//  AtomicHouse
+//
+
+mod atomic_house {
+  use crate::{repairman::Id, world::HouseStatus};
+  use std::{
+    fmt::{Debug, Formatter, Result as FmtResult},
+    iter, ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+  };
+
+  const CHUNK_LEN: usize = 32;
+
+  /// A house that can be shared between threads without locking. Notes are
+  /// stored in an append-only list of fixed-size chunks which are only freed
+  /// when the house is dropped, so readers never observe a dangling chunk.
+  #[derive(Default)]
+  pub struct AtomicHouse {
+    needs_repair: AtomicBool,
+    job_complete: AtomicBool,
+    notes: AtomicPtr<Chunk>,
+  }
+
+  #[derive(Default)]
+  struct Chunk {
+    // The number of reserved entries. It may exceed `CHUNK_LEN`.
+    len: AtomicUsize,
+    entries: [Note; CHUNK_LEN],
+    next: AtomicPtr<Chunk>,
+  }
+
+  // `key` is the `Id` plus one, so that zero marks a reserved entry that is
+  // not written yet.
+  #[derive(Default)]
+  struct Note {
+    key: AtomicUsize,
+    num_repaired: AtomicUsize,
+  }
+
+  impl AtomicHouse {
+    pub fn status(&self) -> HouseStatus {
+      match self.needs_repair.load(Ordering::Acquire) {
+        true => HouseStatus::NeedsRepair,
+        false => HouseStatus::Repaired,
+      }
+    }
+
+    pub fn set_status(&self, status: HouseStatus) {
+      let needs_repair = status == HouseStatus::NeedsRepair;
+      self.needs_repair.store(needs_repair, Ordering::Release);
+    }
+
+    pub fn is_job_complete(&self) -> bool {
+      self.job_complete.load(Ordering::Acquire)
+    }
+
+    pub fn announce_job_complete(&self) {
+      self.job_complete.store(true, Ordering::Release);
+    }
+
+    pub fn try_repair(&self) -> bool {
+      self
+        .needs_repair
+        .compare_exchange(true, false, Ordering::AcqRel, Ordering::Acquire)
+        .is_ok()
+    }
+
+    /// Keeps the bigger number if a note of the same `Id` exists, otherwise
+    /// appends a new note. Two threads appending the same `Id` at the same
+    /// time may both succeed, which is harmless since readers keep the bigger
+    /// number anyway.
+    pub fn write_note(&self, id: Id, num_repaired: usize) {
+      let key = id.as_ref() + 1;
+      let mut link = &self.notes;
+      loop {
+        let chunk = Self::get_or_insert_chunk(link);
+        let len = chunk.len.load(Ordering::Acquire).min(CHUNK_LEN);
+        for note in &chunk.entries[..len] {
+          if note.key.load(Ordering::Acquire) == key {
+            note.num_repaired.fetch_max(num_repaired, Ordering::AcqRel);
+            return;
+          }
+        }
+
+        if len < CHUNK_LEN {
+          let i = chunk.len.fetch_add(1, Ordering::AcqRel);
+          if let Some(note) = chunk.entries.get(i) {
+            note.num_repaired.store(num_repaired, Ordering::Relaxed);
+            note.key.store(key, Ordering::Release);
+            return;
+          }
+        }
+        link = &chunk.next;
+      }
+    }
+
+    pub fn for_each_note(&self, mut f: impl FnMut(Id, usize)) {
+      for chunk in self.chunks() {
+        let len = chunk.len.load(Ordering::Acquire).min(CHUNK_LEN);
+        for note in &chunk.entries[..len] {
+          match note.key.load(Ordering::Acquire) {
+            0 => continue,
+            key => f((key - 1).into(), note.num_repaired.load(Ordering::Acquire)),
+          }
+        }
+      }
+    }
+
+    fn chunks(&self) -> impl Iterator<Item = &Chunk> {
+      let first = self.notes.load(Ordering::Acquire);
+      // SAFETY: chunks are never freed while `self` is borrowed
+      iter::successors(unsafe { first.as_ref() }, |chunk| unsafe {
+        chunk.next.load(Ordering::Acquire).as_ref()
+      })
+    }
+
+    fn get_or_insert_chunk(link: &AtomicPtr<Chunk>) -> &Chunk {
+      let mut chunk = link.load(Ordering::Acquire);
+      if chunk.is_null() {
+        let new = Box::into_raw(Box::<Chunk>::default());
+        chunk =
+          match link.compare_exchange(ptr::null_mut(), new, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => new,
+            Err(current) => {
+              // SAFETY: `new` was never shared
+              drop(unsafe { Box::from_raw(new) });
+              current
+            }
+          };
+      }
+      // SAFETY: chunks are never freed while the house is borrowed
+      unsafe { &*chunk }
+    }
+  }
+
+  impl Drop for AtomicHouse {
+    fn drop(&mut self) {
+      let mut chunk = *self.notes.get_mut();
+      while !chunk.is_null() {
+        // SAFETY: `&mut self` guarantees that nobody else is reading
+        let mut boxed = unsafe { Box::from_raw(chunk) };
+        chunk = *boxed.next.get_mut();
+      }
+    }
+  }
+
+  impl Debug for AtomicHouse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+      let mut notes = Vec::new();
+      self.for_each_note(|id, num| notes.push((id, num)));
+      f.debug_struct("AtomicHouse")
+        .field("status", &self.status())
+        .field("job_complete", &self.is_job_complete())
+        .field("notes", &notes)
+        .finish()
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{HouseWrite, Houses, Storage};
+  use crate::{
+    grid::Layout,
+    position::Position,
+    world::{test::Tst, HouseStatus, Notes},
+  };
+  use std::thread;
+
+  #[test]
+  fn test_try_repair() {
+    for storage in [Storage::Locked, Storage::LockFree] {
+      let houses = Houses::new::<Tst>(storage, Layout::Dense);
+      let house = houses.get(&Position::<Tst>::new(1, 2).unwrap());
+      assert!(!house.try_repair().unwrap());
+
+      house.set_status(HouseStatus::NeedsRepair).unwrap();
+      assert_eq!(Some(HouseStatus::NeedsRepair), house.try_status().unwrap());
+      assert!(house.try_repair().unwrap());
+      assert!(!house.try_repair().unwrap());
+      assert_eq!(HouseStatus::Repaired, house.status().unwrap());
+    }
+  }
+
+  #[test]
+  fn test_repair_with_note() {
+    for storage in [Storage::Locked, Storage::LockFree] {
+      let houses = Houses::new::<Tst>(storage, Layout::Dense);
+      let house = houses.get(&Position::<Tst>::new(1, 2).unwrap());
+      house.set_status(HouseStatus::NeedsRepair).unwrap();
+      let (a, b) = (0.into(), 1.into());
+      assert!(house
+        .write(HouseWrite::Repair(Some((a, 2))), |_| Ok(()))
+        .unwrap());
+      // The note only counts a repair that succeeded
+      assert!(!house
+        .write(HouseWrite::Repair(Some((b, 4))), |_| Ok(()))
+        .unwrap());
+      let notes = house.notes().unwrap();
+      assert_eq!(Some(&3), notes.as_ref().get(&a));
+      assert_eq!(Some(&4), notes.as_ref().get(&b));
+    }
+  }
+
+  #[test]
+  fn test_concurrent_notes() {
+    const THREADS: usize = 4;
+    const WRITES: usize = 100;
+
+    for storage in [Storage::Locked, Storage::LockFree] {
+      let houses = Houses::new::<Tst>(storage, Layout::Sparse);
+      let house = houses.get(&Position::<Tst>::new(0, 0).unwrap());
+      thread::scope(|s| {
+        for id in 0..THREADS {
+          s.spawn(move || {
+            for num in 1..=WRITES {
+              house.write_note(id.into(), num).unwrap();
+              // Older notes must not overwrite newer ones
+              house.write_note(id.into(), num - 1).unwrap();
+            }
+          });
+        }
+      });
+
+      let notes = house.notes().unwrap();
+      assert_eq!(THREADS, notes.as_ref().len());
+      assert!(notes.as_ref().values().all(|num| *num == WRITES));
+    }
+  }
+
+  #[test]
+  fn test_announce_job_complete() {
+    for storage in [Storage::Locked, Storage::LockFree] {
+      let houses = Houses::new::<Tst>(storage, Layout::Dense);
+      let house = houses.get(&Position::<Tst>::new(0, 0).unwrap());
+      let mut notebook = Notes::default();
+      house.read_notes(&mut notebook).unwrap();
+      assert!(!notebook.is_job_complete());
+
+      house.announce_job_complete().unwrap();
+      house.read_notes(&mut notebook).unwrap();
+      assert!(notebook.is_job_complete());
+    }
+  }
+}
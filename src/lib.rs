@@ -0,0 +1,237 @@
+pub mod audit;
+pub mod barrier;
+pub mod engine;
+pub mod env;
+pub mod error;
+pub mod events;
+pub mod export;
+pub mod grid;
+pub mod heatmap;
+pub mod houses;
+pub mod output;
+pub mod position;
+pub mod repairman;
+pub mod scenario;
+pub mod screen;
+pub mod snapshot;
+pub mod solver;
+pub mod strategy;
+pub mod verify;
+pub mod world;
+
+use crate::{
+  engine::Engine,
+  error::CdnResult,
+  events::EventLog,
+  grid::Layout,
+  houses::Storage,
+  repairman::{Id, Repairman, Report},
+  screen::Frontend,
+  snapshot::Snapshot,
+  strategy::Strategy,
+  world::{Notes, World, WorldConfig},
+};
+use std::{
+  collections::BTreeMap,
+  fmt::{Display, Formatter, Result as FmtResult},
+  mem,
+  time::Duration,
+};
+
+/// Stores the result of each finished repairman. See [`World::run`].
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct List {
+  pub notes: BTreeMap<Id, Notes>,
+  pub metrics: Metrics,
+  /// Empty unless `Options::record` is set.
+  pub log: EventLog,
+  /// Set if `Options::snapshot_at` is set and a repairman was still working
+  /// at that tick. The rest of the `List` only covers the ticks before it.
+  pub snapshot: Option<Snapshot>,
+}
+
+/// Measurements of a run, reported alongside the [`List`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metrics {
+  /// The seed the world was generated from.
+  pub seed: u64,
+  /// The number of bytes allocated for the houses of the world.
+  pub houses_memory: usize,
+  /// The number of bytes allocated for the maps of all the repairmen.
+  pub maps_memory: usize,
+  /// The number of ticks until the last repairman was done.
+  pub ticks: usize,
+  /// The number of moves of all the repairmen.
+  pub moves: usize,
+  /// The tick in which the last house was repaired.
+  pub last_repair: usize,
+  /// The tick in which each repairman was done.
+  pub exits: BTreeMap<Id, usize>,
+  /// The number of houses still needing repair after the run.
+  pub unrepaired: usize,
+  /// The number of writes onto lock-free houses that overlapped with another
+  /// one, if the run was audited.
+  pub concurrent_writes: usize,
+}
+
+/// Runtime settings of a [`World`] and of [`World::run`].
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+  pub storage: Storage,
+  pub layout: Layout,
+  /// The seed the world is generated from. A random one is used if not set.
+  pub seed: Option<u64>,
+  pub engine: Engine,
+  pub strategy: Strategy,
+  /// Repairmen who know that every house is repaired leave a note saying
+  /// so, which makes the others stop as soon as they read it.
+  pub announce: bool,
+  /// Hides the number of houses needing repair from the repairmen, so they
+  /// only stop once they have explored the whole world, or read an
+  /// announcement of someone who did.
+  pub hidden_total: bool,
+  /// The repairmen record what they do in every tick into [`List::log`], so
+  /// that the run can be replayed. The log starts from the spawns, so the
+  /// ticks of a world restored from a snapshot can't be replayed.
+  pub record: bool,
+  /// Makes the repairmen take their turns in the order of their `Id`s, so
+  /// that a seed always leads to the same result, regardless of the engine.
+  pub deterministic: bool,
+  /// How the world is displayed every frame.
+  pub frontend: Frontend,
+  /// The world is displayed every frame if set.
+  pub frame_duration: Option<Duration>,
+  /// The repairmen are cancelled if they are not done by then.
+  pub time_limit: Option<Duration>,
+  /// The repairmen stop after this many ticks, and the state of the run is
+  /// returned in [`List::snapshot`]. Only deterministic runs can be resumed
+  /// exactly.
+  pub snapshot_at: Option<usize>,
+  /// Fails the run with [`error::CdnErrorKind::RuleViolation`] as soon as a
+  /// repairman breaks a rule of the challenge. See [`audit`].
+  pub audit: bool,
+}
+
+impl<C: WorldConfig + Sync> World<C> {
+  /// Lets every [`Repairman`] in the world do their tasks on the
+  /// selected [`Engine`]. If `options.frame_duration` is set, the world is
+  /// periodically printed to the standard output with that interval.
+  ///
+  /// If the repairmen are not done within `options.time_limit`, they are
+  /// cancelled and [`error::CdnErrorKind::TimedOut`] is returned. Errors carry
+  /// the seed of the world, see [`error::CdnError::seed`].
+  pub fn run(&self, options: &Options) -> CdnResult<List> {
+    // SAFETY: The engines hire every repairman once
+    let hire = |id| unsafe { Repairman::with_options(id, self, options) };
+    self.run_with(options, &hire)
+  }
+
+  /// Same as [`Self::run`], but every repairman is created by `hire`, with
+  /// their `Id`.
+  pub(crate) fn run_with<'a, H>(&'a self, options: &Options, hire: &H) -> CdnResult<List>
+  where
+    H: Fn(Id) -> Repairman<'a, C> + Sync,
+  {
+    self
+      .try_run(options, hire)
+      .map_err(|e| e.with_seed(self.seed()))
+  }
+
+  fn try_run<'a, H>(&'a self, options: &Options, hire: &H) -> CdnResult<List>
+  where
+    H: Fn(Id) -> Repairman<'a, C> + Sync,
+  {
+    let mut list = match options.engine {
+      Engine::Threads => engine::run_threads(self, options, hire),
+      Engine::Async => engine::run_async(self, options, hire),
+    }?;
+    list.metrics.seed = self.seed();
+    list.metrics.houses_memory = self.memory_usage();
+    list.metrics.unrepaired = self.unrepaired()?;
+    list.metrics.concurrent_writes = self.concurrent_writes();
+    if options.record {
+      list.log = EventLog::new(self, mem::take(&mut list.log.events));
+    }
+    if let Some(tick) = options.snapshot_at {
+      let repairmen = mem::take(&mut list.snapshot).unwrap_or_default().repairmen;
+      list.snapshot = match repairmen.iter().any(|man| man.action.is_some()) {
+        true => Some(Snapshot::new(self, tick, repairmen)?),
+        false => None,
+      };
+    }
+    Ok(list)
+  }
+}
+
+impl List {
+  pub(crate) fn insert(&mut self, report: Report) {
+    self.metrics.maps_memory += report.map_memory;
+    self.metrics.ticks = self.metrics.ticks.max(report.ticks);
+    self.metrics.moves += report.moves;
+    self.metrics.last_repair = self.metrics.last_repair.max(report.last_repair);
+    self.metrics.exits.insert(report.id, report.ticks);
+    self.log.events.extend(report.events);
+    if let Some(state) = report.snapshot {
+      // The states are only collected here, see `World::run`
+      let repairmen = &mut self.snapshot.get_or_insert_with(Default::default).repairmen;
+      let i = *report.id.as_ref();
+      if repairmen.len() <= i {
+        repairmen.resize_with(i + 1, Default::default);
+      }
+      repairmen[i] = state;
+    }
+    self.notes.insert(report.id, report.notebook);
+  }
+}
+
+impl Metrics {
+  /// The number of ticks each repairman kept working after the last house
+  /// was repaired.
+  pub fn exit_lags(&self) -> impl Iterator<Item = (Id, usize)> + '_ {
+    let last_repair = self.last_repair;
+    self
+      .exits
+      .iter()
+      .map(move |(id, exit)| (*id, exit.saturating_sub(last_repair)))
+  }
+
+  /// The number of ticks all the repairmen spent after the last repair.
+  pub fn wasted_ticks(&self) -> usize {
+    self.exit_lags().map(|(_, lag)| lag).sum()
+  }
+}
+
+impl Display for List {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    let mut total = 0;
+    for r in self.summaries() {
+      let (id, r, s, l) = (r.id, r.repaired, r.notes_sum, r.exit_lag);
+      let n: Vec<_> = self.notes[&id].as_ref().values().collect();
+      writeln!(
+        f,
+        "{id:2?}, Repaired({r:2}), Notes({n:?}), NotesSum({s}), ExitLag({l})"
+      )?;
+      total += r;
+    }
+    let wasted = self.metrics.wasted_ticks();
+    let Metrics {
+      houses_memory: h,
+      maps_memory: m,
+      ticks,
+      moves,
+      last_repair,
+      unrepaired,
+      ..
+    } = self.metrics;
+    writeln!(f, "TotalRepaired({total}), Unrepaired({unrepaired})")?;
+    writeln!(
+      f,
+      "Ticks({ticks}), Moves({moves}), LastRepair({last_repair}), WastedTicks({wasted})"
+    )?;
+    writeln!(f, "MemoryUsage(Houses({h}), Maps({m}))")?;
+    if self.metrics.concurrent_writes > 0 {
+      writeln!(f, "ConcurrentWrites({})", self.metrics.concurrent_writes)?;
+    }
+    Ok(())
+  }
+}
@@ -1,28 +1,109 @@
-mod barrier;
-pub mod error;
-pub mod position;
-pub mod repairman;
-pub mod world;
-
-use crate::{
-  barrier::Barrier,
+use cdn_systems_challenge::{
+  engine::Engine,
   error::CdnResult,
-  repairman::{Id, Repairman},
-  world::{Notes, World, WorldConfig},
-};
-use crossterm::{
-  cursor::MoveTo,
-  style::Print,
-  terminal::{Clear, ClearType},
-  ExecutableCommand,
-};
-use std::{
-  collections::BTreeMap,
-  fmt::{Display, Formatter, Result as FmtResult},
-  io::stdout,
-  thread,
-  time::Duration,
+  events::EventLog,
+  export::export,
+  grid::Layout,
+  heatmap::{Heatmap, Measure},
+  houses::Storage,
+  output::Format,
+  scenario::Scenario,
+  screen::Frontend,
+  snapshot::Snapshot,
+  strategy::Strategy,
+  world::{World, WorldConfig},
+  Options,
 };
+use clap::Parser;
+use std::{fs, path::PathBuf, process, time::Duration};
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+  /// How the houses are stored
+  #[arg(long, value_enum, default_value = "locked")]
+  storage: Storage,
+  /// How the houses and the maps of the repairmen are laid out in memory
+  #[arg(long, value_enum, default_value = "dense")]
+  layout: Layout,
+  /// How the repairmen are executed
+  #[arg(long, value_enum, default_value = "threads")]
+  engine: Engine,
+  /// How the repairmen explore the world
+  #[arg(long, value_enum, default_value = "nearest")]
+  strategy: Strategy,
+  /// How the world is displayed while the repairmen are working
+  #[arg(long, value_enum, default_value = "plain")]
+  frontend: Frontend,
+  /// Let the repairmen who know that the job is complete tell the others
+  #[arg(long)]
+  announce: bool,
+  /// Hide the number of houses needing repair from the repairmen
+  #[arg(long)]
+  hidden_total: bool,
+  /// Fail the run as soon as a repairman breaks a rule of the challenge
+  #[arg(long)]
+  audit: bool,
+  /// The seed the world is generated from
+  #[arg(long)]
+  seed: Option<u64>,
+  /// Let the repairmen take turns in a fixed order, so that the same seed
+  /// always gives the same result
+  #[arg(long)]
+  deterministic: bool,
+  /// How the results are printed
+  #[arg(long, value_enum, default_value = "plain")]
+  output: Format,
+  /// Write what the repairmen did in every tick to a file
+  #[arg(long, value_name = "PATH")]
+  log: Option<PathBuf>,
+  /// Export every tick as an animated GIF (.gif), an animated PNG (.png) or
+  /// one SVG file per tick (.svg)
+  #[arg(long, value_name = "PATH")]
+  export: Option<PathBuf>,
+  /// Export a file written by --log instead of running the world
+  #[arg(long, value_name = "PATH", requires = "export")]
+  replay: Option<PathBuf>,
+  /// Load the world from a scenario file instead of generating it. It must
+  /// be as big as the world and have as many repairmen and houses needing
+  /// repair
+  #[arg(long, value_name = "PATH", conflicts_with = "replay")]
+  scenario: Option<PathBuf>,
+  /// Print a heatmap of a measure per house to the standard error, after
+  /// the run
+  #[arg(long, value_enum, value_name = "MEASURE")]
+  heatmap: Option<Measure>,
+  /// Write every measure per house to a CSV file, after the run
+  #[arg(long, value_name = "PATH")]
+  heatmap_csv: Option<PathBuf>,
+  /// Run the world this many times with consecutive seeds, without
+  /// displaying it, and aggregate the heatmaps of all the runs
+  #[arg(long, default_value_t = 1, conflicts_with_all = ["log", "export", "replay"])]
+  runs: u64,
+  /// Stop the repairmen after this many ticks and write the state of the
+  /// run to the file given by --snapshot
+  #[arg(
+    long,
+    value_name = "TICK",
+    requires = "snapshot",
+    conflicts_with = "runs"
+  )]
+  snapshot_at: Option<usize>,
+  /// The file the state of the run is written to, see --snapshot-at
+  #[arg(long, value_name = "PATH", requires = "snapshot_at")]
+  snapshot: Option<PathBuf>,
+  /// Continue a run from a file written by --snapshot. The seed of the file
+  /// is used. The ticks before the snapshot aren't in the file, so the run
+  /// can't be logged, exported or turned into a heatmap
+  #[arg(
+    long,
+    value_name = "PATH",
+    conflicts_with_all = ["scenario", "replay", "log", "export", "heatmap", "heatmap_csv"]
+  )]
+  resume: Option<PathBuf>,
+}
+
+const FRAME_DURATION: Duration = Duration::from_millis(300);
 
 fn main() {
   struct City1;
@@ -33,66 +114,105 @@ fn main() {
     // const HOUSES_NEEDING_REPAIR: usize = 6;
   }
 
-  const FRAME_DURATION_MS: u64 = 300;
-  match World::<City1>::new().run(FRAME_DURATION_MS) {
-    Err(e) => eprintln!("{e}"),
-    Ok(list) => println!("{list}"),
+  const TIME_LIMIT: Option<Duration> = Some(Duration::from_secs(120));
+
+  let cli = Cli::parse();
+  let options = Options {
+    storage: cli.storage,
+    layout: cli.layout,
+    seed: cli.seed,
+    engine: cli.engine,
+    strategy: cli.strategy,
+    announce: cli.announce,
+    hidden_total: cli.hidden_total,
+    deterministic: cli.deterministic,
+    record: cli.log.is_some()
+      || cli.export.is_some()
+      || cli.heatmap.is_some()
+      || cli.heatmap_csv.is_some(),
+    frontend: cli.frontend,
+    // The world is only displayed alongside plain results
+    frame_duration: (cli.runs <= 1 && cli.output == Format::Plain).then_some(FRAME_DURATION),
+    time_limit: TIME_LIMIT,
+    snapshot_at: cli.snapshot_at,
+    audit: cli.audit,
+  };
+  if let Err(e) = run::<City1>(&cli, &options) {
+    eprintln!("{e}");
+    process::exit(1);
   }
 }
 
-/// Stores the result of each finished thread. See [`World.run`].
-#[derive(Debug, Default)]
-pub struct List(BTreeMap<Id, Notes>);
-
-impl<C: WorldConfig + Sync> World<C> {
-  /// This function spawns new threads for each [`Repairman`] in the world
-  /// to execute their tasks. It then periodically prints the world to the
-  /// standard output with a specified interval in milliseconds defined by
-  /// `frame_duration_ms`.
-  fn run(&self, frame_duration_ms: u64) -> CdnResult<List> {
-    thread::scope(|s| {
-      let mut handles = Vec::new();
-      let barrier = Barrier::new();
-      for id in self.get_repairmen_ids() {
-        let bar = barrier.clone();
-        let h = s.spawn(move || unsafe { Repairman::new(id, bar, self).work() });
-        handles.push(h);
-      }
-
-      let mut list = List::default();
-      stdout().execute(Clear(ClearType::All))?;
-      while !handles.is_empty() {
-        stdout().execute(MoveTo(0, 0))?.execute(Print(&self))?;
-
-        let (finished, rest): (Vec<_>, Vec<_>) = handles.into_iter().partition(|h| h.is_finished());
-        handles = rest;
-        for h in finished {
-          let (id, notes) = h.join()??;
-          list.0.insert(id, notes);
+fn run<C: WorldConfig + Sync>(cli: &Cli, options: &Options) -> CdnResult<()> {
+  let mut logs: Vec<EventLog> = Vec::new();
+  match &cli.replay {
+    Some(path) => logs.push(fs::read_to_string(path)?.parse()?),
+    None => {
+      let snapshot: Option<Snapshot> = match &cli.resume {
+        Some(path) => Some(fs::read_to_string(path)?.parse()?),
+        None => None,
+      };
+      let scenario: Option<Scenario> = match &cli.scenario {
+        Some(path) => Some(fs::read_to_string(path)?.parse()?),
+        None => None,
+      };
+      let seed = options.seed.unwrap_or_else(rand::random);
+      for i in 0..cli.runs.max(1) {
+        let options = Options {
+          seed: Some(seed.wrapping_add(i)),
+          ..options.clone()
+        };
+        let world = match (&snapshot, &scenario) {
+          (Some(snapshot), _) => World::<C>::with_snapshot(snapshot, &options)?,
+          (_, Some(scenario)) => World::<C>::with_scenario(scenario, &options)?,
+          _ => World::<C>::with_options(&options)?,
+        };
+        let list = world.run(&options)?;
+        // A run stopped for a snapshot is incomplete. The results of a run
+        // failing verification are still printed.
+        let verified = match list.snapshot {
+          Some(_) => Ok(()),
+          None => world.verify(&list),
+        };
+        match cli.output {
+          Format::Plain => println!("{list}"),
+          Format::Json => println!("{}", list.to_json()),
+          Format::Csv => print!("{}", list.to_csv(i == 0)),
         }
-
-        // These lines slow down the program for better visualization. They
-        // can be removed if not needed.
-        barrier.wait();
-        thread::sleep(Duration::from_millis(frame_duration_ms));
+        verified?;
+        if let Some(path) = &cli.snapshot {
+          match &list.snapshot {
+            Some(snapshot) => fs::write(path, snapshot.to_string())?,
+            None => eprintln!("Every repairman was done before the snapshot"),
+          }
+        }
+        logs.push(list.log);
       }
+    }
+  };
 
-      Ok(list)
-    })
+  if let Some(log) = logs.last() {
+    if let Some(path) = &cli.log {
+      fs::write(path, log.to_string())?;
+    }
+    if let Some(path) = &cli.export {
+      export(log, path, FRAME_DURATION)?;
+    }
   }
-}
 
-impl Display for List {
-  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-    let mut total = 0;
-    for (id, notes) in &self.0 {
-      let r = notes.as_ref().get(id).cloned().unwrap_or_default();
-      let n: Vec<_> = notes.as_ref().iter().map(|n| *n.1).collect();
-      let s = notes.as_ref().iter().fold(0, |s, (_, n)| s + n);
-      writeln!(f, "{id:2?}, Repaired({r:2}), Notes({n:?}), NotesSum({s})")?;
-      total += r;
+  if let (Some(log), true) = (
+    logs.first(),
+    cli.heatmap.is_some() || cli.heatmap_csv.is_some(),
+  ) {
+    let mut heatmap = Heatmap::new(log.len_x, log.len_y);
+    logs.iter().try_for_each(|log| heatmap.add(log))?;
+    if let Some(measure) = cli.heatmap {
+      // Results on the standard output can still be piped
+      eprint!("{}", heatmap.render(measure));
+    }
+    if let Some(path) = &cli.heatmap_csv {
+      fs::write(path, heatmap.to_csv())?;
     }
-    writeln!(f, "TotalRepaired({total})")?;
-    Ok(())
   }
+  Ok(())
 }
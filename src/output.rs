@@ -0,0 +1,246 @@
+//! Machine-readable output of a [`List`], per repairman, for tools that
+//! ingest the results of runs.
+
+use crate::{repairman::Id, List};
+use clap::ValueEnum;
+use std::{collections::BTreeMap, fmt::Write};
+
+/// How a [`List`] is printed.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+  /// The `Display` format, for people.
+  #[default]
+  Plain,
+  /// A JSON object per run. See [`List::to_json`].
+  Json,
+  /// A line per repairman. See [`List::to_csv`].
+  Csv,
+}
+
+/// The result of a repairman, as read from their notebook.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+  pub id: Id,
+  /// The number of houses the repairman repaired themselves.
+  pub repaired: usize,
+  /// The number of houses repaired by each repairman, as far as the
+  /// repairman knows.
+  pub notes: BTreeMap<Id, usize>,
+  pub notes_sum: usize,
+  /// The tick in which the repairman was done.
+  pub exit: usize,
+  /// The number of ticks the repairman kept working after the last repair.
+  pub exit_lag: usize,
+  /// Whether the notebook has the announcement that the job is complete.
+  pub job_complete: bool,
+  /// Whether no note counts more houses than the repairman it is about
+  /// repaired. Notes can only fall behind. See [`List::overcounts`].
+  pub consistent: bool,
+  /// Whether the notes add up to every house repaired in the run.
+  pub complete: bool,
+}
+
+const CSV_HEADER: &str =
+  "seed,id,repaired,notes,notes_sum,exit,exit_lag,job_complete,consistent,complete";
+
+impl List {
+  /// The [`Summary`] of each repairman, in the order of their `Id`s.
+  pub fn summaries(&self) -> Vec<Summary> {
+    let repaired = |id: &Id| self.repaired(id);
+    let total: usize = self.notes.keys().map(repaired).sum();
+    let lags: BTreeMap<_, _> = self.metrics.exit_lags().collect();
+    self
+      .notes
+      .iter()
+      .map(|(id, notebook)| {
+        let notes = notebook.as_ref().clone();
+        let notes_sum = notes.values().sum();
+        Summary {
+          id: *id,
+          repaired: repaired(id),
+          consistent: self.overcounts(id).next().is_none(),
+          complete: notes_sum == total,
+          notes,
+          notes_sum,
+          exit: self.metrics.exits.get(id).copied().unwrap_or_default(),
+          exit_lag: lags.get(id).copied().unwrap_or_default(),
+          job_complete: notebook.is_job_complete(),
+        }
+      })
+      .collect()
+  }
+
+  /// A single line JSON object with the [`crate::Metrics`] of the run and the
+  /// [`Summary`] of each repairman under `repairmen`. The notes are objects
+  /// from `Id`s to numbers of repaired houses.
+  pub fn to_json(&self) -> String {
+    let m = &self.metrics;
+    let mut s = format!(
+      "{{\"seed\":{},\"total_repaired\":{},\"unrepaired\":{},\"ticks\":{},\"moves\":{},\
+       \"last_repair\":{},\"wasted_ticks\":{},\"houses_memory\":{},\"maps_memory\":{},\
+       \"concurrent_writes\":{},\"repairmen\":[",
+      m.seed,
+      self.total_repaired(),
+      m.unrepaired,
+      m.ticks,
+      m.moves,
+      m.last_repair,
+      m.wasted_ticks(),
+      m.houses_memory,
+      m.maps_memory,
+      m.concurrent_writes,
+    );
+    for (i, r) in self.summaries().iter().enumerate() {
+      let notes: Vec<_> = r
+        .notes
+        .iter()
+        .map(|(id, n)| format!("\"{}\":{n}", id.as_ref()))
+        .collect();
+      let _ = write!(
+        s,
+        "{}{{\"id\":{},\"repaired\":{},\"notes\":{{{}}},\"notes_sum\":{},\"exit\":{},\
+         \"exit_lag\":{},\"job_complete\":{},\"consistent\":{},\"complete\":{}}}",
+        if i == 0 { "" } else { "," },
+        r.id.as_ref(),
+        r.repaired,
+        notes.join(","),
+        r.notes_sum,
+        r.exit,
+        r.exit_lag,
+        r.job_complete,
+        r.consistent,
+        r.complete,
+      );
+    }
+    s.push_str("]}");
+    s
+  }
+
+  /// One line per repairman, with the header
+  /// `seed,id,repaired,notes,notes_sum,exit,exit_lag,job_complete,consistent,complete`
+  /// if `header` is set, so that the lines of several runs can be appended.
+  /// The notes are written as `id=repaired` separated by spaces.
+  pub fn to_csv(&self, header: bool) -> String {
+    let mut s = String::new();
+    if header {
+      s.push_str(CSV_HEADER);
+      s.push('\n');
+    }
+    for r in self.summaries() {
+      let notes: Vec<_> = r
+        .notes
+        .iter()
+        .map(|(id, n)| format!("{}={n}", id.as_ref()))
+        .collect();
+      let _ = writeln!(
+        s,
+        "{},{},{},{},{},{},{},{},{},{}",
+        self.metrics.seed,
+        r.id.as_ref(),
+        r.repaired,
+        notes.join(" "),
+        r.notes_sum,
+        r.exit,
+        r.exit_lag,
+        r.job_complete,
+        r.consistent,
+        r.complete,
+      );
+    }
+    s
+  }
+
+  /// The notes in the notebook of `id` that count more houses than the
+  /// repairman they are about repaired, as `(other, noted, repaired)`. Notes
+  /// about someone who isn't a repairman of the run were written before it,
+  /// and are left out.
+  pub fn overcounts<'a>(&'a self, id: &Id) -> impl Iterator<Item = (Id, usize, usize)> + 'a {
+    let notes = self.notes.get(id).map(|n| n.as_ref().iter());
+    notes.into_iter().flatten().filter_map(|(other, &noted)| {
+      let repaired = self.repaired(other);
+      (self.notes.contains_key(other) && noted > repaired).then_some((*other, noted, repaired))
+    })
+  }
+
+  // The number of houses the repairman `id` repaired themselves
+  fn repaired(&self, id: &Id) -> usize {
+    let notes = self.notes.get(id).map(|n| n.as_ref());
+    notes.and_then(|n| n.get(id)).copied().unwrap_or_default()
+  }
+
+  /// The number of houses repaired by all the repairmen.
+  pub fn total_repaired(&self) -> usize {
+    self.summaries().iter().map(|r| r.repaired).sum()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{
+    engine::Engine,
+    world::{test::Tst, Notes, World, WorldConfig},
+    List, Options,
+  };
+
+  #[test]
+  fn test_summaries() {
+    let mut list = List::default();
+    let mut notes = Notes::default();
+    notes.merge_one(0.into(), 2);
+    notes.merge_one(1.into(), 1);
+    list.notes.insert(0.into(), notes.clone());
+    notes.merge_one(1.into(), 3);
+    notes.announce_job_complete();
+    list.notes.insert(1.into(), notes);
+    list.metrics.exits.insert(0.into(), 5);
+    list.metrics.exits.insert(1.into(), 9);
+    list.metrics.last_repair = 7;
+    list.metrics.seed = 42;
+
+    let summaries = list.summaries();
+    assert_eq!(2, summaries[0].repaired);
+    assert_eq!((3, 0), (summaries[0].notes_sum, summaries[0].exit_lag));
+    assert!(summaries[0].consistent && !summaries[0].complete);
+    let r = &summaries[1];
+    assert_eq!((3, 5, 2), (r.repaired, r.notes_sum, r.exit_lag));
+    assert!(r.consistent && r.complete && r.job_complete);
+    assert_eq!(5, list.total_repaired());
+
+    let csv = list.to_csv(true);
+    let mut lines = csv.lines().skip(1);
+    assert_eq!(Some("42,0,2,0=2 1=1,3,5,0,false,true,false"), lines.next());
+    assert_eq!(Some("42,1,3,0=2 1=3,5,9,2,true,true,true"), lines.next());
+    assert_eq!(None, lines.next());
+    assert_eq!(2, list.to_csv(false).lines().count());
+
+    let json = list.to_json();
+    assert!(json.starts_with("{\"seed\":42,\"total_repaired\":5,"));
+    assert!(json.contains(
+      "{\"id\":0,\"repaired\":2,\"notes\":{\"0\":2,\"1\":1},\"notes_sum\":3,\"exit\":5,\
+       \"exit_lag\":0,\"job_complete\":false,\"consistent\":true,\"complete\":false}"
+    ));
+    assert!(json.ends_with("true}]}"));
+  }
+
+  #[test]
+  fn test_runs_are_consistent() {
+    for seed in 0..5 {
+      let options = Options {
+        seed: Some(seed),
+        engine: Engine::Async,
+        ..Default::default()
+      };
+      let list = World::<Tst>::with_options(&options)
+        .unwrap()
+        .run(&options)
+        .unwrap();
+      assert_eq!(seed, list.metrics.seed);
+      assert_eq!(Tst::HOUSES_NEEDING_REPAIR, list.total_repaired());
+      let summaries = list.summaries();
+      assert_eq!(Tst::REPAIRMEN, summaries.len());
+      assert!(summaries.iter().all(|r| r.consistent));
+      // Someone has to know that the job is complete to stop
+      assert!(summaries.iter().any(|r| r.complete));
+    }
+  }
+}
@@ -2,12 +2,10 @@ use crate::{
   error::{CdnErrorKind, CdnResult},
   world::WorldConfig,
 };
-use core::panic;
 use ndarray::{Dim, NdIndex};
 use rand::{
   distributions::{Distribution, Standard},
-  rngs::ThreadRng,
-  seq::SliceRandom,
+  seq::index,
   Rng,
 };
 use std::{fmt::Debug, hash::Hash, marker::PhantomData};
@@ -38,29 +36,40 @@ pub struct Position<C: WorldConfig> {
 
 impl<C: WorldConfig> Position<C> {
   /// Creates a new valid Position.
-  /// Panics if x or y are greater than the size of the World.
-  pub fn new(x: usize, y: usize) -> Self {
+  /// Fails if x or y are not smaller than the size of the World.
+  pub fn new(x: usize, y: usize) -> CdnResult<Self> {
     if x >= C::MAX_LEN_X || y >= C::MAX_LEN_Y {
-      panic!("x and y must be smaller than MAX_X and MAX_Y")
+      let (len_x, len_y) = (C::MAX_LEN_X, C::MAX_LEN_Y);
+      return Err(CdnErrorKind::OutOfBounds { x, y, len_x, len_y }.into());
     }
+    Ok(Self::new_unchecked(x, y))
+  }
 
+  // The caller makes sure that x and y are inside the World
+  const fn new_unchecked(x: usize, y: usize) -> Self {
     Self {
       inner: PositionInner { x, y },
       phantom: PhantomData,
     }
   }
 
-  /// Returns a set of unique and random `Position`s.
-  pub fn new_random_set(rng: &mut ThreadRng, len: usize) -> Vec<Self> {
-    let mut numbers: Vec<usize> = (0..C::MAX_LEN_X * C::MAX_LEN_Y).collect();
-    numbers.shuffle(rng);
-    numbers.truncate(len);
-    numbers
+  /// Returns a set of unique and random `Position`s. Only `len` numbers are
+  /// allocated, no matter how big the world is.
+  pub fn new_random_set<R: Rng + ?Sized>(rng: &mut R, len: usize) -> Vec<Self> {
+    index::sample(rng, C::MAX_LEN_X * C::MAX_LEN_Y, len)
       .into_iter()
-      .map(|n| Self::new(n % C::MAX_LEN_X, n / C::MAX_LEN_X))
+      .map(|n| Self::new_unchecked(n % C::MAX_LEN_X, n / C::MAX_LEN_X))
       .collect()
   }
 
+  pub const fn x(&self) -> usize {
+    self.inner.x
+  }
+
+  pub const fn y(&self) -> usize {
+    self.inner.y
+  }
+
   /// Changes the `Position` according to the `MoveDirection`.
   pub fn r#move(&mut self, direction: MoveDirection) -> CdnResult<()> {
     match direction {
@@ -76,24 +85,28 @@ impl<C: WorldConfig> Position<C> {
       MoveDirection::Down if self.inner.y > 0 => {
         self.inner.y -= 1;
       }
-      _ => return Err(CdnErrorKind::InvalidMoveDirection.into()),
+      _ => {
+        let (x, y) = (self.inner.x, self.inner.y);
+        return Err(CdnErrorKind::InvalidMoveDirection { x, y, direction }.into());
+      }
     }
     Ok(())
   }
 
   /// Converts two adjacent `Position`s to `MoveDirection`.
-  /// Panics if the two positions are not adjacent.
-  pub fn direction_to(&self, other: &Self) -> MoveDirection {
+  /// Fails if the two positions are not adjacent.
+  pub fn direction_to(&self, other: &Self) -> CdnResult<MoveDirection> {
     if self.inner.x.checked_add(1) == Some(other.inner.x) && self.inner.y == other.inner.y {
-      MoveDirection::Right
+      Ok(MoveDirection::Right)
     } else if self.inner.x.checked_sub(1) == Some(other.inner.x) && self.inner.y == other.inner.y {
-      MoveDirection::Left
+      Ok(MoveDirection::Left)
     } else if self.inner.y.checked_add(1) == Some(other.inner.y) && self.inner.x == other.inner.x {
-      MoveDirection::Up
+      Ok(MoveDirection::Up)
     } else if self.inner.y.checked_sub(1) == Some(other.inner.y) && self.inner.x == other.inner.x {
-      MoveDirection::Down
+      Ok(MoveDirection::Down)
     } else {
-      panic!("self and other are not adjacent positions")
+      let (from, to) = ((self.inner.x, self.inner.y), (other.inner.x, other.inner.y));
+      Err(CdnErrorKind::NotAdjacent { from, to }.into())
     }
   }
 
@@ -194,7 +207,10 @@ unsafe impl<C: WorldConfig> NdIndex<Dim<[usize; 2]>> for &Position<C> {
 #[cfg(test)]
 mod test {
   use super::{MoveDirection::*, Position};
-  use crate::world::{test::Tst, WorldConfig};
+  use crate::{
+    error::CdnErrorKind,
+    world::{test::Tst, WorldConfig},
+  };
   use ndarray::{array, Array2};
   use rand::thread_rng;
   use std::collections::HashSet;
@@ -214,35 +230,47 @@ mod test {
   fn test_position_to_index() {
     let arr2: Array2<usize> = array![[0, 1, 2, 3], [4, 5, 6, 7], [8, 9, 10, 11]];
 
-    let idx = Position::<Tst>::new(2, 1).to_index();
+    let idx = Position::<Tst>::new(2, 1).unwrap().to_index();
     assert_eq!(6, arr2[idx]);
 
-    let idx = Position::<Tst>::new(1, 2).to_index();
+    let idx = Position::<Tst>::new(1, 2).unwrap().to_index();
     assert_eq!(9, arr2[idx]);
 
-    let idx = Position::<Tst>::new(3, 2).to_index();
+    let idx = Position::<Tst>::new(3, 2).unwrap().to_index();
     assert_eq!(11, arr2[idx]);
   }
 
   #[test]
-  #[should_panic]
   fn test_new_position_1() {
-    Position::<Tst>::new(0, 4);
+    let e = Position::<Tst>::new(0, 4).unwrap_err();
+    assert!(matches!(
+      e.kind(),
+      CdnErrorKind::OutOfBounds {
+        x: 0,
+        y: 4,
+        len_x: 4,
+        len_y: 3
+      }
+    ));
   }
 
   #[test]
-  #[should_panic]
   fn test_new_position_2() {
-    Position::<Tst>::new(4, 0);
+    let e = Position::<Tst>::new(4, 0).unwrap_err();
+    assert!(matches!(
+      e.kind(),
+      CdnErrorKind::OutOfBounds { x: 4, y: 0, .. }
+    ));
   }
 
   #[test]
   fn test_move_position() {
-    let mut pos = Position::<Tst>::new(Tst::MAX_LEN_X - 1, Tst::MAX_LEN_Y - 1);
+    let mut pos = Position::<Tst>::new(Tst::MAX_LEN_X - 1, Tst::MAX_LEN_Y - 1).unwrap();
     pos.r#move(Up).unwrap_err();
-    pos.r#move(Right).unwrap_err();
+    let e = pos.r#move(Right).unwrap_err();
+    assert_eq!("can't move Right from (3, 2)", e.to_string());
 
-    let mut pos = Position::<Tst>::new(0, 0);
+    let mut pos = Position::<Tst>::new(0, 0).unwrap();
     pos.r#move(Left).unwrap_err();
     pos.r#move(Down).unwrap_err();
 
@@ -258,30 +286,42 @@ mod test {
 
   #[test]
   fn test_direction_to_position() {
-    let pos1 = Position::<Tst>::new(1, 1);
-
-    let pos2 = Position::<Tst>::new(2, 1);
-    assert_eq!(Right, pos1.direction_to(&pos2));
-    let pos2 = Position::<Tst>::new(1, 2);
-    assert_eq!(Up, pos1.direction_to(&pos2));
-    let pos2 = Position::<Tst>::new(0, 1);
-    assert_eq!(Left, pos1.direction_to(&pos2));
-    let pos2 = Position::<Tst>::new(1, 0);
-    assert_eq!(Down, pos1.direction_to(&pos2));
+    let pos1 = Position::<Tst>::new(1, 1).unwrap();
+
+    let pos2 = Position::<Tst>::new(2, 1).unwrap();
+    assert_eq!(Right, pos1.direction_to(&pos2).unwrap());
+    let pos2 = Position::<Tst>::new(1, 2).unwrap();
+    assert_eq!(Up, pos1.direction_to(&pos2).unwrap());
+    let pos2 = Position::<Tst>::new(0, 1).unwrap();
+    assert_eq!(Left, pos1.direction_to(&pos2).unwrap());
+    let pos2 = Position::<Tst>::new(1, 0).unwrap();
+    assert_eq!(Down, pos1.direction_to(&pos2).unwrap());
   }
 
   #[test]
-  #[should_panic]
   fn test_direction_to_non_adjacent_position_1() {
-    let pos = Position::<Tst>::new(0, 0);
-    pos.direction_to(&pos);
+    let pos = Position::<Tst>::new(0, 0).unwrap();
+    let e = pos.direction_to(&pos).unwrap_err();
+    assert!(matches!(
+      e.kind(),
+      CdnErrorKind::NotAdjacent {
+        from: (0, 0),
+        to: (0, 0)
+      }
+    ));
   }
 
   #[test]
-  #[should_panic]
   fn test_direction_to_non_adjacent_position_2() {
-    let pos1 = Position::<Tst>::new(0, 0);
-    let pos2 = Position::<Tst>::new(1, 1);
-    pos1.direction_to(&pos2);
+    let pos1 = Position::<Tst>::new(0, 0).unwrap();
+    let pos2 = Position::<Tst>::new(1, 1).unwrap();
+    let e = pos1.direction_to(&pos2).unwrap_err();
+    assert!(matches!(
+      e.kind(),
+      CdnErrorKind::NotAdjacent {
+        from: (0, 0),
+        to: (1, 1)
+      }
+    ));
   }
 }
@@ -1,194 +1,540 @@
 use crate::{
   barrier::Barrier,
-  error::CdnResult,
+  error::{CdnError, CdnResult, RepairmanContext},
+  events::{Event, EventKind},
+  grid::{Grid, Layout},
+  houses::{HouseRef, HouseWrite},
   position::{MoveDirection, Position},
-  world::{House, HouseStatus, Notes, World, WorldConfig},
+  screen::Frontend,
+  snapshot::RepairmanSnapshot,
+  strategy::{Explore, View},
+  world::{HouseStatus, Notes, World, WorldConfig},
+  Options,
 };
-use ndarray::Array2;
-use pathfinding::directed::bfs::bfs;
-use rand::{seq::SliceRandom, thread_rng};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
 use std::{
+  collections::HashSet,
   ops::{Index, IndexMut},
-  sync::Mutex,
+  panic::{self, AssertUnwindSafe},
 };
 
-enum PathFindingResult {
-  CurrentHouseIsUnexplored,
-  NoUnexploredHouseFound,
-  UnexploredHouseFound(MoveDirection),
-}
-
 /// An unique identifier for [`Repairman`].
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Id(usize);
 
+/// What a [`Repairman`] does in a tick. See [`Repairman::decide`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+  /// The house is occupied by another repairman.
+  Idle,
+  Repair,
+  Move(MoveDirection),
+}
+
+/// What a [`Repairman`] knows about a house.
 #[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
-enum MapStatus {
+pub enum MapStatus {
   #[default]
   Unexplored,
   Explored,
+  /// An obstacle, seen from a house next to it.
+  Blocked,
 }
 
-type FnMove<'a> = Box<dyn Fn(MoveDirection) -> CdnResult<&'a Mutex<House>> + 'a>;
+/// What a [`Repairman`] leaves behind once their work is done.
+#[derive(Debug)]
+pub struct Report {
+  pub id: Id,
+  pub notebook: Notes,
+  /// The number of bytes allocated for the map of the repairman.
+  pub map_memory: usize,
+  /// The number of actions taken, including idling.
+  pub ticks: usize,
+  pub moves: usize,
+  /// The tick in which the repairman repaired their last house, or zero.
+  pub last_repair: usize,
+  /// Empty unless `Options::record` is set.
+  pub events: Vec<Event>,
+  /// The state of the repairman, if `Options::snapshot_at` is set.
+  pub snapshot: Option<RepairmanSnapshot>,
+}
+
+/// What a repairman knows about the world. Shared with [`crate::env::Env`],
+/// whose repairmen learn the same way.
+pub(crate) struct Knowledge {
+  pub(crate) world_map: Grid<MapStatus>,
+  /// The number of explored houses inside `world_map`.
+  pub(crate) explored: usize,
+  pub(crate) notebook: Notes,
+}
+
+type FnMove<'a> = Box<dyn Fn(MoveDirection) -> CdnResult<HouseRef<'a>> + 'a>;
+type FnAccess<'a> = Box<dyn Fn(HouseRef<'a>, Option<HouseWrite>) -> CdnResult<bool> + 'a>;
+type FnPublish<'a> = Box<dyn Fn(&Notes) -> CdnResult<()> + 'a>;
 
 pub struct Repairman<'a, C: WorldConfig> {
   id: Id,
-  world_map: Array2<MapStatus>,
-  notebook: Notes,
+  knowledge: Knowledge,
   position: &'a Position<C>,
-  house: &'a Mutex<House>,
-  barrier: Barrier,
+  house: HouseRef<'a>,
+  obstacles: &'a HashSet<Position<C>>,
+  rng: ChaCha8Rng,
+  explorer: Box<dyn Explore<C> + 'a>,
+  ticks: usize,
+  moves: usize,
+  last_repair: usize,
+  announce: bool,
+  hidden_total: bool,
+  events: Option<Vec<Event>>,
+  snapshot_at: Option<usize>,
+  // The action decided before a snapshot, to be taken when resumed
+  pending: Option<Action>,
+  // Whether the repairman was already done before a snapshot
+  done: bool,
   fn_move: FnMove<'a>,
+  fn_access: FnAccess<'a>,
+  fn_publish: Option<FnPublish<'a>>,
 }
 
 impl<'a, C: WorldConfig + Sync> Repairman<'a, C> {
-  /// Creates a new Repairman. [`Barrier`] is used for communication between
-  /// repairmen.
+  /// Creates a new Repairman. Its random decisions are derived from the seed
+  /// of the world, so that a world always plays out the same way when the
+  /// repairmen take turns in a fixed order.
   ///
   /// # Safety
   /// Two repairmen must not use the same `Id`
-  pub unsafe fn new(id: impl Into<Id>, barrier: Barrier, world: &'a World<C>) -> Self {
-    let inner = |id| Self {
-      id,
-      barrier,
-      world_map: Array2::default((C::MAX_LEN_Y, C::MAX_LEN_X)),
-      notebook: Default::default(),
-      position: world.get_repairman_position(id),
-      house: world.get_repairman_house(id),
-      // The fn_move method is created as a closure to ensure that
-      // each repairman can only modify their own position.
-      // This is done to comply with the challenge rules.
-      fn_move: Box::new(move |dir| world.move_repairman(id, dir)),
+  pub unsafe fn new(id: impl Into<Id>, world: &'a World<C>) -> Self {
+    Self::with_options(id, world, &Options::default())
+  }
+
+  /// Same as [`Repairman::new`], but the world is explored according to
+  /// `options.strategy`, the completion of the job is announced if
+  /// `options.announce` is set and the number of houses needing repair is
+  /// unknown to the repairman if `options.hidden_total` is set. If the world
+  /// was restored from a snapshot, the repairman continues from their state
+  /// in it.
+  ///
+  /// # Safety
+  /// Two repairmen must not use the same `Id`
+  pub unsafe fn with_options(id: impl Into<Id>, world: &'a World<C>, options: &Options) -> Self {
+    let id = id.into();
+    let explorer = options.strategy.explorer(&world.spawns()[*id.as_ref()]);
+    Self::with_explorer(id, world, options, explorer)
+  }
+
+  /// Same as [`Repairman::with_options`], but the world is explored by
+  /// `explorer` instead of `options.strategy`.
+  ///
+  /// # Safety
+  /// Two repairmen must not use the same `Id`
+  pub unsafe fn with_explorer(
+    id: impl Into<Id>,
+    world: &'a World<C>,
+    options: &Options,
+    explorer: Box<dyn Explore<C> + 'a>,
+  ) -> Self {
+    let inner = |id: Id| {
+      let mut rng = ChaCha8Rng::seed_from_u64(world.seed());
+      rng.set_stream(id.0 as u64);
+      Self {
+        id,
+        rng,
+        knowledge: Knowledge::new::<C>(world.layout()),
+        explorer,
+        ticks: 0,
+        moves: 0,
+        last_repair: 0,
+        announce: options.announce,
+        hidden_total: options.hidden_total,
+        events: options.record.then(Vec::new),
+        snapshot_at: options.snapshot_at,
+        pending: None,
+        done: false,
+        position: world.get_repairman_position(id),
+        house: world.get_repairman_house(id),
+        obstacles: world.obstacles(),
+        // The fn_move method is created as a closure to ensure that
+        // each repairman can only modify their own position.
+        // This is done to comply with the challenge rules.
+        fn_move: Box::new(move |dir| world.move_repairman_as(id, id, dir)),
+        // Every access to a house goes through the world as the repairman,
+        // to be audited
+        fn_access: Box::new(move |house, write| match write {
+          Some(write) => world.write_house(id, house, write),
+          None => world.audit_access(id, house).map(|_| true),
+        }),
+        // Only the dashboard shows the notebooks
+        fn_publish: (options.frame_duration.is_some() && options.frontend == Frontend::Dashboard)
+          .then(|| Box::new(move |notes: &Notes| world.publish_notebook(id, notes)) as FnPublish),
+      }
     };
 
-    inner(id.into())
+    let mut man = inner(id.into());
+    if let Some(state) = world.take_resumed(man.id) {
+      man.restore(state);
+    }
+    man
+  }
+
+  /// This is the primary loop of the Repairman. [`Barrier`] is used for
+  /// communication between repairmen: every action waits for the others to
+  /// finish their previous one. See [`Self::decide`] for when the work is
+  /// completed.
+  pub fn work(mut self, barrier: &Barrier) -> CdnResult<Report> {
+    while let Some(action) = self.next_action()? {
+      barrier.wait()?;
+      self.act(action)?;
+    }
+    Ok(self.into_result())
   }
 
-  /// This is the primary decision-making function of the Repairman.
-  /// It completes its work whenever one of these conditions is met:
+  /// This is the primary decision-making function of the Repairman. It
+  /// returns `None` when the work is completed, which happens whenever one of
+  /// these conditions is met:
   /// 1. There are no unexplored houses remaining on the map.
   /// 2. The total number of repaired houses inside the repairman's notebook
-  /// equals the number of houses needing repair.
-  pub fn work(mut self) -> CdnResult<(Id, Notes)> {
-    while self.get_total_num_repaired() < C::HOUSES_NEEDING_REPAIR {
-      // To prevent deadlock between multiple repairmen in the same house,
-      // try_lock() is used instead of lock().
-      let status = match self.house.try_lock() {
-        Ok(house) => house.status,
-        Err(_) => {
-          self.idle();
-          continue;
-        }
-      };
+  ///    equals the number of houses needing repair, unless it is hidden.
+  /// 3. Someone announced that the job is complete.
+  ///
+  /// Errors carry the `Id`, the tick and the position of the repairman, and
+  /// so do panics of the strategy, which are turned into errors.
+  pub fn decide(&mut self) -> CdnResult<Option<Action>> {
+    let tick = self.ticks + 1;
+    let res = panic::catch_unwind(AssertUnwindSafe(|| self.try_decide()));
+    res
+      .unwrap_or_else(|e| Err(e.into()))
+      .map_err(|e| self.context(e, tick))
+  }
+
+  fn try_decide(&mut self) -> CdnResult<Option<Action>> {
+    if self.is_job_complete() {
+      return self.finish();
+    }
+
+    // To prevent deadlock between multiple repairmen in the same house,
+    // the status is not waited for.
+    self.access(None)?;
+    let Some(status) = self.house.try_status()? else {
+      self.record(self.ticks + 1, EventKind::Contend);
+      return Ok(Some(Action::Idle));
+    };
+    if status == HouseStatus::NeedsRepair {
+      return Ok(Some(Action::Repair));
+    }
 
-      match status {
-        HouseStatus::NeedsRepair => self.repair_and_write_note()?,
-        HouseStatus::Repaired => self.write_note()?,
+    self.write_note()?;
+    self.read_notes()?;
+    if self.is_job_complete() {
+      return self.finish();
+    }
+    self.knowledge.explore(self.position, self.obstacles);
+
+    match self.find_path()? {
+      Some(dir) => Ok(Some(Action::Move(dir))),
+      None => self.finish(),
+    }
+  }
+
+  /// Same as [`Self::decide`], but the action decided before a snapshot is
+  /// returned first if the repairman was resumed. It also returns `None` once
+  /// `options.snapshot_at` ticks are played, keeping the decided action for
+  /// the snapshot.
+  pub fn next_action(&mut self) -> CdnResult<Option<Action>> {
+    if self.pending.is_some() || self.done {
+      return Ok(self.pending.take());
+    }
+    let action = self.decide()?;
+    if action.is_some() && self.snapshot_at == Some(self.ticks) {
+      self.pending = action;
+      return Ok(None);
+    }
+    Ok(action)
+  }
+
+  /// Performs an action returned by [`Self::decide`]. It must only be called
+  /// after all the other repairmen have decided their action too.
+  pub fn act(&mut self, action: Action) -> CdnResult<()> {
+    self.ticks += 1;
+    let res = match action {
+      Action::Idle => {
+        self.record(self.ticks, EventKind::Idle);
+        Ok(())
       }
+      Action::Repair => self.repair_and_write_note(),
+      Action::Move(dir) => self.r#move(dir),
+    };
+    res.map_err(|e| self.context(e, self.ticks))
+  }
+
+  pub fn id(&self) -> Id {
+    self.id
+  }
+
+  /// The number of bytes allocated for the map of the repairman.
+  pub fn memory_usage(&self) -> usize {
+    self.knowledge.world_map.memory_usage()
+  }
+
+  pub fn into_result(self) -> Report {
+    Report {
+      id: self.id,
+      map_memory: self.memory_usage(),
+      ticks: self.ticks,
+      moves: self.moves,
+      last_repair: self.last_repair,
+      snapshot: self.snapshot_at.map(|_| self.snapshot()),
+      notebook: self.knowledge.notebook,
+      events: self.events.unwrap_or_default(),
+    }
+  }
 
-      self.read_notes()?;
-      self.world_map[self.position] = MapStatus::Explored;
+  fn snapshot(&self) -> RepairmanSnapshot {
+    let (mut explored, mut blocked) = (Vec::new(), Vec::new());
+    let world_map = &self.knowledge.world_map;
+    for pos in world_map.positions::<C>() {
+      let xy = (pos.x(), pos.y());
+      match world_map.peek(&pos) {
+        Some(MapStatus::Explored) => explored.push(xy),
+        Some(MapStatus::Blocked) => blocked.push(xy),
+        _ => (),
+      }
+    }
+    RepairmanSnapshot {
+      x: self.position.x(),
+      y: self.position.y(),
+      notebook: self.knowledge.notebook.clone(),
+      explored,
+      blocked,
+      explorer: self.explorer.state(),
+      word_pos: self.rng.get_word_pos(),
+      ticks: self.ticks,
+      moves: self.moves,
+      last_repair: self.last_repair,
+      action: self.pending,
+    }
+  }
 
-      use PathFindingResult::*;
-      match self.find_path() {
-        UnexploredHouseFound(dir) => self.r#move(dir)?,
-        CurrentHouseIsUnexplored => unreachable!(),
-        NoUnexploredHouseFound => break,
+  fn restore(&mut self, state: RepairmanSnapshot) {
+    let positions = [
+      (state.explored, MapStatus::Explored),
+      (state.blocked, MapStatus::Blocked),
+    ];
+    // The positions are checked by `World::with_snapshot`
+    for (positions, status) in positions {
+      for pos in positions
+        .into_iter()
+        .filter_map(|(x, y)| Position::<C>::new(x, y).ok())
+      {
+        *self.knowledge.world_map.get_mut(&pos) = status;
+        self.knowledge.explored += 1;
       }
     }
+    self.knowledge.notebook = state.notebook;
+    self.explorer.restore(&state.explorer);
+    self.rng.set_word_pos(state.word_pos);
+    self.ticks = state.ticks;
+    self.moves = state.moves;
+    self.last_repair = state.last_repair;
+    self.pending = state.action;
+    self.done = state.action.is_none();
+  }
 
-    Ok((self.id, self.notebook))
+  fn is_job_complete(&self) -> bool {
+    self.knowledge.is_job_complete::<C>(self.hidden_total)
   }
 
-  /// Summarizes the number of repaired houses inside the notebook.
-  fn get_total_num_repaired(&self) -> usize {
-    self.notebook.as_ref().iter().fold(0, |r, (_, i)| r + *i)
+  /// Leaves the announcement in the current house, if enabled, so that the
+  /// others don't have to collect every note to know the job is complete.
+  fn finish(&mut self) -> CdnResult<Option<Action>> {
+    if self.announce {
+      self.knowledge.notebook.announce_job_complete();
+      self.access(Some(HouseWrite::AnnounceJobComplete))?;
+      self.publish()?;
+    }
+    Ok(None)
   }
 
-  /// Writes the number of repaired houses onto the house.
+  /// Writes the number of repaired houses onto the house. Before the first
+  /// repair, zero is only written if the explorer needs to know whom the
+  /// repairman met, see [`Explore::needs_introductions`].
   fn write_note(&self) -> CdnResult<()> {
-    if let Some(num_repaired) = self.notebook.as_ref().get(&self.id) {
-      let mut house = self.house.lock()?;
-      house.notes.as_mut().insert(self.id, *num_repaired);
+    let known = self.knowledge.notebook.as_ref().contains_key(&self.id);
+    if known || self.explorer.needs_introductions() {
+      let (id, num_repaired) = self.knowledge.note(self.id);
+      self.access(Some(HouseWrite::Note(id, num_repaired)))?;
     }
     Ok(())
   }
 
   /// Reads the notes inside the house and updates the notebook if necessary.
   fn read_notes(&mut self) -> CdnResult<()> {
-    let house = self.house.lock()?;
-    for (id, num) in house.notes.as_ref() {
-      let local_num = self.notebook.as_mut().entry(*id).or_default();
-      if *local_num < *num {
-        *local_num = *num;
-      }
-    }
-    Ok(())
+    self.access(None)?;
+    self.house.read_notes(&mut self.knowledge.notebook)?;
+    self.publish()
   }
 
-  // /// This function locates the nearest unexplored house on the map using the BFS
-  // algorithm and then returns the direction to that house. The search direction
-  // is randomized.
-  fn find_path(&self) -> PathFindingResult {
-    let successors = |pos: &Position<C>| {
-      use MoveDirection::*;
-      let mut vec = vec![Right, Left, Up, Down];
-      vec.shuffle(&mut thread_rng());
-      vec
-        .into_iter()
-        .filter_map(|d| {
-          let mut p = pos.clone();
-          p.r#move(d).ok()?;
-          Some(p)
-        })
-        .collect::<Vec<_>>()
-    };
+  /// Reads the current house, or writes onto it. See
+  /// [`World::write_house`].
+  fn access(&self, write: Option<HouseWrite>) -> CdnResult<bool> {
+    (self.fn_access)(self.house, write)
+  }
 
-    let success = |pos: &Position<C>| self.world_map[pos] == MapStatus::Unexplored;
+  /// Records an event at the current position.
+  fn record(&mut self, tick: usize, kind: EventKind) {
+    if let Some(events) = &mut self.events {
+      events.push(Event {
+        tick,
+        id: self.id,
+        kind,
+        x: self.position.x(),
+        y: self.position.y(),
+      });
+    }
+  }
 
-    use PathFindingResult::*;
-    match bfs(self.position, successors, success) {
-      Some(path) if path.len() < 2 => CurrentHouseIsUnexplored,
-      Some(path) => UnexploredHouseFound(self.position.direction_to(&path[1])),
-      None => NoUnexploredHouseFound,
+  /// Attaches where and when the repairman was to an error of theirs.
+  fn context(&self, e: CdnError, tick: usize) -> CdnError {
+    let (x, y) = (self.position.x(), self.position.y());
+    e.with_repairman(RepairmanContext {
+      id: self.id,
+      tick,
+      x,
+      y,
+    })
+  }
+
+  /// Publishes a copy of the notebook for displaying.
+  fn publish(&self) -> CdnResult<()> {
+    match &self.fn_publish {
+      Some(fn_publish) => fn_publish(&self.knowledge.notebook),
+      None => Ok(()),
     }
   }
 
+  /// Returns the direction of the next house to explore, chosen by the
+  /// strategy of the repairman.
+  fn find_path(&mut self) -> CdnResult<Option<MoveDirection>> {
+    let view = self.knowledge.view(self.id, self.position);
+    self.explorer.next_move(&view, &mut self.rng)
+  }
+
   //
   // actions
   //
 
-  fn idle(&self) {
-    self.barrier.wait();
-  }
-
   fn r#move(&mut self, direction: MoveDirection) -> CdnResult<()> {
-    self.barrier.wait();
-
     self.house = (self.fn_move)(direction)?;
+    self.moves += 1;
+    self.record(self.ticks, EventKind::Move);
     Ok(())
   }
 
   fn repair_and_write_note(&mut self) -> CdnResult<()> {
-    self.barrier.wait();
-
-    let mut house = self.house.lock()?;
-    match house.status {
-      HouseStatus::NeedsRepair => {
-        let num_repaired = self.notebook.as_mut().entry(self.id).or_default();
-        *num_repaired += 1;
-        *house.notes.as_mut().entry(self.id).or_default() = *num_repaired;
-        house.status = HouseStatus::Repaired;
-      }
-      HouseStatus::Repaired => {
-        drop(house);
-        self.write_note()?;
-      }
+    let note = Some(self.knowledge.note(self.id));
+    if self.access(Some(HouseWrite::Repair(note)))? {
+      *self.knowledge.notebook.as_mut().entry(self.id).or_default() += 1;
+      self.last_repair = self.ticks;
+      self.record(self.ticks, EventKind::Repair);
+      self.publish()?;
+    } else {
+      self.record(self.ticks, EventKind::Idle);
     }
     Ok(())
   }
 }
 
+impl Knowledge {
+  pub(crate) fn new<C: WorldConfig>(layout: Layout) -> Self {
+    Self {
+      world_map: Grid::new::<C>(layout),
+      explored: 0,
+      notebook: Notes::default(),
+    }
+  }
+
+  /// Marks the house at `position` as explored on the map, along with the
+  /// obstacles next to it. Returns whether it was explored for the first
+  /// time.
+  pub(crate) fn explore<C: WorldConfig>(
+    &mut self,
+    position: &Position<C>,
+    obstacles: &HashSet<Position<C>>,
+  ) -> bool {
+    let map_status = self.world_map.get_mut(position);
+    if *map_status != MapStatus::Unexplored {
+      return false;
+    }
+    *map_status = MapStatus::Explored;
+    self.explored += 1;
+    self.look_around(position, obstacles);
+    true
+  }
+
+  /// Marks the obstacles next to `position` on the map. Seen obstacles count
+  /// as explored.
+  fn look_around<C: WorldConfig>(
+    &mut self,
+    position: &Position<C>,
+    obstacles: &HashSet<Position<C>>,
+  ) {
+    if obstacles.is_empty() {
+      return;
+    }
+    use MoveDirection::*;
+    for dir in [Right, Left, Up, Down] {
+      let mut pos = position.clone();
+      if pos.r#move(dir).is_ok() && obstacles.contains(&pos) {
+        let map_status = self.world_map.get_mut(&pos);
+        if *map_status == MapStatus::Unexplored {
+          *map_status = MapStatus::Blocked;
+          self.explored += 1;
+        }
+      }
+    }
+  }
+
+  /// Summarizes the number of repaired houses inside the notebook. Notes
+  /// about someone who isn't a repairman of the world, which only scenarios
+  /// write, are left out.
+  fn get_total_num_repaired<C: WorldConfig>(&self) -> usize {
+    let notes = self.notebook.as_ref().iter();
+    notes
+      .filter(|(id, _)| *id.as_ref() < C::REPAIRMEN)
+      .fold(0, |r, (_, i)| r + *i)
+  }
+
+  /// Whether the notebook tells that every house is repaired, either by an
+  /// announcement or, unless `hidden_total` is set, by the number of
+  /// repaired houses.
+  pub(crate) fn is_job_complete<C: WorldConfig>(&self, hidden_total: bool) -> bool {
+    let total = !hidden_total && self.get_total_num_repaired::<C>() >= C::HOUSES_NEEDING_REPAIR;
+    self.notebook.is_job_complete() || total
+  }
+
+  /// The note of the repairman `id` about the houses they repaired so far.
+  pub(crate) fn note(&self, id: Id) -> (Id, usize) {
+    (
+      id,
+      self.notebook.as_ref().get(&id).copied().unwrap_or_default(),
+    )
+  }
+
+  pub(crate) fn view<'a, C: WorldConfig>(
+    &'a self,
+    id: Id,
+    position: &'a Position<C>,
+  ) -> View<'a, C> {
+    View {
+      id,
+      position,
+      world_map: &self.world_map,
+      explored: self.explored,
+      notebook: &self.notebook,
+    }
+  }
+}
+
 //
 // boilerplate
 //
@@ -221,48 +567,168 @@ impl<T> IndexMut<Id> for Vec<T> {
 
 #[cfg(test)]
 mod test {
-  use super::Repairman;
+  use super::{Action, Repairman};
   use crate::{
-    barrier::Barrier,
+    error::RepairmanContext,
+    position::MoveDirection,
+    strategy::Strategy,
     world::{test::Tst, World},
+    Options,
   };
 
   #[test]
   fn test_wrote_note() {
     let world = World::<Tst>::default();
     let id = 0.into();
-    let mut man = unsafe { Repairman::new(id, Barrier::new(), &world) };
+    let mut man = unsafe { Repairman::new(id, &world) };
 
     man.write_note().unwrap();
-    let num = man.house.lock().unwrap().notes.as_ref().get(&id).cloned();
+    let num = man.house.notes().unwrap().as_ref().get(&id).cloned();
     assert!(num.is_none());
 
     const TEST_NUM: usize = 3;
-    man.notebook.as_mut().insert(id, TEST_NUM);
+    man.knowledge.notebook.as_mut().insert(id, TEST_NUM);
     man.write_note().unwrap();
-    let num = *man.house.lock().unwrap().notes.as_ref().get(&id).unwrap();
+    let num = *man.house.notes().unwrap().as_ref().get(&id).unwrap();
     assert_eq!(TEST_NUM, num);
+
+    // The territory strategy needs to know whom the repairman met
+    let strategy = Strategy::Territory;
+    let options = Options {
+      strategy,
+      ..Default::default()
+    };
+    let id = 1.into();
+    let man = unsafe { Repairman::with_options(id, &world, &options) };
+    man.write_note().unwrap();
+    let num = man.house.notes().unwrap().as_ref().get(&id).cloned();
+    assert_eq!(Some(0), num);
+  }
+
+  #[test]
+  fn test_error_context() {
+    // Every repairman of the default world is at (0, 0)
+    let world = World::<Tst>::default();
+    let mut man = unsafe { Repairman::new(2, &world) };
+    man.act(Action::Idle).unwrap();
+    let e = man.act(Action::Move(MoveDirection::Left)).unwrap_err();
+    let context = RepairmanContext {
+      id: 2.into(),
+      tick: 2,
+      x: 0,
+      y: 0,
+    };
+    assert_eq!(Some(&context), e.repairman());
+    assert_eq!(
+      "repairman 2 at (0, 0) in tick 2: can't move Left from (0, 0)",
+      e.to_string()
+    );
   }
 
   #[test]
   fn test_read_notes() {
     let world = World::<Tst>::default();
-    let mut man = unsafe { Repairman::new(0, Barrier::new(), &world) };
+    let mut man = unsafe { Repairman::new(0, &world) };
 
     // only the bigger values must remain
-    let mut house = man.house.lock().unwrap();
     let other_id1 = 3.into();
     let other_id2 = 4.into();
-    house.notes.as_mut().insert(other_id1, 7);
-    house.notes.as_mut().insert(other_id2, 10);
-    man.notebook.as_mut().insert(other_id1, 5);
-    man.notebook.as_mut().insert(other_id2, 12);
-    drop(house);
+    man.house.write_note(other_id1, 7).unwrap();
+    man.house.write_note(other_id2, 10).unwrap();
+    man.knowledge.notebook.as_mut().insert(other_id1, 5);
+    man.knowledge.notebook.as_mut().insert(other_id2, 12);
 
     man.read_notes().unwrap();
-    let num1 = *man.notebook.as_ref().get(&other_id1).unwrap();
-    let num2 = *man.notebook.as_ref().get(&other_id2).unwrap();
+    let num1 = *man.knowledge.notebook.as_ref().get(&other_id1).unwrap();
+    let num2 = *man.knowledge.notebook.as_ref().get(&other_id2).unwrap();
     assert_eq!(7, num1);
     assert_eq!(12, num2);
   }
 }
+
+// A reduced model of `Repairman::work` on two houses, checked with loom.
+// Run with `RUSTFLAGS="--cfg loom" cargo test --release loom_test`
+#[cfg(all(test, loom))]
+mod loom_test {
+  use crate::barrier::Barrier;
+  use loom::{
+    sync::{Arc, Mutex},
+    thread,
+  };
+
+  const REPAIRMEN: usize = 2;
+  const TICKS: usize = 3;
+
+  #[derive(Default)]
+  struct House {
+    needs_repair: bool,
+    notes: [usize; REPAIRMEN],
+  }
+
+  // Every repairman starts in the first house, moves to the other house after
+  // each visit and idles when the house is occupied.
+  fn work(id: usize, barrier: Barrier, houses: Arc<[Mutex<House>; 2]>) -> [usize; REPAIRMEN] {
+    let mut notebook = [0; REPAIRMEN];
+    let mut pos = 0;
+    for _ in 0..TICKS {
+      let Ok(mut house) = houses[pos].try_lock() else {
+        barrier.wait().unwrap();
+        continue;
+      };
+
+      if house.needs_repair {
+        house.needs_repair = false;
+        notebook[id] += 1;
+      }
+      house.notes[id] = notebook[id];
+      for (local, num) in notebook.iter_mut().zip(house.notes) {
+        *local = num.max(*local);
+      }
+      drop(house);
+
+      barrier.wait().unwrap();
+      pos = 1 - pos;
+    }
+    notebook
+  }
+
+  #[test]
+  fn test_repairmen_protocol() {
+    let mut builder = loom::model::Builder::new();
+    builder.preemption_bound = Some(3);
+    builder.check(|| {
+      let houses = Arc::new([(); 2].map(|_| {
+        Mutex::new(House {
+          needs_repair: true,
+          ..Default::default()
+        })
+      }));
+
+      let barrier = Barrier::new();
+      let handles: Vec<_> = (0..REPAIRMEN)
+        .map(|id| {
+          let (bar, houses) = (barrier.clone(), houses.clone());
+          thread::spawn(move || work(id, bar, houses))
+        })
+        .collect();
+      drop(barrier);
+
+      let notebooks: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+      let repaired = houses
+        .iter()
+        .filter(|h| !h.lock().unwrap().needs_repair)
+        .count();
+      // The first house is always visited in the first tick
+      assert!(repaired >= 1);
+      for (id, notebook) in notebooks.iter().enumerate() {
+        // Nobody knows more than the truth
+        for (other, num) in notebook.iter().enumerate() {
+          assert!(*num <= notebooks[other][other]);
+        }
+        assert!(notebook[id] <= repaired);
+      }
+      let total: usize = (0..REPAIRMEN).map(|id| notebooks[id][id]).sum();
+      assert_eq!(repaired, total);
+    });
+  }
+}
@@ -0,0 +1,365 @@
+//! A text format for hand-crafted worlds, to reproduce tricky layouts such as
+//! corridors and dead ends. A scenario is loaded with
+//! [`crate::world::World::with_scenario`].
+
+use crate::{
+  error::{CdnError, CdnErrorKind, CdnResult},
+  repairman::Id,
+  screen::GLYPHS,
+};
+use std::{
+  collections::VecDeque,
+  fmt::{Display, Formatter, Result as FmtResult},
+  str::FromStr,
+};
+
+/// A note written onto a house before the repairmen start working, by
+/// someone who isn't one of them, so that it never adds up with their own
+/// count.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Note {
+  pub x: usize,
+  pub y: usize,
+  pub id: Id,
+  pub repaired: usize,
+}
+
+/// The initial state of a world. Every house can be reached from every other
+/// house without passing through an obstacle.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Scenario {
+  pub len_x: usize,
+  pub len_y: usize,
+  /// The initial position of each repairman, in the order of their `Id`s.
+  pub spawns: Vec<(usize, usize)>,
+  pub broken_houses: Vec<(usize, usize)>,
+  pub obstacles: Vec<(usize, usize)>,
+  pub notes: Vec<Note>,
+}
+
+impl Scenario {
+  fn glyph(&self, x: usize, y: usize) -> char {
+    let xy = (x, y);
+    if let Some(id) = self.spawns.iter().position(|&s| s == xy) {
+      GLYPHS[id] as char
+    } else if self.broken_houses.contains(&xy) {
+      '!'
+    } else if self.obstacles.contains(&xy) {
+      '#'
+    } else {
+      '.'
+    }
+  }
+
+  // The first house that can't be reached from the first house of the map.
+  fn unreachable_house(&self) -> Option<(usize, usize)> {
+    let (len_x, len_y) = (self.len_x, self.len_y);
+    let mut reached = vec![false; len_x * len_y];
+    for &(x, y) in &self.obstacles {
+      reached[y * len_x + x] = true;
+    }
+    let first = reached.iter().position(|r| !r)?;
+    reached[first] = true;
+    let mut queue = VecDeque::from([first]);
+    while let Some(i) = queue.pop_front() {
+      let (x, y) = (i % len_x, i / len_x);
+      let neighbours = [
+        (x + 1 < len_x).then(|| i + 1),
+        (x > 0).then(|| i - 1),
+        (y + 1 < len_y).then(|| i + len_x),
+        (y > 0).then(|| i - len_x),
+      ];
+      for j in neighbours.into_iter().flatten() {
+        if !reached[j] {
+          reached[j] = true;
+          queue.push_back(j);
+        }
+      }
+    }
+    let i = reached.iter().position(|r| !r)?;
+    Some((i % len_x, i / len_x))
+  }
+}
+
+/// A header, an empty line and a map with one character per house:
+/// ```text
+/// # Comments are only allowed in the header
+/// size 5 3
+/// note 4 0 2 2
+///
+/// A.#..
+/// .!#.!
+/// ...B.
+/// ```
+/// The header starts with the size of the world, followed by the notes
+/// written as `note x y id repaired`. The `Id` of a note can't be the one of
+/// a repairman of the map. In the map, `.` is a house, `!` is a
+/// house needing repair, `#` is an obstacle and a letter is a repairman
+/// spawned in a house, `A` to `Z` and then `a` to `z` in the order of their
+/// `Id`s, same as the dashboard. The first row of the map is `y = 0`.
+impl Display for Scenario {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    writeln!(f, "size {} {}", self.len_x, self.len_y)?;
+    for note in &self.notes {
+      let (x, y, id) = (note.x, note.y, note.id.as_ref());
+      writeln!(f, "note {x} {y} {id} {}", note.repaired)?;
+    }
+    writeln!(f)?;
+    for y in 0..self.len_y {
+      let row: String = (0..self.len_x).map(|x| self.glyph(x, y)).collect();
+      writeln!(f, "{row}")?;
+    }
+    Ok(())
+  }
+}
+
+impl FromStr for Scenario {
+  type Err = CdnError;
+
+  /// Parses the format written by [`Display`]. The map must have as many rows
+  /// and columns as the size, the letters of the repairmen must be unique
+  /// and consecutive, notes can't be written onto obstacles or by repairmen
+  /// and every house must be reachable.
+  fn from_str(s: &str) -> CdnResult<Self> {
+    let invalid = |line: usize| CdnError::from(CdnErrorKind::InvalidScenario(line));
+    let mut scenario = Self::default();
+    let mut lines = s.lines().enumerate().map(|(i, line)| (i + 1, line.trim()));
+
+    let mut has_size = false;
+    let mut note_lines = Vec::new();
+    for (i, line) in lines.by_ref() {
+      let num = |word: &str| word.parse::<usize>().map_err(|_| invalid(i));
+      let words: Vec<_> = line.split_whitespace().collect();
+      match words.as_slice() {
+        [] => break,
+        [comment, ..] if comment.starts_with('#') => (),
+        ["size", x, y] if !has_size => {
+          (scenario.len_x, scenario.len_y) = (num(x)?, num(y)?);
+          has_size = true;
+        }
+        ["note", x, y, id, repaired] if has_size => {
+          let (x, y) = (num(x)?, num(y)?);
+          if x >= scenario.len_x || y >= scenario.len_y {
+            return Err(invalid(i));
+          }
+          scenario.notes.push(Note {
+            x,
+            y,
+            id: num(id)?.into(),
+            repaired: num(repaired)?,
+          });
+          note_lines.push(i);
+        }
+        _ => return Err(invalid(i)),
+      }
+    }
+    if !has_size {
+      return Err(invalid(s.lines().count() + 1));
+    }
+
+    // The line of each spawn, by `Id`
+    let mut spawn_lines = Vec::new();
+    let mut row_lines = Vec::new();
+    let mut rows = lines.skip_while(|(_, line)| line.is_empty());
+    for y in 0..scenario.len_y {
+      let end = s.lines().count() + 1;
+      let (i, row) = rows.next().ok_or_else(|| invalid(end))?;
+      row_lines.push(i);
+      if row.chars().count() != scenario.len_x {
+        return Err(invalid(i));
+      }
+      for (x, c) in row.chars().enumerate() {
+        match c {
+          '.' => (),
+          '!' => scenario.broken_houses.push((x, y)),
+          '#' => scenario.obstacles.push((x, y)),
+          _ => {
+            let id = GLYPHS.iter().position(|&g| g as char == c);
+            let id = id.ok_or_else(|| invalid(i))?;
+            if spawn_lines.len() <= id {
+              spawn_lines.resize(id + 1, None);
+              scenario.spawns.resize(id + 1, (0, 0));
+            }
+            if spawn_lines[id].replace(i).is_some() {
+              return Err(invalid(i));
+            }
+            scenario.spawns[id] = (x, y);
+          }
+        }
+      }
+    }
+    if let Some((i, _)) = rows.find(|(_, line)| !line.is_empty()) {
+      return Err(invalid(i));
+    }
+    if spawn_lines.iter().any(Option::is_none) {
+      let last_spawn = spawn_lines.last().copied().flatten();
+      return Err(invalid(last_spawn.unwrap_or_default()));
+    }
+
+    for (note, i) in scenario.notes.iter().zip(note_lines) {
+      if scenario.obstacles.contains(&(note.x, note.y)) || *note.id.as_ref() < scenario.spawns.len()
+      {
+        return Err(invalid(i));
+      }
+    }
+    if let Some((_, y)) = scenario.unreachable_house() {
+      return Err(invalid(row_lines[y]));
+    }
+    Ok(scenario)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Note, Scenario};
+  use crate::{
+    engine::Engine,
+    error::{CdnErrorKind, Mismatch},
+    position::Position,
+    strategy::Strategy,
+    world::{
+      test::{Corridor, CORRIDOR},
+      World, WorldConfig,
+    },
+    Options,
+  };
+  use clap::ValueEnum;
+
+  const DEAD_ENDS: &str = include_str!("../scenarios/dead-ends.txt");
+
+  struct DeadEnds;
+  impl WorldConfig for DeadEnds {
+    const MAX_LEN_X: usize = 7;
+    const MAX_LEN_Y: usize = 7;
+    const REPAIRMEN: usize = 4;
+    const HOUSES_NEEDING_REPAIR: usize = 6;
+  }
+
+  #[test]
+  fn test_parse() {
+    let s = "# a comment\nsize 5 3\nnote 4 0 2 2\n\nA.#..\n.!#.!\n...B.\n";
+    let scenario: Scenario = s.parse().unwrap();
+    assert_eq!((5, 3), (scenario.len_x, scenario.len_y));
+    assert_eq!(vec![(0, 0), (3, 2)], scenario.spawns);
+    assert_eq!(vec![(1, 1), (4, 1)], scenario.broken_houses);
+    assert_eq!(vec![(2, 0), (2, 1)], scenario.obstacles);
+    let note = Note {
+      x: 4,
+      y: 0,
+      id: 2.into(),
+      repaired: 2,
+    };
+    assert_eq!(vec![note], scenario.notes);
+    assert_eq!(&s[12..], scenario.to_string());
+
+    for s in [CORRIDOR, DEAD_ENDS] {
+      let scenario: Scenario = s.parse().unwrap();
+      assert_eq!(scenario, scenario.to_string().parse().unwrap());
+    }
+  }
+
+  #[test]
+  fn test_invalid_scenario() {
+    for (s, line) in [
+      ("..\n..", 1),
+      ("size 2 2", 2),
+      ("size 2 2\n\n..\n...", 4),
+      ("size 2 2\n\n..\n..\n..", 5),
+      ("size 2 2\n\nA.\n.A", 4),
+      ("size 2 2\n\nB.\n..", 3),
+      ("size 2 2\n\n.?\n..", 3),
+      ("size 2 2\nnote 1 0 0 1\n\n.#\n..", 2),
+      ("size 2 2\nnote 0 0 2 1\nnote 1 0 1 1\n\nA.\nB.", 3),
+      ("size 3 2\n\n.#.\n.#.", 3),
+      ("size 2 5000000000000000000\n\n..", 4),
+    ] {
+      let e = s.parse::<Scenario>().unwrap_err();
+      assert!(
+        matches!(e.kind(), CdnErrorKind::InvalidScenario(l) if *l == line),
+        "{s}"
+      );
+    }
+  }
+
+  #[test]
+  fn test_load_scenario() {
+    let mut scenario: Scenario = CORRIDOR.parse().unwrap();
+    scenario.notes.push(Note {
+      x: 1,
+      y: 0,
+      id: 2.into(),
+      repaired: 1,
+    });
+    let world = World::<Corridor>::with_scenario(&scenario, &Options::default()).unwrap();
+    assert!(world.is_obstacle(&Position::new(0, 1).unwrap()));
+    assert_eq!(3, world.unrepaired().unwrap());
+    let spawns: Vec<_> = world.spawns().iter().map(|p| (p.x(), p.y())).collect();
+    assert_eq!(scenario.spawns, spawns);
+    let world = world.to_string();
+    assert_eq!(
+      Some("# # # # # # # - -"),
+      world.lines().nth(1).map(strip).as_deref()
+    );
+
+    let e = World::<DeadEnds>::with_scenario(&scenario, &Options::default()).err();
+    assert_eq!(
+      "config mismatch: expected a 7x7 world, found 9x5",
+      e.unwrap().to_string()
+    );
+
+    // The note makes the repairmen stop early, but never adds up with their
+    // own counts
+    let options = Options {
+      seed: Some(0),
+      engine: Engine::Async,
+      ..Default::default()
+    };
+    let world = World::<Corridor>::with_scenario(&scenario, &options).unwrap();
+    let list = world.run(&options).unwrap();
+    let repaired = Corridor::HOUSES_NEEDING_REPAIR - world.unrepaired().unwrap();
+    assert_eq!(repaired, list.total_repaired());
+
+    scenario.notes[0].id = 1.into();
+    let e = World::<Corridor>::with_scenario(&scenario, &Options::default()).err();
+    let mismatch = Mismatch::Note { id: 1.into() };
+    assert!(matches!(e.unwrap().kind(), CdnErrorKind::ConfigMismatch(m) if *m == mismatch));
+  }
+
+  // Removes the colours of the houses
+  fn strip(line: &str) -> String {
+    let mut s = String::new();
+    let mut escaped = false;
+    for c in line.chars() {
+      match c {
+        '\u{1b}' => escaped = true,
+        'm' if escaped => escaped = false,
+        _ if !escaped => s.push(c),
+        _ => (),
+      }
+    }
+    s.trim().to_string()
+  }
+
+  #[test]
+  fn test_strategies_solve_scenarios() {
+    fn solve<C: WorldConfig + Sync>(s: &str) {
+      let scenario: Scenario = s.parse().unwrap();
+      for &strategy in Strategy::value_variants() {
+        for seed in 0..3 {
+          let options = Options {
+            seed: Some(seed),
+            engine: Engine::Async,
+            strategy,
+            ..Default::default()
+          };
+          let world = World::<C>::with_scenario(&scenario, &options).unwrap();
+          let list = world.run(&options).unwrap();
+          assert_eq!(0, list.metrics.unrepaired, "{strategy:?} {seed}");
+          world.verify(&list).unwrap();
+        }
+      }
+    }
+    solve::<Corridor>(CORRIDOR);
+    solve::<DeadEnds>(DEAD_ENDS);
+  }
+}
@@ -0,0 +1,641 @@
+use self::dashboard::Dashboard;
+use crate::{
+  error::CdnResult,
+  repairman::Id,
+  world::{World, WorldConfig},
+  Options,
+};
+use clap::ValueEnum;
+use crossterm::{
+  cursor::MoveTo,
+  style::Print,
+  terminal::{Clear, ClearType},
+  ExecutableCommand,
+};
+use std::{
+  collections::{HashMap, HashSet},
+  io::stdout,
+  thread,
+  time::Duration,
+};
+
+pub(crate) type Rgb = (u8, u8, u8);
+pub(crate) const FOREGROUND: Rgb = (250, 250, 250);
+pub(crate) const REPAIRED: Rgb = (50, 50, 100);
+pub(crate) const NEEDS_REPAIR: Rgb = (200, 100, 100);
+pub(crate) const OBSTACLE: Rgb = (20, 20, 30);
+/// The letters of the repairmen, in the order of their `Id`s. Scenarios use
+/// the same letters for the spawns.
+pub(crate) const GLYPHS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+// The number of ticks it takes for a trail to fade away
+const TRAIL_LEN: usize = 8;
+
+/// The way [`World::run`] displays the world every frame.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Frontend {
+  /// The world is printed to the standard output.
+  #[default]
+  Plain,
+  /// A full screen dashboard with the notebook of each repairman and the
+  /// progress of the job. It can be paused, stepped through one tick at a
+  /// time, sped up and slowed down.
+  Dashboard,
+}
+
+/// What the engines display between ticks, chosen by `options.frontend`.
+pub(crate) enum Screen {
+  Hidden,
+  Plain(Plain),
+  Dashboard(Box<Dashboard>),
+}
+
+/// Prints the world with the trails of the repairmen.
+pub(crate) struct Plain {
+  frame_duration: Duration,
+  tick: usize,
+  tracks: Tracks,
+}
+
+// What the frontends remember from the previous ticks. The maps of the
+// repairmen are private, but they explore every house they stand in, so
+// the maps are reconstructed from their positions.
+#[derive(Default)]
+struct Tracks {
+  // The last repairman in each house, and the tick they were there
+  visits: HashMap<(usize, usize), (Id, usize)>,
+  explored: Vec<HashSet<(usize, usize)>>,
+}
+
+impl Screen {
+  /// Nothing is displayed if `options.frame_duration` is not set.
+  pub fn new(options: &Options) -> CdnResult<Self> {
+    let Some(frame_duration) = options.frame_duration else {
+      return Ok(Self::Hidden);
+    };
+    match options.frontend {
+      Frontend::Plain => {
+        stdout().execute(Clear(ClearType::All))?;
+        Ok(Self::Plain(Plain {
+          frame_duration,
+          tick: 0,
+          tracks: Tracks::default(),
+        }))
+      }
+      Frontend::Dashboard => Ok(Self::Dashboard(Box::new(Dashboard::new(frame_duration)?))),
+    }
+  }
+
+  pub fn draw<C: WorldConfig>(&mut self, world: &World<C>) -> CdnResult<()> {
+    match self {
+      Self::Hidden => Ok(()),
+      Self::Plain(plain) => {
+        plain.tracks.record(world, plain.tick);
+        let (tracks, tick) = (&plain.tracks, plain.tick);
+        let grid = world.display_with(move |pos| tracks.trail((pos.x(), pos.y()), tick));
+        stdout().execute(MoveTo(0, 0))?.execute(Print(grid))?;
+        Ok(())
+      }
+      Self::Dashboard(dashboard) => dashboard.draw(world),
+    }
+  }
+
+  /// Waits a frame after each tick, which slows down the program for better
+  /// visualization. Returns how long the world was paused for, which doesn't
+  /// count towards the time limit.
+  pub fn wait<C: WorldConfig>(&mut self, world: &World<C>) -> CdnResult<Duration> {
+    match self {
+      Self::Hidden => Ok(Duration::ZERO),
+      Self::Plain(plain) => {
+        thread::sleep(plain.frame_duration);
+        plain.tick += 1;
+        Ok(Duration::ZERO)
+      }
+      Self::Dashboard(dashboard) => dashboard.wait(world),
+    }
+  }
+}
+
+/// The colour of a repairman. The hues are spread with the golden ratio, so
+/// that consecutive `Id`s get distinct colours however many repairmen there
+/// are.
+pub(crate) fn colour(id: Id) -> Rgb {
+  let hue = (*id.as_ref() as f64 * 0.618_033_988_75).fract() * 6.0;
+  let (saturation, value) = (0.7, 0.95);
+  let c = value * saturation;
+  let x = c * (1.0 - (hue % 2.0 - 1.0).abs());
+  let (r, g, b) = match hue as usize {
+    0 => (c, x, 0.0),
+    1 => (x, c, 0.0),
+    2 => (0.0, c, x),
+    3 => (0.0, x, c),
+    4 => (x, 0.0, c),
+    _ => (c, 0.0, x),
+  };
+  let m = value - c;
+  let byte = |v: f64| ((v + m) * 255.0).round() as u8;
+  (byte(r), byte(g), byte(b))
+}
+
+/// The letter of a repairman.
+pub(crate) fn glyph(id: Id) -> char {
+  GLYPHS[id.as_ref() % GLYPHS.len()] as char
+}
+
+/// Blends `b` into `a` by `t`, between 0 and 1.
+pub(crate) fn mix(a: Rgb, b: Rgb, t: f64) -> Rgb {
+  let channel = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+  (channel(a.0, b.0), channel(a.1, b.1), channel(a.2, b.2))
+}
+
+impl Tracks {
+  fn record<C: WorldConfig>(&mut self, world: &World<C>, tick: usize) {
+    for id in world.get_repairmen_ids() {
+      // This is safe if [`World::move_repairman_as`] is used correctly.
+      let pos = unsafe { world.get_repairman_position(id) };
+      let key = (pos.x(), pos.y());
+      self.visits.insert(key, (id, tick));
+
+      let i = *id.as_ref();
+      if self.explored.len() <= i {
+        self.explored.resize_with(i + 1, HashSet::new);
+      }
+      self.explored[i].insert(key);
+    }
+  }
+
+  // The last repairman in the house at `key` and how faded their trail is at
+  // `tick`, from 0 to 1. `None` once the trail faded away.
+  fn trail(&self, key: (usize, usize), tick: usize) -> Option<(Id, f64)> {
+    let &(id, visit) = self.visits.get(&key)?;
+    let fade = (tick - visit + 1) as f64 / (TRAIL_LEN + 1) as f64;
+    (tick - visit < TRAIL_LEN).then_some((id, fade))
+  }
+}
+
+//
+//  Dashboard
+//
+
+mod dashboard {
+  use super::{colour, glyph, mix, Rgb, Tracks, FOREGROUND, NEEDS_REPAIR, OBSTACLE, REPAIRED};
+  use crate::{
+    error::{CdnErrorKind, CdnResult},
+    position::Position,
+    repairman::Id,
+    world::{HouseStatus, World, WorldConfig},
+  };
+  use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind},
+    terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+    ExecutableCommand,
+  };
+  use ratatui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Borders, Gauge, Paragraph},
+    Frame, Terminal,
+  };
+  use std::{
+    collections::HashMap,
+    io::{stdout, Stdout},
+    time::{Duration, Instant},
+  };
+
+  const PANEL_WIDTH: u16 = 40;
+  const MIN_FRAME_DURATION: Duration = Duration::from_millis(10);
+  const MAX_FRAME_DURATION: Duration = Duration::from_secs(5);
+  // How often the keyboard is polled while the world is paused
+  const POLL_INTERVAL: Duration = Duration::from_millis(100);
+  // How strongly the explored maps tint the houses
+  const OVERLAY_ALPHA: f64 = 0.4;
+
+  /// Draws the world, the repairmen and the progress of the job on the
+  /// alternate screen of the terminal. The terminal is restored on drop.
+  pub struct Dashboard {
+    terminal: Terminal<CrosstermBackend<Stdout>>,
+    state: State,
+    tracks: Tracks,
+  }
+
+  #[derive(Clone, Copy)]
+  struct State {
+    frame_duration: Duration,
+    tick: usize,
+    paused: bool,
+    overlay: Overlay,
+  }
+
+  /// The explored maps shown on top of the world.
+  #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+  enum Overlay {
+    Off,
+    All,
+    One(Id),
+  }
+
+  // Everything read from the world for a frame. Reading may fail, unlike
+  // rendering.
+  struct Snapshot {
+    grid: Vec<Line<'static>>,
+    repairmen: Vec<Line<'static>>,
+    repaired: usize,
+    broken: usize,
+  }
+
+  impl Dashboard {
+    pub fn new(frame_duration: Duration) -> CdnResult<Self> {
+      let terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+      // The terminal is restored by `Drop` from now on
+      let mut dashboard = Self {
+        terminal,
+        state: State {
+          frame_duration,
+          tick: 0,
+          paused: false,
+          overlay: Overlay::Off,
+        },
+        tracks: Tracks::default(),
+      };
+      terminal::enable_raw_mode()?;
+      dashboard
+        .terminal
+        .backend_mut()
+        .execute(EnterAlternateScreen)?;
+      dashboard.terminal.clear()?;
+      Ok(dashboard)
+    }
+
+    /// Draws a new tick of the world.
+    pub fn draw<C: WorldConfig>(&mut self, world: &World<C>) -> CdnResult<()> {
+      self.tracks.record(world, self.state.tick);
+      self.redraw(world)
+    }
+
+    /// Waits for the frame duration, while handling the keyboard:
+    /// - `space` pauses and resumes
+    /// - `n` steps a single tick while paused
+    /// - `+` and `-` speed up and slow down
+    /// - `o` overlays the explored maps of all the repairmen, then of each
+    ///   one of them
+    /// - `q` and `esc` cancel the run
+    pub fn wait<C: WorldConfig>(&mut self, world: &World<C>) -> CdnResult<Duration> {
+      let start = Instant::now();
+      let mut deadline = start + self.state.frame_duration;
+      let mut paused_at = self.state.paused.then_some(start);
+      let mut paused = Duration::ZERO;
+      loop {
+        let now = Instant::now();
+        let timeout = match paused_at {
+          Some(_) => POLL_INTERVAL,
+          None if now >= deadline => break,
+          None => deadline - now,
+        };
+        if !event::poll(timeout)? {
+          continue;
+        }
+        let Event::Key(key) = event::read()? else {
+          continue;
+        };
+        if key.kind != KeyEventKind::Press {
+          continue;
+        }
+
+        match key.code {
+          KeyCode::Char(' ') => {
+            self.state.paused = !self.state.paused;
+            match paused_at.take() {
+              Some(at) => {
+                paused += now - at;
+                deadline = now;
+              }
+              None => paused_at = Some(now),
+            }
+          }
+          KeyCode::Char('n') if self.state.paused => break,
+          KeyCode::Char('+') => {
+            self.state.frame_duration = (self.state.frame_duration / 2).max(MIN_FRAME_DURATION);
+            deadline = deadline.min(now + self.state.frame_duration);
+          }
+          KeyCode::Char('-') => {
+            self.state.frame_duration = (self.state.frame_duration * 2).min(MAX_FRAME_DURATION);
+          }
+          KeyCode::Char('o') => {
+            let repairmen = world.get_repairmen_ids().count();
+            self.state.overlay = self.state.overlay.next(repairmen);
+          }
+          KeyCode::Char('q') | KeyCode::Esc => return Err(CdnErrorKind::Cancelled.into()),
+          _ => (),
+        }
+        self.redraw(world)?;
+      }
+
+      if let Some(at) = paused_at {
+        paused += at.elapsed();
+      }
+      self.state.tick += 1;
+      Ok(paused)
+    }
+
+    fn redraw<C: WorldConfig>(&mut self, world: &World<C>) -> CdnResult<()> {
+      let [grid, panel, _] = areas(self.terminal.size()?);
+      let snapshot = Snapshot::new(world, &self.tracks, &self.state, grid, panel.height)?;
+      let state = self.state;
+      self.terminal.draw(|frame| state.render(frame, snapshot))?;
+      Ok(())
+    }
+  }
+
+  impl State {
+    fn render(&self, frame: &mut Frame, snapshot: Snapshot) {
+      let [grid, panel, bottom] = areas(frame.size());
+      let [gauge, status] = Layout::vertical([Constraint::Length(1); 2]).areas(bottom);
+
+      let block = Block::new().borders(Borders::ALL);
+      frame.render_widget(
+        Paragraph::new(snapshot.grid).block(block.clone().title(" World ")),
+        grid,
+      );
+      frame.render_widget(
+        Paragraph::new(snapshot.repairmen).block(block.title(" Repairmen ")),
+        panel,
+      );
+
+      let ratio = match snapshot.broken {
+        0 => 1.0,
+        broken => snapshot.repaired as f64 / broken as f64,
+      };
+      let label = format!("Repaired {}/{}", snapshot.repaired, snapshot.broken);
+      frame.render_widget(
+        Gauge::default()
+          .ratio(ratio)
+          .label(label)
+          .gauge_style(Style::new().fg(rgb(NEEDS_REPAIR)).bg(rgb(REPAIRED))),
+        gauge,
+      );
+
+      let state = if self.paused { "Paused" } else { "Running" };
+      let frame_ms = self.frame_duration.as_millis();
+      let overlay = match self.overlay {
+        Overlay::One(id) => format!("{id:?}"),
+        overlay => format!("{overlay:?}"),
+      };
+      let help = "[space] pause  [n] step  [+/-] speed  [o] overlay  [q] quit";
+      let status_line = Line::from(vec![
+        Span::raw(format!(
+          "Tick({}), Frame({frame_ms}ms), Overlay({overlay}), ",
+          self.tick
+        )),
+        Span::raw(state).bold(),
+        Span::raw(format!("  {help}")),
+      ]);
+      frame.render_widget(Paragraph::new(status_line), status);
+    }
+  }
+
+  impl Drop for Dashboard {
+    fn drop(&mut self) {
+      // Errors are ignored, since there is nothing left to do about them
+      let _ = terminal::disable_raw_mode();
+      let _ = self.terminal.backend_mut().execute(LeaveAlternateScreen);
+      let _ = self.terminal.show_cursor();
+    }
+  }
+
+  impl Overlay {
+    // Off, all the repairmen, then each one of them by `Id`.
+    fn next(self, repairmen: usize) -> Self {
+      match self {
+        Self::Off if repairmen > 0 => Self::All,
+        Self::Off => Self::Off,
+        Self::All => Self::One(0.into()),
+        Self::One(id) if *id.as_ref() + 1 < repairmen => Self::One((*id.as_ref() + 1).into()),
+        Self::One(_) => Self::Off,
+      }
+    }
+  }
+
+  impl Tracks {
+    fn is_explored(&self, key: (usize, usize), overlay: Overlay) -> Option<Id> {
+      match overlay {
+        Overlay::Off => None,
+        Overlay::All => self.visits.get(&key).map(|(id, _)| *id),
+        Overlay::One(id) => {
+          let explored = self.explored.get(*id.as_ref())?;
+          explored.contains(&key).then_some(id)
+        }
+      }
+    }
+  }
+
+  impl Snapshot {
+    // Only reads the part of the world that fits inside `grid`.
+    fn new<C: WorldConfig>(
+      world: &World<C>,
+      tracks: &Tracks,
+      state: &State,
+      grid: Rect,
+      panel_height: u16,
+    ) -> CdnResult<Self> {
+      // The first `Id` and the number of repairmen in each house
+      let mut occupants = HashMap::<(usize, usize), (Id, usize)>::new();
+      for id in world.get_repairmen_ids() {
+        // This is safe if [`World::move_repairman_as`] is used correctly.
+        let pos = unsafe { world.get_repairman_position(id) };
+        occupants.entry((pos.x(), pos.y())).or_insert((id, 0)).1 += 1;
+      }
+
+      // Every house takes two columns, same as the plain frontend
+      let inner = Block::new().borders(Borders::ALL).inner(grid);
+      let len_x = C::MAX_LEN_X.min(inner.width as usize / 2);
+      let len_y = C::MAX_LEN_Y.min(inner.height as usize);
+      let mut lines = Vec::with_capacity(len_y);
+      for y in 0..len_y {
+        let mut spans = Vec::with_capacity(len_x);
+        for x in 0..len_x {
+          let pos = Position::<C>::new(x, y)?;
+          let mut bg = match world.house_status(&pos)? {
+            _ if world.is_obstacle(&pos) => OBSTACLE,
+            HouseStatus::Repaired => REPAIRED,
+            HouseStatus::NeedsRepair => NEEDS_REPAIR,
+          };
+          if let Some(id) = tracks.is_explored((x, y), state.overlay) {
+            bg = mix(bg, colour(id), OVERLAY_ALPHA);
+          }
+
+          let style = Style::new().bg(rgb(bg));
+          let span = match (occupants.get(&(x, y)), tracks.trail((x, y), state.tick)) {
+            (Some(&(id, n)), _) => {
+              let n = match n {
+                1 => ' ',
+                2..=9 => char::from_digit(n as u32, 10).unwrap_or('+'),
+                _ => '+',
+              };
+              let text = format!("{}{n}", glyph(id));
+              Span::styled(text, style.fg(rgb(colour(id))).bold())
+            }
+            (None, Some((id, fade))) => {
+              Span::styled(" ·", style.fg(rgb(mix(colour(id), bg, fade))))
+            }
+            (None, None) => Span::styled(" -", style.fg(rgb(FOREGROUND))),
+          };
+          spans.push(span);
+        }
+        lines.push(Line::from(spans));
+      }
+
+      let repairmen = world
+        .get_repairmen_ids()
+        .take(panel_height.saturating_sub(2) as usize)
+        .map(|id| repairman_line(world, id))
+        .collect::<CdnResult<_>>()?;
+      let broken = world.broken_houses().len();
+      Ok(Self {
+        grid: lines,
+        repairmen,
+        repaired: broken - world.unrepaired()?,
+        broken,
+      })
+    }
+  }
+
+  fn repairman_line<C: WorldConfig>(world: &World<C>, id: Id) -> CdnResult<Line<'static>> {
+    // This is safe if [`World::move_repairman_as`] is used correctly.
+    let pos = unsafe { world.get_repairman_position(id) };
+    let notebook = world.notebook(id)?;
+    let n: Vec<_> = notebook.as_ref().values().collect();
+    let mut line = Line::from(vec![
+      Span::raw(glyph(id).to_string()).fg(rgb(colour(id))).bold(),
+      Span::raw(format!(" {id:2?} ({}, {}) Notes({n:?})", pos.x(), pos.y())),
+    ]);
+    if notebook.is_job_complete() {
+      line.push_span(Span::raw(" Done").bold());
+    }
+    Ok(line)
+  }
+
+  // The grid, the side panel and the bottom bar.
+  fn areas(area: Rect) -> [Rect; 3] {
+    let [main, bottom] = Layout::vertical([Constraint::Min(0), Constraint::Length(2)]).areas(area);
+    let [grid, panel] =
+      Layout::horizontal([Constraint::Min(0), Constraint::Length(PANEL_WIDTH)]).areas(main);
+    [grid, panel, bottom]
+  }
+
+  fn rgb((r, g, b): Rgb) -> Color {
+    Color::Rgb(r, g, b)
+  }
+
+  #[cfg(test)]
+  mod test {
+    use super::{areas, Overlay, Snapshot, State, Tracks};
+    use crate::{
+      position::MoveDirection,
+      world::{test::Tst, Notes, World, WorldConfig},
+      Options,
+    };
+    use ratatui::{backend::TestBackend, Terminal};
+    use std::time::Duration;
+
+    fn render(world: &World<Tst>, tracks: &Tracks, state: State) -> Vec<String> {
+      let mut terminal = Terminal::new(TestBackend::new(100, 20)).unwrap();
+      let [grid, panel, _] = areas(terminal.size().unwrap());
+      let snapshot = Snapshot::new(world, tracks, &state, grid, panel.height).unwrap();
+      terminal
+        .draw(|frame| state.render(frame, snapshot))
+        .unwrap();
+
+      let buffer = terminal.backend().buffer();
+      (0..buffer.area.height)
+        .map(|y| {
+          (0..buffer.area.width)
+            .map(|x| buffer.get(x, y).symbol())
+            .collect()
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_render() {
+      let world = World::<Tst>::with_options(&Options {
+        seed: Some(0),
+        ..Default::default()
+      })
+      .unwrap();
+      let mut notebook = Notes::default();
+      notebook.as_mut().insert(1.into(), 2);
+      notebook.announce_job_complete();
+      world.publish_notebook(1.into(), &notebook).unwrap();
+
+      let state = State {
+        frame_duration: Duration::from_millis(300),
+        tick: 7,
+        paused: true,
+        overlay: Overlay::One(2.into()),
+      };
+      let text = render(&world, &Tracks::default(), state).join("\n");
+      let broken = Tst::HOUSES_NEEDING_REPAIR;
+      assert!(text.contains(&format!("Repaired 0/{broken}")), "{text}");
+      assert!(text.contains("B Id( 1) ("), "{text}");
+      assert!(text.contains("Notes([2]) Done"), "{text}");
+      assert!(
+        text.contains("Tick(7), Frame(300ms), Overlay(Id(2)), Paused"),
+        "{text}"
+      );
+    }
+
+    #[test]
+    fn test_trails() {
+      let world = World::<Tst>::with_options(&Options {
+        seed: Some(0),
+        ..Default::default()
+      })
+      .unwrap();
+      let mut tracks = Tracks::default();
+      let mut state = State {
+        frame_duration: Duration::ZERO,
+        tick: 0,
+        paused: false,
+        overlay: Overlay::Off,
+      };
+      tracks.record(&world, state.tick);
+      // Moves the first repairman away from their spawn
+      let spawn = world.spawns()[0].clone();
+      let dir = [MoveDirection::Right, MoveDirection::Left]
+        .into_iter()
+        .find(|&dir| spawn.clone().r#move(dir).is_ok())
+        .unwrap();
+      unsafe { world.move_repairman_as(0.into(), 0.into(), dir).unwrap() };
+      state.tick += 1;
+      tracks.record(&world, state.tick);
+
+      assert_eq!(2, tracks.explored[0].len());
+      assert_eq!(
+        Some(0.into()),
+        tracks.is_explored((spawn.x(), spawn.y()), Overlay::One(0.into()))
+      );
+      // The spawn shows a trail, unless someone else stands there
+      let lines = render(&world, &tracks, state);
+      let row: Vec<char> = lines[spawn.y() + 1].chars().collect();
+      let cell = &row[1 + spawn.x() * 2..][..2];
+      assert!(
+        cell == [' ', '·'] || cell[0].is_ascii_alphabetic(),
+        "{cell:?}"
+      );
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::colour;
+  use crate::world::{test::Tst, WorldConfig};
+  use std::collections::HashSet;
+
+  #[test]
+  fn test_colours_are_distinct() {
+    let colours: HashSet<_> = (0..Tst::REPAIRMEN * 8).map(|i| colour(i.into())).collect();
+    assert_eq!(Tst::REPAIRMEN * 8, colours.len());
+  }
+}
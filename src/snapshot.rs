@@ -0,0 +1,477 @@
+//! The full state of a run between two ticks, taken if `Options::snapshot_at`
+//! is set. A world is restored from it with
+//! [`crate::world::World::with_snapshot`], and running that world continues
+//! the run as if it was never stopped, given the same options.
+
+use crate::{
+  error::{CdnError, CdnErrorKind, CdnResult},
+  position::{MoveDirection, Position},
+  repairman::Action,
+  strategy::ExplorerState,
+  world::{HouseStatus, Notes, World, WorldConfig},
+};
+use std::{
+  fmt::{Display, Formatter, Result as FmtResult},
+  str::FromStr,
+};
+
+/// A house that is not a repaired house without notes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HouseSnapshot {
+  pub x: usize,
+  pub y: usize,
+  pub status: HouseStatus,
+  pub notes: Notes,
+}
+
+/// Everything a repairman knows and has done, and what they do next.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RepairmanSnapshot {
+  pub x: usize,
+  pub y: usize,
+  pub notebook: Notes,
+  /// The explored houses of the map.
+  pub explored: Vec<(usize, usize)>,
+  /// The obstacles seen on the map.
+  pub blocked: Vec<(usize, usize)>,
+  pub explorer: ExplorerState,
+  /// The number of words the random number generator of the repairman has
+  /// used. The generator itself is derived from the seed of the world.
+  pub word_pos: u128,
+  pub ticks: usize,
+  pub moves: usize,
+  pub last_repair: usize,
+  /// The action decided for the next tick, or `None` if the repairman is
+  /// done.
+  pub action: Option<Action>,
+}
+
+/// See the module documentation.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Snapshot {
+  /// The number of ticks played before the snapshot.
+  pub tick: usize,
+  pub seed: u64,
+  pub len_x: usize,
+  pub len_y: usize,
+  /// The initial position of each repairman, in the order of their `Id`s.
+  pub spawns: Vec<(usize, usize)>,
+  /// The houses that needed repair when the world was created.
+  pub broken_houses: Vec<(usize, usize)>,
+  pub obstacles: Vec<(usize, usize)>,
+  pub houses: Vec<HouseSnapshot>,
+  /// In the order of their `Id`s.
+  pub repairmen: Vec<RepairmanSnapshot>,
+}
+
+impl Snapshot {
+  pub fn new<C: WorldConfig>(
+    world: &World<C>,
+    tick: usize,
+    repairmen: Vec<RepairmanSnapshot>,
+  ) -> CdnResult<Self> {
+    let xy = |pos: &Position<C>| (pos.x(), pos.y());
+    let mut obstacles: Vec<_> = world.obstacles().iter().map(xy).collect();
+    obstacles.sort_by_key(|&(x, y)| (y, x));
+    let mut houses = Vec::new();
+    for (pos, status, notes) in world.house_states()? {
+      if status != HouseStatus::default() || notes != Notes::default() {
+        let (x, y) = xy(&pos);
+        houses.push(HouseSnapshot {
+          x,
+          y,
+          status,
+          notes,
+        });
+      }
+    }
+
+    Ok(Self {
+      tick,
+      seed: world.seed(),
+      len_x: C::MAX_LEN_X,
+      len_y: C::MAX_LEN_Y,
+      spawns: world.spawns().iter().map(xy).collect(),
+      broken_houses: world.broken_houses().iter().map(xy).collect(),
+      obstacles,
+      houses,
+      repairmen,
+    })
+  }
+}
+
+//
+// text format
+//
+
+fn action_str(action: Option<Action>) -> &'static str {
+  match action {
+    None => "done",
+    Some(Action::Idle) => "idle",
+    Some(Action::Repair) => "repair",
+    Some(Action::Move(dir)) => match dir {
+      MoveDirection::Right => "right",
+      MoveDirection::Left => "left",
+      MoveDirection::Up => "up",
+      MoveDirection::Down => "down",
+    },
+  }
+}
+
+fn parse_action(s: &str) -> Option<Option<Action>> {
+  let action = match s {
+    "done" => None,
+    "idle" => Some(Action::Idle),
+    "repair" => Some(Action::Repair),
+    _ => Some(Action::Move(parse_direction(s)?)),
+  };
+  Some(action)
+}
+
+fn parse_direction(s: &str) -> Option<MoveDirection> {
+  match s {
+    "right" => Some(MoveDirection::Right),
+    "left" => Some(MoveDirection::Left),
+    "up" => Some(MoveDirection::Up),
+    "down" => Some(MoveDirection::Down),
+    _ => None,
+  }
+}
+
+// Writes the notes as `id=num` words, followed by `complete` if the job is
+// announced as complete.
+fn write_notes(f: &mut Formatter<'_>, notes: &Notes) -> FmtResult {
+  for (id, num) in notes.as_ref() {
+    write!(f, " {}={num}", id.as_ref())?;
+  }
+  if notes.is_job_complete() {
+    write!(f, " complete")?;
+  }
+  Ok(())
+}
+
+fn parse_notes(words: &[&str]) -> Option<Notes> {
+  let mut notes = Notes::default();
+  for word in words {
+    match word.split_once('=') {
+      _ if *word == "complete" => notes.announce_job_complete(),
+      Some((id, num)) => notes.merge_one(id.parse::<usize>().ok()?.into(), num.parse().ok()?),
+      None => return None,
+    }
+  }
+  Some(notes)
+}
+
+fn write_positions(f: &mut Formatter<'_>, positions: &[(usize, usize)]) -> FmtResult {
+  positions
+    .iter()
+    .try_for_each(|(x, y)| write!(f, " {x} {y}"))
+}
+
+/// One line per item, with the houses and the repairmen last:
+/// ```text
+/// tick 12
+/// seed 42
+/// size 7 7
+/// spawn 3 4
+/// broken 1 2
+/// obstacle 0 1
+/// house 1 2 needs-repair 0=1 3=2 complete
+/// repairman 0 3 5 12 9 4 96 right
+/// notebook 0 0=1 3=2
+/// explored 0 3 4 3 5
+/// blocked 0 0 1
+/// explorer 0 5 2 3 detour - 0 0
+/// ```
+/// A repairman is written as `repairman id x y ticks moves last_repair
+/// word_pos action`, where the action is `idle`, `repair`, a direction or
+/// `done`. The explored and blocked houses are listed as `x y` pairs. An
+/// explorer is written as `explorer id taken target detour direction steps
+/// owners`, where `-` marks a missing target or direction and `detour` is
+/// either `detour` or `-`. The lines of a repairman must follow their
+/// `repairman` line.
+impl Display for Snapshot {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    writeln!(f, "tick {}", self.tick)?;
+    writeln!(f, "seed {}", self.seed)?;
+    writeln!(f, "size {} {}", self.len_x, self.len_y)?;
+    for (x, y) in &self.spawns {
+      writeln!(f, "spawn {x} {y}")?;
+    }
+    for (x, y) in &self.broken_houses {
+      writeln!(f, "broken {x} {y}")?;
+    }
+    for (x, y) in &self.obstacles {
+      writeln!(f, "obstacle {x} {y}")?;
+    }
+    for house in &self.houses {
+      let status = match house.status {
+        HouseStatus::Repaired => "repaired",
+        HouseStatus::NeedsRepair => "needs-repair",
+      };
+      write!(f, "house {} {} {status}", house.x, house.y)?;
+      write_notes(f, &house.notes)?;
+      writeln!(f)?;
+    }
+
+    for (id, man) in self.repairmen.iter().enumerate() {
+      let (x, y, action) = (man.x, man.y, action_str(man.action));
+      let (ticks, moves, last_repair) = (man.ticks, man.moves, man.last_repair);
+      writeln!(
+        f,
+        "repairman {id} {x} {y} {ticks} {moves} {last_repair} {} {action}",
+        man.word_pos
+      )?;
+      write!(f, "notebook {id}")?;
+      write_notes(f, &man.notebook)?;
+      write!(f, "\nexplored {id}")?;
+      write_positions(f, &man.explored)?;
+      write!(f, "\nblocked {id}")?;
+      write_positions(f, &man.blocked)?;
+
+      let e = &man.explorer;
+      let target = e.target.map_or("- -".into(), |(x, y)| format!("{x} {y}"));
+      let detour = if e.detour { "detour" } else { "-" };
+      let direction = e
+        .direction
+        .map_or("-", |d| action_str(Some(Action::Move(d))));
+      writeln!(
+        f,
+        "\nexplorer {id} {} {target} {detour} {direction} {} {}",
+        e.taken, e.steps, e.owners
+      )?;
+    }
+    Ok(())
+  }
+}
+
+impl FromStr for Snapshot {
+  type Err = CdnError;
+
+  /// Parses the format written by [`Display`]. Every position must be inside
+  /// the world, whose size can only be given once, and every spawn must have
+  /// a repairman.
+  fn from_str(s: &str) -> CdnResult<Self> {
+    let mut snapshot = Self::default();
+    let mut has_size = false;
+    for (i, line) in s.lines().enumerate() {
+      let invalid = || CdnError::from(CdnErrorKind::InvalidSnapshot(i + 1));
+      let words: Vec<_> = line.split_whitespace().collect();
+      let num = |j: usize| -> CdnResult<usize> {
+        let word = words.get(j).ok_or_else(invalid)?;
+        word.parse().map_err(|_| invalid())
+      };
+      let (len_x, len_y) = (snapshot.len_x, snapshot.len_y);
+      let xy = |j: usize| -> CdnResult<(usize, usize)> {
+        let (x, y) = (num(j)?, num(j + 1)?);
+        match x < len_x && y < len_y {
+          true => Ok((x, y)),
+          false => Err(invalid()),
+        }
+      };
+      let positions = |j: usize| -> CdnResult<Vec<(usize, usize)>> {
+        match (words.len() - j) % 2 {
+          0 => (j..words.len()).step_by(2).map(xy).collect(),
+          _ => Err(invalid()),
+        }
+      };
+      // The other lines of a repairman follow their `repairman` line
+      let man = |snapshot: &mut Self| -> CdnResult<usize> {
+        let id = num(1)?;
+        match id + 1 == snapshot.repairmen.len() {
+          true => Ok(id),
+          false => Err(invalid()),
+        }
+      };
+
+      match words.as_slice() {
+        [] => (),
+        ["tick", _] => snapshot.tick = num(1)?,
+        ["seed", seed] => snapshot.seed = seed.parse().map_err(|_| invalid())?,
+        ["size", _, _] if !has_size => {
+          (snapshot.len_x, snapshot.len_y) = (num(1)?, num(2)?);
+          has_size = true;
+        }
+        ["spawn", _, _] => snapshot.spawns.push(xy(1)?),
+        ["broken", _, _] => snapshot.broken_houses.push(xy(1)?),
+        ["obstacle", _, _] => snapshot.obstacles.push(xy(1)?),
+        ["house", _, _, status, notes @ ..] => {
+          let (x, y) = xy(1)?;
+          let status = match *status {
+            "repaired" => HouseStatus::Repaired,
+            "needs-repair" => HouseStatus::NeedsRepair,
+            _ => return Err(invalid()),
+          };
+          let notes = parse_notes(notes).ok_or_else(invalid)?;
+          snapshot.houses.push(HouseSnapshot {
+            x,
+            y,
+            status,
+            notes,
+          });
+        }
+        ["repairman", _, _, _, _, _, _, word_pos, action] => {
+          if num(1)? != snapshot.repairmen.len()
+            || snapshot.repairmen.len() >= snapshot.spawns.len()
+          {
+            return Err(invalid());
+          }
+          let (x, y) = xy(2)?;
+          snapshot.repairmen.push(RepairmanSnapshot {
+            x,
+            y,
+            ticks: num(4)?,
+            moves: num(5)?,
+            last_repair: num(6)?,
+            word_pos: word_pos.parse().map_err(|_| invalid())?,
+            action: parse_action(action).ok_or_else(invalid)?,
+            ..Default::default()
+          });
+        }
+        ["notebook", _, notes @ ..] => {
+          let id = man(&mut snapshot)?;
+          snapshot.repairmen[id].notebook = parse_notes(notes).ok_or_else(invalid)?;
+        }
+        ["explored", _, ..] => {
+          let id = man(&mut snapshot)?;
+          snapshot.repairmen[id].explored = positions(2)?;
+        }
+        ["blocked", _, ..] => {
+          let id = man(&mut snapshot)?;
+          snapshot.repairmen[id].blocked = positions(2)?;
+        }
+        ["explorer", _, _, tx, ty, detour, direction, _, _] => {
+          let id = man(&mut snapshot)?;
+          let target = match (*tx, *ty) {
+            ("-", "-") => None,
+            _ => Some(xy(3)?),
+          };
+          let direction = match *direction {
+            "-" => None,
+            d => Some(parse_direction(d).ok_or_else(invalid)?),
+          };
+          let detour = match *detour {
+            "detour" => true,
+            "-" => false,
+            _ => return Err(invalid()),
+          };
+          snapshot.repairmen[id].explorer = ExplorerState {
+            taken: num(2)?,
+            target,
+            detour,
+            direction,
+            steps: num(7)?,
+            owners: num(8)?,
+          };
+        }
+        _ => return Err(invalid()),
+      }
+    }
+    match snapshot.repairmen.len() == snapshot.spawns.len() {
+      true => Ok(snapshot),
+      false => Err(CdnErrorKind::InvalidSnapshot(s.lines().count() + 1).into()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::Snapshot;
+  use crate::{
+    engine::Engine,
+    error::CdnErrorKind,
+    scenario::Scenario,
+    strategy::Strategy,
+    world::{
+      test::{runs, Corridor, Tst, CORRIDOR},
+      World, WorldConfig,
+    },
+    Options,
+  };
+  use clap::ValueEnum;
+
+  // Runs the world to the end in one go, then again with a snapshot in the
+  // middle which is written, parsed and resumed.
+  fn resume<C: WorldConfig + Sync>(new: impl Fn(&Options) -> World<C>, options: &Options) {
+    let full = new(options);
+    let list = full.run(options).unwrap();
+    assert!(list.snapshot.is_none());
+
+    let snapshot_at = Some(list.metrics.ticks / 2);
+    let stopped = Options {
+      snapshot_at,
+      ..options.clone()
+    };
+    let world = new(&stopped);
+    let snapshot = world.run(&stopped).unwrap().snapshot.unwrap();
+    assert_eq!(list.metrics.ticks / 2, snapshot.tick);
+    let parsed: Snapshot = snapshot.to_string().parse().unwrap();
+    assert_eq!(snapshot, parsed);
+
+    let resumed = World::<C>::with_snapshot(&parsed, options).unwrap();
+    let resumed_list = resumed.run(options).unwrap();
+    assert_eq!(list, resumed_list, "{options:?}");
+    let positions = |world: &World<C>| -> Vec<_> {
+      let ids = world.get_repairmen_ids();
+      ids
+        .map(|id| unsafe { world.get_repairman_position(id) }.clone())
+        .collect()
+    };
+    assert_eq!(positions(&full), positions(&resumed));
+  }
+
+  #[test]
+  fn test_resume() {
+    for options in runs(14) {
+      let seed = options.seed.unwrap_or_default();
+      let options = Options {
+        announce: seed % 3 == 0,
+        hidden_total: seed % 5 == 0,
+        deterministic: true,
+        ..options
+      };
+      resume(
+        |options| World::<Tst>::with_options(options).unwrap(),
+        &options,
+      );
+    }
+  }
+
+  #[test]
+  fn test_resume_scenario() {
+    let scenario: Scenario = CORRIDOR.parse().unwrap();
+    for &strategy in Strategy::value_variants() {
+      let options = Options {
+        seed: Some(1),
+        strategy,
+        engine: Engine::Async,
+        ..Default::default()
+      };
+      let new = |options: &Options| World::with_scenario(&scenario, options).unwrap();
+      resume::<Corridor>(new, &options);
+    }
+  }
+
+  #[test]
+  fn test_invalid_snapshot() {
+    for (s, line) in [
+      ("size 7 7\nspawn 7 0", 2),
+      ("size 7 7\nspawn 6 6\nsize 1 1", 3),
+      ("size 7 7\nspawn 0 0\nnotebook 0 0=1", 3),
+      ("size 7 7\nspawn 0 0\nrepairman 0 0 0 1 1 0 8 fly", 3),
+      ("size 7 7\nhouse 0 0 repaired 1", 2),
+      (
+        "size 7 7\nspawn 0 0\nrepairman 0 0 0 1 1 0 8 up\nexplored 0 1",
+        4,
+      ),
+      // The second spawn has no repairman
+      ("size 7 7\nspawn 0 0\nspawn 1 1", 4),
+    ] {
+      let e = s.parse::<Snapshot>().unwrap_err();
+      assert!(
+        matches!(e.kind(), CdnErrorKind::InvalidSnapshot(l) if *l == line),
+        "{s}"
+      );
+    }
+  }
+}
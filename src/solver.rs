@@ -0,0 +1,247 @@
+//! Estimates the minimum number of ticks needed to repair every house with
+//! full knowledge of the world, to measure how far the strategies are from
+//! the optimum. The distance between two houses is the length of the
+//! shortest path around the obstacles, which is their Manhattan distance in a
+//! world without any.
+
+use crate::{
+  position::{MoveDirection, Position},
+  world::{World, WorldConfig},
+};
+use pathfinding::directed::dijkstra::dijkstra_all;
+use std::collections::HashMap;
+
+/// The bounds of the minimum number of ticks, in which every house can be
+/// repaired. Moving to a neighbour and repairing a house both take a tick.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Solution<C: WorldConfig> {
+  /// No schedule can repair every house in fewer ticks.
+  pub lower_bound: usize,
+  /// The number of ticks of [`Self::routes`].
+  pub upper_bound: usize,
+  /// The houses each repairman repairs, in order.
+  pub routes: Vec<Vec<Position<C>>>,
+}
+
+/// Solves the initial state of `world`. See [`solve`]. If the world has
+/// obstacles, the repairmen walk around them, and houses no one can reach are
+/// left out since they can't be repaired.
+///
+/// Takes `O((H + R) * N log N)` more time for the `N` houses of the world.
+pub fn solve_world<C: WorldConfig>(world: &World<C>) -> Solution<C> {
+  let (spawns, houses) = (world.spawns(), world.broken_houses());
+  if world.obstacles().is_empty() {
+    return solve(spawns, houses);
+  }
+
+  // The distances from every spawn and house to the houses they can reach
+  let distances: HashMap<_, _> = spawns
+    .iter()
+    .chain(houses)
+    .map(|from| (from.clone(), path_lengths(world, from)))
+    .collect();
+  let houses: Vec<_> = houses
+    .iter()
+    .filter(|h| spawns.iter().any(|s| distances[s].contains_key(h)))
+    .cloned()
+    .collect();
+  solve_with(spawns, &houses, |a, b| {
+    distances[a].get(b).copied().unwrap_or(usize::MAX)
+  })
+}
+
+/// The lower bound is the bigger one of:
+/// 1. The number of ticks for the nearest repairman to reach and repair the
+///    farthest house.
+/// 2. The number of repairs plus the weight of a minimum spanning tree over
+///    the houses and the spawns, divided between the repairmen. The paths of
+///    the repairmen together connect every house to a spawn, so they can't be
+///    shorter than the tree.
+///
+/// The upper bound is a multi-agent TSP heuristic: houses are appended one by
+/// one to the route that finishes the earliest with them.
+///
+/// Takes `O(H^2 * R)` time for `H` houses and `R` repairmen. There must be
+/// no obstacles, see [`solve_world`].
+pub fn solve<C: WorldConfig>(spawns: &[Position<C>], houses: &[Position<C>]) -> Solution<C> {
+  solve_with(spawns, houses, manhattan)
+}
+
+// Same as `solve`, but the houses are `distance` apart, which is `usize::MAX`
+// between houses that can't reach each other.
+fn solve_with<C: WorldConfig>(
+  spawns: &[Position<C>],
+  houses: &[Position<C>],
+  distance: impl Fn(&Position<C>, &Position<C>) -> usize,
+) -> Solution<C> {
+  let nearest_spawn = |h: &Position<C>| spawns.iter().map(|s| distance(s, h)).min();
+
+  let farthest = houses.iter().filter_map(nearest_spawn).max();
+  let farthest = farthest.map_or(0, |d| d + 1);
+  let tree = houses.len() + spanning_tree(spawns, houses, &distance);
+  let lower_bound = farthest.max(tree.div_ceil(spawns.len().max(1)));
+
+  let mut routes = vec![Vec::new(); spawns.len()];
+  let mut ends: Vec<_> = spawns.iter().map(|s| (s.clone(), 0usize)).collect();
+  let mut remaining: Vec<_> = houses.to_vec();
+  while !remaining.is_empty() {
+    let best = (0..remaining.len())
+      .flat_map(|h| (0..ends.len()).map(move |r| (h, r)))
+      .min_by_key(|&(h, r)| {
+        ends[r]
+          .1
+          .saturating_add(distance(&ends[r].0, &remaining[h]))
+      });
+    let Some((h, r)) = best else {
+      break;
+    };
+
+    let house = remaining.swap_remove(h);
+    let (end, ticks) = &mut ends[r];
+    *ticks += distance(end, &house) + 1;
+    *end = house.clone();
+    routes[r].push(house);
+  }
+  let upper_bound = ends.iter().map(|(_, ticks)| *ticks).max().unwrap_or(0);
+
+  Solution {
+    lower_bound,
+    upper_bound,
+    routes,
+  }
+}
+
+fn manhattan<C: WorldConfig>(a: &Position<C>, b: &Position<C>) -> usize {
+  a.x().abs_diff(b.x()) + a.y().abs_diff(b.y())
+}
+
+// The length of the shortest path from `from` to every house it can reach
+// around the obstacles of `world`.
+fn path_lengths<C: WorldConfig>(
+  world: &World<C>,
+  from: &Position<C>,
+) -> HashMap<Position<C>, usize> {
+  let successors = |pos: &Position<C>| {
+    use MoveDirection::*;
+    let pos = pos.clone();
+    [Right, Left, Up, Down].into_iter().filter_map(move |d| {
+      let mut p = pos.clone();
+      p.r#move(d).ok()?;
+      (!world.is_obstacle(&p)).then_some((p, 1))
+    })
+  };
+  let mut lengths: HashMap<_, _> = dijkstra_all(from, successors)
+    .into_iter()
+    .map(|(pos, (_, length))| (pos, length))
+    .collect();
+  lengths.insert(from.clone(), 0);
+  lengths
+}
+
+// The weight of a minimum spanning tree over the houses and a root, which is
+// connected to each house by the distance to the nearest spawn. Uses Prim's
+// algorithm.
+fn spanning_tree<C: WorldConfig>(
+  spawns: &[Position<C>],
+  houses: &[Position<C>],
+  distance: impl Fn(&Position<C>, &Position<C>) -> usize,
+) -> usize {
+  let mut costs: Vec<_> = houses
+    .iter()
+    .map(|h| spawns.iter().map(|s| distance(s, h)).min().unwrap_or(0))
+    .collect();
+  let mut in_tree = vec![false; houses.len()];
+  let mut weight = 0;
+  for _ in 0..houses.len() {
+    let next = (0..houses.len())
+      .filter(|&i| !in_tree[i])
+      .min_by_key(|&i| costs[i]);
+    let Some(next) = next else {
+      break;
+    };
+
+    in_tree[next] = true;
+    weight += costs[next];
+    for (i, cost) in costs.iter_mut().enumerate() {
+      *cost = (*cost).min(distance(&houses[next], &houses[i]));
+    }
+  }
+  weight
+}
+
+#[cfg(test)]
+mod test {
+  use super::{solve, solve_world};
+  use crate::{
+    engine::Engine,
+    position::Position,
+    scenario::Scenario,
+    strategy::Strategy,
+    world::{
+      test::{Corridor, Tst, CORRIDOR},
+      World,
+    },
+    Options,
+  };
+  use clap::ValueEnum;
+
+  #[test]
+  fn test_solve() {
+    let spawns = [Position::<Tst>::new(0, 0).unwrap()];
+    let houses = [Position::new(3, 0).unwrap(), Position::new(3, 2).unwrap()];
+    let solution = solve(&spawns, &houses);
+    assert_eq!(7, solution.lower_bound);
+    assert_eq!(7, solution.upper_bound);
+
+    let spawns = [
+      Position::<Tst>::new(0, 0).unwrap(),
+      Position::new(3, 2).unwrap(),
+    ];
+    let solution = solve(&spawns, &houses);
+    assert_eq!(3, solution.lower_bound);
+    assert_eq!(4, solution.upper_bound);
+    assert_eq!(vec![houses[1].clone()], solution.routes[1]);
+  }
+
+  #[test]
+  fn test_bounds_hold() {
+    for seed in 0..20 {
+      let options = Options {
+        seed: Some(seed),
+        ..Default::default()
+      };
+      let world = World::<Tst>::with_options(&options).unwrap();
+      let solution = solve_world(&world);
+      let list = world.run(&options).unwrap();
+      assert!(solution.lower_bound <= solution.upper_bound);
+      assert!(solution.lower_bound <= list.metrics.last_repair);
+    }
+  }
+
+  #[test]
+  fn test_obstacles() {
+    // The houses at the ends of the corridor are much farther apart than
+    // their Manhattan distance
+    let scenario: Scenario = CORRIDOR.parse().unwrap();
+    let world = World::<Corridor>::with_scenario(&scenario, &Options::default()).unwrap();
+    let solution = solve_world(&world);
+    let manhattan = solve(world.spawns(), world.broken_houses());
+    assert!(solution.lower_bound > manhattan.lower_bound);
+    assert!(solution.lower_bound <= solution.upper_bound);
+
+    for &strategy in Strategy::value_variants() {
+      let options = Options {
+        seed: Some(0),
+        strategy,
+        engine: Engine::Async,
+        ..Default::default()
+      };
+      let world = World::<Corridor>::with_scenario(&scenario, &options).unwrap();
+      let list = world.run(&options).unwrap();
+      assert!(
+        solution.lower_bound <= list.metrics.last_repair,
+        "{strategy:?}"
+      );
+    }
+  }
+}
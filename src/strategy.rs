@@ -0,0 +1,721 @@
+use crate::{
+  error::{CdnErrorKind, CdnResult, Mismatch},
+  grid::Grid,
+  position::{MoveDirection, Position},
+  repairman::{Id, MapStatus},
+  world::{Notes, WorldConfig},
+};
+use clap::ValueEnum;
+use pathfinding::directed::bfs::{bfs, bfs_reach};
+use rand::{seq::SliceRandom, Rng};
+use rand_chacha::ChaCha8Rng;
+use std::iter;
+
+/// The way a [`crate::repairman::Repairman`] explores the world. It can be
+/// selected at runtime.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Strategy {
+  /// Go to the nearest unexplored house.
+  #[default]
+  Nearest,
+  /// Divide the unexplored houses between the repairmen met so far by `Id`
+  /// and go to the nearest unexplored house of the own territory.
+  Territory,
+  /// Go to the nearest corner, then sweep the world row by row, changing the
+  /// direction at the end of each row. It takes at most `MAX_LEN_X *
+  /// MAX_LEN_Y - 1` moves after reaching the corner.
+  Boustrophedon,
+  /// Sweep the world in a square spiral around the spawn.
+  Spiral,
+  /// Move in a random direction.
+  RandomWalk,
+  /// Move to a random unexplored neighbour, or in a random direction if
+  /// every neighbour is explored.
+  BiasedRandomWalk,
+  /// Move in a random direction for a random number of steps, drawn from a
+  /// heavy tailed distribution.
+  LevyFlight,
+}
+
+/// What a repairman knows when choosing where to go next.
+pub struct View<'a, C: WorldConfig> {
+  pub id: Id,
+  pub position: &'a Position<C>,
+  pub world_map: &'a Grid<MapStatus>,
+  /// The number of explored houses inside `world_map`.
+  pub explored: usize,
+  pub notebook: &'a Notes,
+}
+
+/// What an explorer remembers between two moves, so that it can be written
+/// into a [`crate::snapshot::Snapshot`]. Stateless explorers leave it empty.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExplorerState {
+  /// The number of houses a sweep has taken from its sequence, or the
+  /// houses before a territory along the longer side of the world.
+  pub taken: usize,
+  /// The house a sweep is heading to, or the last house of a territory.
+  pub target: Option<(usize, usize)>,
+  /// Whether a sweep is walking around an obstacle.
+  pub detour: bool,
+  /// The direction of a flight.
+  pub direction: Option<MoveDirection>,
+  /// The number of steps left in a flight.
+  pub steps: usize,
+  /// The number of repairmen a territory was divided between.
+  pub owners: usize,
+}
+
+/// Chooses the next move of a repairman. It's only consulted once the house
+/// the repairman occupies is explored.
+pub trait Explore<C: WorldConfig> {
+  /// Returns `None` if there is nothing left to explore.
+  fn next_move(
+    &mut self,
+    view: &View<'_, C>,
+    rng: &mut ChaCha8Rng,
+  ) -> CdnResult<Option<MoveDirection>>;
+
+  fn state(&self) -> ExplorerState {
+    ExplorerState::default()
+  }
+
+  /// Continues from a state returned by [`Self::state`], of an explorer
+  /// created for the same spawn.
+  fn restore(&mut self, _state: &ExplorerState) {}
+
+  /// Whether the explorer needs to know every repairman met so far. Their
+  /// notes only show up after their first repair otherwise.
+  fn needs_introductions(&self) -> bool {
+    false
+  }
+}
+
+impl Strategy {
+  /// Creates the explorer of a repairman spawned at `spawn`.
+  pub fn explorer<'a, C: WorldConfig + 'a>(self, spawn: &Position<C>) -> Box<dyn Explore<C> + 'a> {
+    match self {
+      Self::Nearest => Box::new(Nearest),
+      Self::Territory => Box::new(Territory::default()),
+      Self::Boustrophedon => Box::new(Sweep::new(boustrophedon(spawn))),
+      Self::Spiral => Box::new(Sweep::new(spiral(spawn))),
+      Self::RandomWalk => Box::new(RandomWalk::default()),
+      Self::BiasedRandomWalk => Box::new(BiasedRandomWalk::default()),
+      Self::LevyFlight => Box::new(LevyFlight::default()),
+    }
+  }
+}
+
+/// See [`Strategy::Nearest`].
+pub struct Nearest;
+
+impl<C: WorldConfig> Explore<C> for Nearest {
+  fn next_move(
+    &mut self,
+    view: &View<'_, C>,
+    rng: &mut ChaCha8Rng,
+  ) -> CdnResult<Option<MoveDirection>> {
+    find_path(view, rng, |pos| is_unexplored(view.world_map, pos))
+  }
+}
+
+/// See [`Strategy::Territory`]. The houses are ordered along the longer side
+/// of the world, and the unexplored ones are divided into a range of that
+/// order per repairman met so far, by `Id`. Repairmen who meet share their
+/// notes, so they divide the rest of the world the same way, as far as their
+/// maps agree. Once the own territory is explored, the repairman helps the
+/// others.
+#[derive(Default)]
+pub struct Territory {
+  // The number of repairmen the territories were divided between
+  owners: usize,
+  // The range of the own houses. The houses of the range before `start`
+  // are explored, so they are not checked again.
+  start: usize,
+  end: usize,
+}
+
+impl Territory {
+  fn index<C: WorldConfig>(pos: &Position<C>) -> usize {
+    match C::MAX_LEN_X >= C::MAX_LEN_Y {
+      true => pos.x() * C::MAX_LEN_Y + pos.y(),
+      false => pos.y() * C::MAX_LEN_X + pos.x(),
+    }
+  }
+
+  fn position<C: WorldConfig>(index: usize) -> Option<Position<C>> {
+    match C::MAX_LEN_X >= C::MAX_LEN_Y {
+      true => Position::new(index / C::MAX_LEN_Y, index % C::MAX_LEN_Y).ok(),
+      false => Position::new(index % C::MAX_LEN_X, index / C::MAX_LEN_X).ok(),
+    }
+  }
+
+  // Divides the unexplored houses between `owners` repairmen and takes the
+  // houses of `rank`
+  fn divide<C: WorldConfig>(&mut self, world_map: &Grid<MapStatus>, rank: usize, owners: usize) {
+    let unexplored = (0..C::MAX_LEN_X * C::MAX_LEN_Y)
+      .filter(|&i| Self::position::<C>(i).is_some_and(|pos| is_unexplored(world_map, &pos)));
+    let len = unexplored.clone().count();
+    let (first, last) = (
+      (rank * len).div_ceil(owners),
+      ((rank + 1) * len).div_ceil(owners),
+    );
+    let mut own = unexplored.skip(first).take(last - first);
+    self.start = own.next().unwrap_or_default();
+    self.end = own.last().unwrap_or(self.start) + usize::from(first < last);
+    self.owners = owners;
+  }
+}
+
+impl<C: WorldConfig> Explore<C> for Territory {
+  fn next_move(
+    &mut self,
+    view: &View<'_, C>,
+    rng: &mut ChaCha8Rng,
+  ) -> CdnResult<Option<MoveDirection>> {
+    if *view.id.as_ref() >= C::REPAIRMEN {
+      let (id, repairmen) = (view.id, C::REPAIRMEN);
+      return Err(CdnErrorKind::ConfigMismatch(Mismatch::Id { id, repairmen }).into());
+    }
+    // Notes about someone who isn't a repairman of the world are left out
+    let met = view.notebook.as_ref().keys();
+    let met = met.filter(|id| **id != view.id && *id.as_ref() < C::REPAIRMEN);
+    let rank = met.clone().filter(|id| **id < view.id).count();
+    let owners = met.count() + 1;
+    let world_map = view.world_map;
+    if owners != self.owners {
+      self.divide::<C>(world_map, rank, owners);
+    }
+
+    // Searching for a territory without unexplored houses would visit the
+    // whole map, so the territory is checked first. Explored houses stay
+    // explored, so the check continues from where it stopped.
+    self.start += (self.start..self.end)
+      .filter_map(Self::position::<C>)
+      .take_while(|pos| !is_unexplored(world_map, pos))
+      .count();
+    let own = match self.start < self.end {
+      true => {
+        let territory = self.start..self.end;
+        find_path(view, rng, |pos| {
+          territory.contains(&Self::index(pos)) && is_unexplored(world_map, pos)
+        })?
+      }
+      false => None,
+    };
+    // The rest of the territory may be walled off
+    match own {
+      Some(dir) => Ok(Some(dir)),
+      None => find_path(view, rng, |pos| is_unexplored(world_map, pos)),
+    }
+  }
+
+  fn state(&self) -> ExplorerState {
+    let last = (self.start < self.end)
+      .then(|| Self::position::<C>(self.end - 1))
+      .flatten();
+    ExplorerState {
+      taken: self.start,
+      target: last.map(|pos| (pos.x(), pos.y())),
+      owners: self.owners,
+      ..Default::default()
+    }
+  }
+
+  fn restore(&mut self, state: &ExplorerState) {
+    self.owners = state.owners;
+    self.start = state.taken;
+    self.end = match state
+      .target
+      .and_then(|(x, y)| Position::<C>::new(x, y).ok())
+    {
+      Some(last) => Self::index(&last) + 1,
+      None => self.start,
+    };
+  }
+
+  fn needs_introductions(&self) -> bool {
+    true
+  }
+}
+
+/// Visits a sequence of houses in order, skipping the explored ones. Used by
+/// [`Strategy::Boustrophedon`] and [`Strategy::Spiral`]. Obstacles are walked
+/// around, and houses behind them that can't be reached are skipped.
+pub struct Sweep<C: WorldConfig, I> {
+  houses: I,
+  taken: usize,
+  target: Option<Position<C>>,
+  // Whether the way to the target is searched for, instead of stepping
+  // straight towards it.
+  detour: bool,
+}
+
+impl<C: WorldConfig, I: Iterator<Item = Position<C>>> Sweep<C, I> {
+  pub fn new(houses: I) -> Self {
+    Self {
+      houses,
+      taken: 0,
+      target: None,
+      detour: false,
+    }
+  }
+}
+
+impl<C: WorldConfig, I: Iterator<Item = Position<C>>> Explore<C> for Sweep<C, I> {
+  fn next_move(
+    &mut self,
+    view: &View<'_, C>,
+    rng: &mut ChaCha8Rng,
+  ) -> CdnResult<Option<MoveDirection>> {
+    loop {
+      match &self.target {
+        Some(target) if is_unexplored(view.world_map, target) => {
+          let dir = step_towards(view.position, target);
+          if !self.detour && neighbour(view, dir).is_some() {
+            return Ok(Some(dir));
+          }
+          // Stepping straight towards the target again would walk back and
+          // forth in front of the obstacle.
+          self.detour = true;
+          match find_path(view, rng, |pos| pos == target)? {
+            Some(dir) => return Ok(Some(dir)),
+            None => self.target = None,
+          }
+        }
+        _ => {
+          let Some(target) = self.houses.next() else {
+            return Ok(None);
+          };
+          self.target = Some(target);
+          self.taken += 1;
+          self.detour = false;
+        }
+      }
+    }
+  }
+
+  fn state(&self) -> ExplorerState {
+    ExplorerState {
+      taken: self.taken,
+      target: self.target.as_ref().map(|t| (t.x(), t.y())),
+      detour: self.detour,
+      ..Default::default()
+    }
+  }
+
+  fn restore(&mut self, state: &ExplorerState) {
+    while self.taken < state.taken && self.houses.next().is_some() {
+      self.taken += 1;
+    }
+    self.target = state.target.and_then(|(x, y)| Position::new(x, y).ok());
+    self.detour = state.detour;
+  }
+}
+
+/// The houses of the world in the order of a boustrophedon sweep, starting
+/// from the corner nearest to `spawn`.
+fn boustrophedon<C: WorldConfig>(spawn: &Position<C>) -> impl Iterator<Item = Position<C>> {
+  let (len_x, len_y) = (C::MAX_LEN_X, C::MAX_LEN_Y);
+  let mirror_x = spawn.x() >= len_x / 2;
+  let mirror_y = spawn.y() >= len_y / 2;
+  (0..len_x * len_y).filter_map(move |i| {
+    let (row, col) = (i / len_x, i % len_x);
+    let x = if row % 2 == 0 { col } else { len_x - 1 - col };
+    let x = if mirror_x { len_x - 1 - x } else { x };
+    let y = if mirror_y { len_y - 1 - row } else { row };
+    Position::new(x, y).ok()
+  })
+}
+
+/// The houses of the world in the order of a square spiral around `spawn`.
+fn spiral<C: WorldConfig>(spawn: &Position<C>) -> impl Iterator<Item = Position<C>> {
+  use MoveDirection::*;
+  // Beyond this length, every segment of the spiral is outside of the world
+  let max_segment = 2 * C::MAX_LEN_X.max(C::MAX_LEN_Y) as isize + 1;
+  let (mut x, mut y) = (spawn.x() as isize, spawn.y() as isize);
+  let segments = (1..=max_segment)
+    .step_by(2)
+    .flat_map(|len| [(len, Right), (len, Up), (len + 1, Left), (len + 1, Down)])
+    .flat_map(|(len, dir)| iter::repeat_n(dir, len as usize));
+  let steps = segments.map(move |dir| {
+    match dir {
+      Right => x += 1,
+      Left => x -= 1,
+      Up => y += 1,
+      Down => y -= 1,
+    }
+    (x, y)
+  });
+  iter::once((spawn.x() as isize, spawn.y() as isize))
+    .chain(steps)
+    .filter_map(|(x, y)| Position::new(x.try_into().ok()?, y.try_into().ok()?).ok())
+}
+
+/// The direction of the first move of a shortest path to `target`.
+fn step_towards<C: WorldConfig>(position: &Position<C>, target: &Position<C>) -> MoveDirection {
+  use std::cmp::Ordering::*;
+  match (target.x().cmp(&position.x()), target.y().cmp(&position.y())) {
+    (Greater, _) => MoveDirection::Right,
+    (Less, _) => MoveDirection::Left,
+    (_, Greater) => MoveDirection::Up,
+    _ => MoveDirection::Down,
+  }
+}
+
+/// See [`Strategy::RandomWalk`].
+#[derive(Default)]
+pub struct RandomWalk {
+  frontier: Frontier,
+}
+
+impl<C: WorldConfig> Explore<C> for RandomWalk {
+  fn next_move(
+    &mut self,
+    view: &View<'_, C>,
+    rng: &mut ChaCha8Rng,
+  ) -> CdnResult<Option<MoveDirection>> {
+    match self.frontier.is_explored(view) {
+      true => Ok(None),
+      false => Ok(random_step(view, rng)),
+    }
+  }
+}
+
+/// See [`Strategy::BiasedRandomWalk`].
+#[derive(Default)]
+pub struct BiasedRandomWalk {
+  frontier: Frontier,
+}
+
+impl<C: WorldConfig> Explore<C> for BiasedRandomWalk {
+  fn next_move(
+    &mut self,
+    view: &View<'_, C>,
+    rng: &mut ChaCha8Rng,
+  ) -> CdnResult<Option<MoveDirection>> {
+    if self.frontier.is_explored(view) {
+      return Ok(None);
+    }
+
+    use MoveDirection::*;
+    let unexplored: Vec<_> = [Right, Left, Up, Down]
+      .into_iter()
+      .filter(|&d| neighbour(view, d).is_some_and(|p| is_unexplored(view.world_map, &p)))
+      .collect();
+    match unexplored.choose(rng) {
+      Some(&dir) => Ok(Some(dir)),
+      None => Ok(random_step(view, rng)),
+    }
+  }
+}
+
+/// See [`Strategy::LevyFlight`]. The number of steps of a flight follows a
+/// power law with the exponent [`Self::MU`], capped at the size of the world.
+/// A flight ends early when it hits the edge of the world or an obstacle.
+#[derive(Default)]
+pub struct LevyFlight {
+  direction: Option<MoveDirection>,
+  steps: usize,
+  frontier: Frontier,
+}
+
+impl LevyFlight {
+  pub const MU: f64 = 2.0;
+}
+
+impl<C: WorldConfig> Explore<C> for LevyFlight {
+  fn next_move(
+    &mut self,
+    view: &View<'_, C>,
+    rng: &mut ChaCha8Rng,
+  ) -> CdnResult<Option<MoveDirection>> {
+    if self.frontier.is_explored(view) {
+      return Ok(None);
+    }
+
+    if let Some(dir) = self.direction.filter(|_| self.steps > 0) {
+      if neighbour(view, dir).is_some() {
+        self.steps -= 1;
+        return Ok(Some(dir));
+      }
+    }
+
+    let max_steps = C::MAX_LEN_X.max(C::MAX_LEN_Y) as f64;
+    let u: f64 = rng.gen_range(f64::EPSILON..1.0);
+    let Some(dir) = random_step(view, rng) else {
+      return Ok(None);
+    };
+    self.direction = Some(dir);
+    self.steps = u.powf(-1.0 / (Self::MU - 1.0)).min(max_steps) as usize - 1;
+    Ok(Some(dir))
+  }
+
+  fn state(&self) -> ExplorerState {
+    ExplorerState {
+      direction: self.direction,
+      steps: self.steps,
+      ..Default::default()
+    }
+  }
+
+  fn restore(&mut self, state: &ExplorerState) {
+    self.direction = state.direction;
+    self.steps = state.steps;
+  }
+}
+
+/// Returns a random direction that doesn't lead outside of the world or into
+/// a seen obstacle, or `None` if there is no such direction.
+fn random_step<C: WorldConfig>(view: &View<'_, C>, rng: &mut ChaCha8Rng) -> Option<MoveDirection> {
+  use MoveDirection::*;
+  [Right, Left, Up, Down]
+    .into_iter()
+    .find(|&d| neighbour(view, d).is_some())?;
+  loop {
+    let dir = rng.gen();
+    if neighbour(view, dir).is_some() {
+      return Some(dir);
+    }
+  }
+}
+
+/// The position next to the repairman in `direction`, unless it's outside of
+/// the world or a seen obstacle.
+fn neighbour<C: WorldConfig>(view: &View<'_, C>, direction: MoveDirection) -> Option<Position<C>> {
+  let mut pos = view.position.clone();
+  pos.r#move(direction).ok()?;
+  (!is_blocked(view.world_map, &pos)).then_some(pos)
+}
+
+/// Tells random walks and the repairmen of [`crate::env::Env`] when to stop:
+/// once no unexplored house can be reached past the seen obstacles, as far as
+/// the map of the repairman tells. The answer only changes when a house is
+/// explored or an obstacle is seen, so it is kept until then.
+#[derive(Default)]
+pub(crate) struct Frontier {
+  // The number of explored houses of the last answer, and the answer
+  checked: Option<(usize, bool)>,
+}
+
+impl Frontier {
+  pub(crate) fn is_explored<C: WorldConfig>(&mut self, view: &View<'_, C>) -> bool {
+    match self.checked {
+      Some((explored, is_explored)) if explored == view.explored => is_explored,
+      _ => {
+        let is_explored = view.explored >= C::MAX_LEN_X * C::MAX_LEN_Y
+          || !bfs_reach(view.position.clone(), |pos| neighbours(view.world_map, pos))
+            .any(|pos| is_unexplored(view.world_map, &pos));
+        self.checked = Some((view.explored, is_explored));
+        is_explored
+      }
+    }
+  }
+}
+
+/// The positions next to `pos` that are inside of the world and not a seen
+/// obstacle.
+fn neighbours<'a, C: WorldConfig + 'a>(
+  world_map: &'a Grid<MapStatus>,
+  pos: &Position<C>,
+) -> impl Iterator<Item = Position<C>> + 'a {
+  use MoveDirection::*;
+  let pos = pos.clone();
+  [Right, Left, Up, Down].into_iter().filter_map(move |d| {
+    let mut p = pos.clone();
+    p.r#move(d).ok()?;
+    (!is_blocked(world_map, &p)).then_some(p)
+  })
+}
+
+fn is_unexplored<C: WorldConfig>(world_map: &Grid<MapStatus>, pos: &Position<C>) -> bool {
+  // Unallocated parts of the map are unexplored, so they are not allocated
+  // while searching.
+  matches!(world_map.peek(pos), None | Some(MapStatus::Unexplored))
+}
+
+fn is_blocked<C: WorldConfig>(world_map: &Grid<MapStatus>, pos: &Position<C>) -> bool {
+  world_map.peek(pos) == Some(&MapStatus::Blocked)
+}
+
+/// Locates the nearest position matching `success` using the BFS algorithm
+/// and then returns the direction to that position. The search direction is
+/// randomized, and the obstacles on the map are avoided. Returns `None` if no
+/// such position can be reached.
+pub fn find_path<C: WorldConfig>(
+  view: &View<'_, C>,
+  rng: &mut ChaCha8Rng,
+  success: impl FnMut(&Position<C>) -> bool,
+) -> CdnResult<Option<MoveDirection>> {
+  let position = view.position;
+  let successors = |pos: &Position<C>| {
+    use MoveDirection::*;
+    let mut vec = vec![Right, Left, Up, Down];
+    vec.shuffle(rng);
+    vec
+      .into_iter()
+      .filter_map(|d| {
+        let mut p = pos.clone();
+        p.r#move(d).ok()?;
+        (!is_blocked(view.world_map, &p)).then_some(p)
+      })
+      .collect::<Vec<_>>()
+  };
+
+  let Some(path) = bfs(position, successors, success) else {
+    return Ok(None);
+  };
+  path
+    .get(1)
+    .map(|next| position.direction_to(next))
+    .transpose()
+}
+
+#[cfg(test)]
+mod test {
+  use super::{boustrophedon, spiral, Explore, Strategy, Territory, View};
+  use crate::{
+    grid::{Grid, Layout},
+    position::{MoveDirection, Position},
+    repairman::MapStatus,
+    world::{test::Tst, Notes, WorldConfig},
+  };
+  use rand::SeedableRng;
+  use rand_chacha::ChaCha8Rng;
+  use std::collections::HashSet;
+
+  const LEN: usize = Tst::MAX_LEN_X * Tst::MAX_LEN_Y;
+
+  // The first and the last house of a territory, and the number of its owners
+  fn territory(territory: &Territory) -> (usize, Option<(usize, usize)>, usize) {
+    let state = Explore::<Tst>::state(territory);
+    (state.taken, state.target, state.owners)
+  }
+
+  #[test]
+  fn test_territory() {
+    let mut world_map = Grid::new::<Tst>(Layout::Dense);
+    let mut notebook = Notes::default();
+    let rng = &mut ChaCha8Rng::seed_from_u64(0);
+    let mut next = |territory: &mut Territory, id: usize, world_map: &_, notebook: &_| {
+      let position = Position::<Tst>::new(1, 1).unwrap();
+      let view = View {
+        id: id.into(),
+        position: &position,
+        world_map,
+        explored: 0,
+        notebook,
+      };
+      territory.next_move(&view, rng)
+    };
+    let explore = |world_map: &mut Grid<MapStatus>, x| {
+      for y in 0..Tst::MAX_LEN_Y {
+        *world_map.get_mut(&Position::<Tst>::new(x, y).unwrap()) = MapStatus::Explored;
+      }
+    };
+
+    // Alone, the territory is every unexplored house, which are ordered by
+    // column first
+    explore(&mut world_map, 1);
+    notebook.merge_one(0.into(), 0);
+    let mut first = Territory::default();
+    assert!(next(&mut first, 0, &world_map, &notebook)
+      .unwrap()
+      .is_some());
+    assert_eq!((0, Some((3, 2)), 1), territory(&first));
+
+    // Two repairmen meet in a house and split the rest of the world
+    explore(&mut world_map, 0);
+    notebook.merge_one(1.into(), 0);
+    let dir = next(&mut first, 0, &world_map, &notebook).unwrap();
+    assert_eq!(Some(MoveDirection::Right), dir);
+    assert_eq!((6, Some((2, 2)), 2), territory(&first));
+    let mut second = Territory::default();
+    assert!(next(&mut second, 1, &world_map, &notebook)
+      .unwrap()
+      .is_some());
+    assert_eq!((9, Some((3, 2)), 2), territory(&second));
+
+    // Notes about someone who isn't a repairman of the world are left out
+    notebook.merge_one(Tst::REPAIRMEN.into(), 0);
+    *world_map.get_mut(&Position::<Tst>::new(2, 0).unwrap()) = MapStatus::Explored;
+    assert!(next(&mut first, 0, &world_map, &notebook)
+      .unwrap()
+      .is_some());
+    assert_eq!((7, Some((2, 2)), 2), territory(&first));
+
+    // Once the own territory is explored, the others are helped
+    explore(&mut world_map, 2);
+    let dir = next(&mut first, 0, &world_map, &notebook).unwrap();
+    assert_eq!(Some(MoveDirection::Right), dir);
+    assert_eq!((9, None, 2), territory(&first));
+
+    let mut restored = Territory::default();
+    Explore::<Tst>::restore(&mut restored, &Explore::<Tst>::state(&second));
+    assert_eq!(territory(&second), territory(&restored));
+
+    let e = next(&mut Territory::default(), 3, &world_map, &notebook).unwrap_err();
+    assert_eq!(
+      "config mismatch: repairman 3 is not one of the 3 of the world",
+      e.to_string()
+    );
+  }
+
+  #[test]
+  fn test_sweeps_cover_the_world() {
+    for y in 0..Tst::MAX_LEN_Y {
+      for x in 0..Tst::MAX_LEN_X {
+        let spawn = Position::<Tst>::new(x, y).unwrap();
+        let b: Vec<_> = boustrophedon(&spawn).collect();
+        let s: Vec<_> = spiral(&spawn).collect();
+        assert_eq!(LEN, b.len());
+        assert_eq!(LEN, b.iter().collect::<HashSet<_>>().len());
+        assert_eq!(LEN, s.len());
+        assert_eq!(LEN, s.iter().collect::<HashSet<_>>().len());
+        assert_eq!(spawn, s[0]);
+      }
+    }
+  }
+
+  #[test]
+  fn test_spiral() {
+    let spawn = Position::<Tst>::new(1, 1).unwrap();
+    let positions: Vec<_> = spiral(&spawn).take(6).map(|p| (p.x(), p.y())).collect();
+    assert_eq!(
+      vec![(1, 1), (2, 1), (2, 2), (1, 2), (0, 2), (0, 1)],
+      positions
+    );
+  }
+
+  #[test]
+  fn test_random_walks() {
+    let world_map = Grid::new::<Tst>(Layout::Dense);
+    let notebook = Notes::default();
+    for strategy in [
+      Strategy::RandomWalk,
+      Strategy::BiasedRandomWalk,
+      Strategy::LevyFlight,
+    ] {
+      let walk = |seed| {
+        let rng = &mut ChaCha8Rng::seed_from_u64(seed);
+        let mut position = Position::<Tst>::default();
+        let mut explorer = strategy.explorer(&position);
+        let mut moves = Vec::new();
+        for _ in 0..100 {
+          let view = View {
+            id: 0.into(),
+            position: &position,
+            world_map: &world_map,
+            explored: 0,
+            notebook: &notebook,
+          };
+          let dir = explorer.next_move(&view, rng).unwrap().unwrap();
+          position.r#move(dir).unwrap();
+          moves.push(dir);
+        }
+        moves
+      };
+      assert_eq!(walk(7), walk(7));
+    }
+  }
+}
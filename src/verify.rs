@@ -0,0 +1,421 @@
+//! Checks the result of a finished run against the final state of the
+//! [`World`].
+
+use crate::{
+  error::{CdnErrorKind, CdnResult},
+  repairman::Id,
+  world::{HouseStatus, World, WorldConfig},
+  List,
+};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// A difference between a [`List`] and the [`World`] it was run in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Discrepancy {
+  /// The repairmen counted `repaired` houses repaired by themselves, but
+  /// `expected` houses needing repair were repaired in the world.
+  TotalRepaired { repaired: usize, expected: usize },
+  /// The house at `(x, y)` still needs repair.
+  Unrepaired { x: usize, y: usize },
+  /// The notebook of `id` says `other` repaired `noted` houses, but `other`
+  /// only repaired `repaired`.
+  Overcounted {
+    id: Id,
+    other: Id,
+    noted: usize,
+    repaired: usize,
+  },
+}
+
+impl<C: WorldConfig> World<C> {
+  /// Checks that every house needing repair was repaired, that the
+  /// repairmen counted each repair of the world exactly once and that no note
+  /// counts more houses than were repaired. Notes written before the run,
+  /// such as the ones of a [`crate::scenario::Scenario`], are left out. Fails
+  /// with [`CdnErrorKind::Verification`] listing every [`Discrepancy`]. Only a
+  /// complete run can be verified, not one stopped for a snapshot.
+  pub fn verify(&self, list: &List) -> CdnResult<()> {
+    let mut discrepancies = Vec::new();
+    let mut expected = 0;
+    for pos in self.broken_houses() {
+      expected += (self.house_status(pos)? == HouseStatus::Repaired) as usize;
+    }
+    let repaired = list.total_repaired();
+    if repaired != expected {
+      discrepancies.push(Discrepancy::TotalRepaired { repaired, expected });
+    }
+
+    for (pos, status, _) in self.house_states()? {
+      if status == HouseStatus::NeedsRepair {
+        let (x, y) = (pos.x(), pos.y());
+        discrepancies.push(Discrepancy::Unrepaired { x, y });
+      }
+    }
+
+    for &id in list.notes.keys() {
+      for (other, noted, repaired) in list.overcounts(&id) {
+        discrepancies.push(Discrepancy::Overcounted {
+          id,
+          other,
+          noted,
+          repaired,
+        });
+      }
+    }
+
+    match discrepancies.is_empty() {
+      true => Ok(()),
+      false => Err(CdnErrorKind::Verification(discrepancies).into()),
+    }
+  }
+}
+
+impl Display for Discrepancy {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    match *self {
+      Self::TotalRepaired { repaired, expected } => {
+        write!(
+          f,
+          "the repairmen counted {repaired} repairs, but {expected} houses were repaired"
+        )
+      }
+      Self::Unrepaired { x, y } => write!(f, "({x}, {y}) still needs repair"),
+      Self::Overcounted {
+        id,
+        other,
+        noted,
+        repaired,
+      } => {
+        let (id, other) = (id.as_ref(), other.as_ref());
+        write!(
+          f,
+          "repairman {id} noted {noted} repairs of {other}, who only repaired {repaired}"
+        )
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::Discrepancy;
+  use crate::{
+    engine::Engine,
+    error::{CdnError, CdnErrorKind},
+    grid::Layout,
+    houses::Storage,
+    position::Position,
+    scenario::{Note, Scenario},
+    strategy::Strategy,
+    world::{
+      test::{runs, Corridor, Tst, CORRIDOR},
+      HouseStatus, Notes, World, WorldConfig,
+    },
+    List, Options,
+  };
+  use clap::ValueEnum;
+  use proptest::{
+    collection::vec,
+    prelude::{any, prop_assert, prop_assert_eq, proptest, ProptestConfig, TestCaseError},
+    sample::select,
+    strategy::Strategy as ProptestStrategy,
+  };
+  use std::{
+    collections::{HashSet, VecDeque},
+    time::Duration,
+  };
+
+  #[test]
+  fn test_verify_runs() {
+    for options in runs(8) {
+      let seed = options.seed.unwrap_or_default();
+      let options = Options {
+        hidden_total: seed % 3 == 0,
+        ..options
+      };
+      let world = World::<Tst>::with_options(&options).unwrap();
+      let list = world.run(&options).unwrap();
+      world.verify(&list).unwrap();
+
+      // A house broken again after the run is caught
+      let pos = &world.broken_houses()[0];
+      world
+        .houses
+        .get(pos)
+        .set_status(HouseStatus::NeedsRepair)
+        .unwrap();
+      let (x, y) = (pos.x(), pos.y());
+      let found = discrepancies(world.verify(&list).unwrap_err());
+      assert!(
+        found.contains(&Discrepancy::Unrepaired { x, y }),
+        "{found:?}"
+      );
+    }
+  }
+
+  fn discrepancies(e: CdnError) -> Vec<Discrepancy> {
+    match e.kind() {
+      CdnErrorKind::Verification(discrepancies) => discrepancies.clone(),
+      _ => panic!("{e}"),
+    }
+  }
+
+  #[test]
+  fn test_discrepancies() {
+    let options = Options {
+      seed: Some(0),
+      engine: Engine::Async,
+      ..Default::default()
+    };
+    // Nothing is repaired before the run, and nothing is counted
+    let world = World::<Tst>::with_options(&options).unwrap();
+    let mut found = discrepancies(world.verify(&List::default()).unwrap_err());
+    let mut expected: Vec<_> = world
+      .broken_houses()
+      .iter()
+      .map(|pos| Discrepancy::Unrepaired {
+        x: pos.x(),
+        y: pos.y(),
+      })
+      .collect();
+    expected.sort_by_key(|d| format!("{d:?}"));
+    found.sort_by_key(|d| format!("{d:?}"));
+    assert_eq!(expected, found);
+
+    let mut list = world.run(&options).unwrap();
+    world.verify(&list).unwrap();
+    let (id, other) = (0.into(), 1.into());
+    let repaired = list.notes[&other].as_ref().get(&other).copied();
+    let repaired = repaired.unwrap_or_default();
+    let notes = list.notes.get_mut(&id).unwrap().as_mut();
+    notes.insert(other, repaired + 1);
+    let overcounted = Discrepancy::Overcounted {
+      id,
+      other,
+      noted: repaired + 1,
+      repaired,
+    };
+    assert_eq!(
+      vec![overcounted],
+      discrepancies(world.verify(&list).unwrap_err())
+    );
+
+    // A repair counted twice doesn't match the world
+    let notes = list.notes.get_mut(&other).unwrap().as_mut();
+    *notes.entry(other).or_default() += 1;
+    let total = Discrepancy::TotalRepaired {
+      repaired: Tst::HOUSES_NEEDING_REPAIR + 1,
+      expected: Tst::HOUSES_NEEDING_REPAIR,
+    };
+    assert_eq!(vec![total], discrepancies(world.verify(&list).unwrap_err()));
+  }
+
+  #[test]
+  fn test_verify_scenario_notes() {
+    // Notes written before the run are neither repairs nor overcounts
+    let mut scenario: Scenario = CORRIDOR.parse().unwrap();
+    for (x, y) in scenario.spawns.clone() {
+      scenario.notes.push(Note {
+        x,
+        y,
+        id: Corridor::REPAIRMEN.into(),
+        repaired: Corridor::HOUSES_NEEDING_REPAIR,
+      });
+    }
+    // Nor do they count towards the total the repairmen stop at
+    for hidden_total in [true, false] {
+      let options = Options {
+        seed: Some(0),
+        engine: Engine::Async,
+        hidden_total,
+        ..Default::default()
+      };
+      let world = World::<Corridor>::with_scenario(&scenario, &options).unwrap();
+      let list = world.run(&options).unwrap();
+      let id = Corridor::REPAIRMEN.into();
+      assert!(list.notes[&0.into()].as_ref().contains_key(&id));
+      world.verify(&list).unwrap();
+      assert_eq!(0, list.metrics.unrepaired, "{hidden_total}");
+      assert!(list.summaries().iter().all(|s| s.consistent));
+    }
+  }
+
+  // The worlds the properties are checked in, from the smallest to the
+  // biggest one, so that failures shrink to small worlds
+  macro_rules! configs {
+    ($($name:ident: $x:expr, $y:expr, $repairmen:expr, $houses:expr;)*) => {
+      $(
+        struct $name;
+        impl WorldConfig for $name {
+          const MAX_LEN_X: usize = $x;
+          const MAX_LEN_Y: usize = $y;
+          const REPAIRMEN: usize = $repairmen;
+          const HOUSES_NEEDING_REPAIR: usize = $houses;
+        }
+      )*
+
+      const CONFIGS: usize = [$(stringify!($name)),*].len();
+
+      fn check_config(case: &Case) -> Result<(), TestCaseError> {
+        let checks = [$(check::<$name> as fn(&Case) -> Result<(), TestCaseError>),*];
+        checks[case.config](case)
+      }
+    };
+  }
+
+  configs! {
+    Single: 1, 1, 1, 1;
+    Pair: 2, 1, 2, 1;
+    Strip: 5, 1, 1, 2;
+    Small: 3, 2, 1, 2;
+    Square: 3, 3, 2, 4;
+    Crowded: 3, 3, 9, 3;
+    Wide: 8, 2, 3, 5;
+    Medium: 6, 6, 4, 10;
+    Big: 10, 10, 5, 20;
+  }
+
+  #[derive(Clone, Debug)]
+  struct Case {
+    config: usize,
+    seed: u64,
+    strategy: Strategy,
+    engine: Engine,
+    storage: Storage,
+    layout: Layout,
+    announce: bool,
+    hidden_total: bool,
+    /// Reduced to the size of the world. Spawns and houses needing repair
+    /// are never obstacles.
+    obstacles: Vec<(usize, usize)>,
+  }
+
+  fn cases() -> impl ProptestStrategy<Value = Case> {
+    let options = (
+      select(Strategy::value_variants()),
+      select(Engine::value_variants()),
+      select(Storage::value_variants()),
+      select(Layout::value_variants()),
+      any::<bool>(),
+      any::<bool>(),
+    );
+    let obstacles = vec((0..10usize, 0..10usize), 0..12);
+    (0..CONFIGS, any::<u64>(), options, obstacles).prop_map(|(config, seed, options, obstacles)| {
+      let (strategy, engine, storage, layout, announce, hidden_total) = options;
+      Case {
+        config,
+        seed,
+        strategy,
+        engine,
+        storage,
+        layout,
+        announce,
+        hidden_total,
+        obstacles,
+      }
+    })
+  }
+
+  // The houses reachable from any spawn
+  fn reachable(scenario: &Scenario) -> HashSet<(usize, usize)> {
+    let obstacles: HashSet<_> = scenario.obstacles.iter().copied().collect();
+    let mut reached: HashSet<_> = scenario.spawns.iter().copied().collect();
+    let mut queue: VecDeque<_> = reached.iter().copied().collect();
+    while let Some((x, y)) = queue.pop_front() {
+      let neighbours = [
+        (x + 1 < scenario.len_x).then(|| (x + 1, y)),
+        x.checked_sub(1).map(|x| (x, y)),
+        (y + 1 < scenario.len_y).then(|| (x, y + 1)),
+        y.checked_sub(1).map(|y| (x, y)),
+      ];
+      for xy in neighbours.into_iter().flatten() {
+        if !obstacles.contains(&xy) && reached.insert(xy) {
+          queue.push_back(xy);
+        }
+      }
+    }
+    reached
+  }
+
+  fn check<C: WorldConfig + Sync>(case: &Case) -> Result<(), TestCaseError> {
+    let options = Options {
+      seed: Some(case.seed),
+      strategy: case.strategy,
+      engine: case.engine,
+      storage: case.storage,
+      layout: case.layout,
+      announce: case.announce,
+      hidden_total: case.hidden_total,
+      time_limit: Some(Duration::from_secs(10)),
+      audit: true,
+      ..Default::default()
+    };
+    let world = World::<C>::with_options(&options).unwrap();
+    let xy =
+      |positions: &[Position<C>]| -> Vec<_> { positions.iter().map(|p| (p.x(), p.y())).collect() };
+    let (spawns, broken_houses) = (xy(world.spawns()), xy(world.broken_houses()));
+    let mut obstacles: Vec<_> = case
+      .obstacles
+      .iter()
+      .map(|&(x, y)| (x % C::MAX_LEN_X, y % C::MAX_LEN_Y))
+      .filter(|xy| !spawns.contains(xy) && !broken_houses.contains(xy))
+      .collect();
+    obstacles.sort();
+    obstacles.dedup();
+    let scenario = Scenario {
+      len_x: C::MAX_LEN_X,
+      len_y: C::MAX_LEN_Y,
+      spawns,
+      broken_houses,
+      obstacles,
+      notes: Vec::new(),
+    };
+
+    // The run terminates, and no error escapes, including invalid moves
+    let world = World::<C>::with_scenario(&scenario, &options).unwrap();
+    let list = world
+      .run(&options)
+      .map_err(|e| TestCaseError::fail(format!("{e}")))?;
+
+    // Every reachable house is repaired, and no note overcounts
+    let reachable = reachable(&scenario);
+    let mut unreachable: Vec<_> = scenario
+      .broken_houses
+      .iter()
+      .filter(|xy| !reachable.contains(xy))
+      .collect();
+    unreachable.sort();
+    let mut unrepaired = Vec::new();
+    if let Err(e) = world.verify(&list) {
+      let CdnErrorKind::Verification(found) = e.kind() else {
+        return Err(TestCaseError::fail(format!("{e}")));
+      };
+      for discrepancy in found {
+        match *discrepancy {
+          Discrepancy::Unrepaired { x, y } => unrepaired.push((x, y)),
+          Discrepancy::TotalRepaired { .. } | Discrepancy::Overcounted { .. } => {
+            prop_assert!(false, "{discrepancy:?}")
+          }
+        }
+      }
+    }
+    unrepaired.sort();
+    prop_assert_eq!(&unreachable, &unrepaired.iter().collect::<Vec<_>>());
+
+    // The notebooks converge to the truth: merged, they count every repair.
+    // Repairmen can stop with fewer notes, once they explored the world.
+    let mut merged = Notes::default();
+    list.notes.values().for_each(|notes| merged.merge(notes));
+    let repaired: usize = merged.as_ref().values().sum();
+    prop_assert_eq!(C::HOUSES_NEEDING_REPAIR - unreachable.len(), repaired);
+    Ok(())
+  }
+
+  proptest! {
+    #![proptest_config(ProptestConfig::with_cases(256))]
+    #[test]
+    fn test_properties(case in cases()) {
+      check_config(&case)?;
+    }
+  }
+}
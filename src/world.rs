@@ -1,16 +1,23 @@
 use self::sync_cell::SyncCell;
 use crate::{
-  error::CdnResult,
+  audit::{Auditor, Violation},
+  error::{CdnErrorKind, CdnResult, Mismatch},
+  grid::Layout,
+  houses::{HouseRef, HouseWrite, Houses, Storage},
   position::{MoveDirection, Position},
   repairman::Id,
+  scenario::Scenario,
+  screen,
+  snapshot::{RepairmanSnapshot, Snapshot},
+  Options,
 };
-use ndarray::Array2;
 use owo_colors::{OwoColorize, Style as OwoStyle};
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use std::{
-  collections::BTreeMap,
+  collections::{BTreeMap, HashSet},
   fmt::{Debug, Display, Error as FmtError, Formatter, Result as FmtResult, Write},
-  sync::{Mutex, OnceLock},
+  sync::{Mutex, OnceLock, PoisonError},
 };
 
 static HOUSE_NEEDS_REPAIR_STYLE: OnceLock<OwoStyle> = OnceLock::new();
@@ -39,6 +46,12 @@ pub trait WorldConfig {
         .bg_rgb::<200, 100, 100>()
     })
   }
+
+  /// The style of a repairman in the house styled with `house`. Repairmen
+  /// are told apart by their colour.
+  fn repairman_style(house: OwoStyle, (r, g, b): (u8, u8, u8)) -> OwoStyle {
+    house.truecolor(r, g, b).bold()
+  }
 }
 
 #[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
@@ -48,8 +61,13 @@ pub enum HouseStatus {
   NeedsRepair,
 }
 
-#[derive(Default, Debug, Clone)]
-pub struct Notes(BTreeMap<Id, usize>);
+/// The number of houses repaired by each repairman, and the announcement that
+/// the job is complete.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Notes {
+  repaired: BTreeMap<Id, usize>,
+  job_complete: bool,
+}
 
 #[derive(Default, Debug)]
 pub struct House {
@@ -59,41 +77,303 @@ pub struct House {
 
 #[derive(Debug)]
 pub struct World<C: WorldConfig> {
-  houses: Array2<Mutex<House>>,
+  pub(crate) houses: Houses,
   // The unsafe [`SyncCell`] is used to eliminate the need for using Mutexes,
   // as each `Repairman` will only change their own `Position`.
   repairmen: Vec<SyncCell<Position<C>>>,
+  // A copy of the notebook of each repairman, only published for the
+  // dashboard. Repairmen never read it.
+  notebooks: Vec<Mutex<Notes>>,
+  spawns: Vec<Position<C>>,
+  broken_houses: Vec<Position<C>>,
+  // Only scenarios have obstacles
+  obstacles: HashSet<Position<C>>,
+  // The state of each repairman of a snapshot, taken once they are created
+  resumed: Vec<Mutex<Option<RepairmanSnapshot>>>,
+  pub(crate) auditor: Option<Auditor>,
+  seed: u64,
+  layout: Layout,
 }
 
 impl<C: WorldConfig> Default for World<C> {
   fn default() -> Self {
     Self {
       repairmen: (0..C::REPAIRMEN).map(|_| Default::default()).collect(),
-      houses: Array2::default((C::MAX_LEN_Y, C::MAX_LEN_X)),
+      notebooks: (0..C::REPAIRMEN).map(|_| Default::default()).collect(),
+      spawns: (0..C::REPAIRMEN).map(|_| Default::default()).collect(),
+      broken_houses: Vec::new(),
+      obstacles: HashSet::new(),
+      resumed: Vec::new(),
+      auditor: None,
+      houses: Houses::new::<C>(Storage::default(), Layout::default()),
+      seed: 0,
+      layout: Layout::default(),
     }
   }
 }
 
 impl<C: WorldConfig> World<C> {
   /// Creates a new world with houses requiring repair and repairmen scattered
-  /// randomly across it.
-  pub fn new() -> Self {
+  /// randomly across it. Fails if the houses requiring repair don't fit in
+  /// the world.
+  pub fn new() -> CdnResult<Self> {
+    Self::with_options(&Options::default())
+  }
+
+  /// Same as [`World::new`], but the houses are stored according to
+  /// `options.storage` and `options.layout`, and the world is generated from
+  /// `options.seed`.
+  pub fn with_options(options: &Options) -> CdnResult<Self> {
     if C::MAX_LEN_X * C::MAX_LEN_Y < C::HOUSES_NEEDING_REPAIR {
-      panic!("MAX_X * MAX_Y must be bigger than HOUSES_NEEDING_REPAIR")
+      let (houses, len_x, len_y) = (C::HOUSES_NEEDING_REPAIR, C::MAX_LEN_X, C::MAX_LEN_Y);
+      return Err(
+        CdnErrorKind::TooManyBrokenHouses {
+          houses,
+          len_x,
+          len_y,
+        }
+        .into(),
+      );
+    }
+
+    let seed = options.seed.unwrap_or_else(|| rand::thread_rng().gen());
+    let rng = &mut ChaCha8Rng::seed_from_u64(seed);
+    let houses = Houses::new::<C>(options.storage, options.layout);
+    let broken_houses = Position::<C>::new_random_set(rng, C::HOUSES_NEEDING_REPAIR);
+    for pos in &broken_houses {
+      houses.get(pos).set_status(HouseStatus::NeedsRepair)?;
+    }
+
+    let spawns: Vec<Position<C>> = (0..C::REPAIRMEN).map(|_| rng.gen()).collect();
+    let repairmen = spawns.iter().cloned().map(SyncCell::new).collect();
+    let notebooks = spawns.iter().map(|_| Default::default()).collect();
+
+    Ok(Self {
+      houses,
+      repairmen,
+      notebooks,
+      spawns,
+      broken_houses,
+      obstacles: HashSet::new(),
+      resumed: Vec::new(),
+      auditor: options.audit.then(|| Auditor::new::<C>(options.layout)),
+      seed,
+      layout: options.layout,
+    })
+  }
+
+  /// Creates the world described by a [`Scenario`]. The houses are stored
+  /// according to `options.storage` and `options.layout`, and the repairmen
+  /// derive their random number generators from `options.seed`. The scenario
+  /// must be as big as the world, and have as many repairmen and houses
+  /// needing repair. Its notes can't be about the repairmen, whose own counts
+  /// would otherwise start from them.
+  pub fn with_scenario(scenario: &Scenario, options: &Options) -> CdnResult<Self> {
+    let size = (scenario.len_x, scenario.len_y);
+    Self::check_config(size, scenario.spawns.len(), scenario.broken_houses.len())?;
+    if let Some(note) = scenario
+      .notes
+      .iter()
+      .find(|n| *n.id.as_ref() < C::REPAIRMEN)
+    {
+      return Err(CdnErrorKind::ConfigMismatch(Mismatch::Note { id: note.id }).into());
     }
 
-    let rng = &mut rand::thread_rng();
-    let houses: Array2<Mutex<House>> = Array2::default((C::MAX_LEN_Y, C::MAX_LEN_X));
-    for pos in Position::<C>::new_random_set(rng, C::HOUSES_NEEDING_REPAIR) {
-      let mut house = houses[pos].lock().unwrap_or_else(|_| unreachable!());
-      house.status = HouseStatus::NeedsRepair;
+    let seed = options.seed.unwrap_or_else(|| rand::thread_rng().gen());
+    let houses = Houses::new::<C>(options.storage, options.layout);
+    let positions = |xys: &[(usize, usize)]| -> CdnResult<Vec<Position<C>>> {
+      xys.iter().map(|&(x, y)| Position::new(x, y)).collect()
+    };
+    let broken_houses = positions(&scenario.broken_houses)?;
+    for pos in &broken_houses {
+      houses.get(pos).set_status(HouseStatus::NeedsRepair)?;
     }
+    for note in &scenario.notes {
+      let house = houses.get(&Position::<C>::new(note.x, note.y)?);
+      house.write_note(note.id, note.repaired)?;
+    }
+
+    let spawns = positions(&scenario.spawns)?;
+    let repairmen = spawns.iter().cloned().map(SyncCell::new).collect();
+    let notebooks = spawns.iter().map(|_| Default::default()).collect();
+    Ok(Self {
+      houses,
+      repairmen,
+      notebooks,
+      spawns,
+      broken_houses,
+      obstacles: positions(&scenario.obstacles)?.into_iter().collect(),
+      resumed: Vec::new(),
+      auditor: options.audit.then(|| Auditor::new::<C>(options.layout)),
+      seed,
+      layout: options.layout,
+    })
+  }
 
-    let repairmen = (0..C::REPAIRMEN)
-      .map(|_| SyncCell::new(rng.gen()))
+  /// Restores the world of a [`Snapshot`]. The houses are stored according
+  /// to `options.storage` and `options.layout`, but the seed is the one of
+  /// the snapshot. Running the world continues the run, as long as the
+  /// options that affect the repairmen are the same. The snapshot must be as
+  /// big as the world, and have as many repairmen and houses needing repair.
+  pub fn with_snapshot(snapshot: &Snapshot, options: &Options) -> CdnResult<Self> {
+    let size = (snapshot.len_x, snapshot.len_y);
+    Self::check_config(size, snapshot.repairmen.len(), snapshot.broken_houses.len())?;
+
+    let houses = Houses::new::<C>(options.storage, options.layout);
+    for state in &snapshot.houses {
+      let house = houses.get(&Position::<C>::new(state.x, state.y)?);
+      house.set_status(state.status)?;
+      for (id, num) in state.notes.as_ref() {
+        house.write_note(*id, *num)?;
+      }
+      if state.notes.is_job_complete() {
+        house.announce_job_complete()?;
+      }
+    }
+
+    let positions = |xys: &[(usize, usize)]| -> CdnResult<Vec<Position<C>>> {
+      xys.iter().map(|&(x, y)| Position::new(x, y)).collect()
+    };
+    // Repairmen restore their maps and explorers once they are created
+    for man in &snapshot.repairmen {
+      positions(&man.explored)?;
+      positions(&man.blocked)?;
+      positions(man.explorer.target.as_slice())?;
+    }
+    let repairmen = snapshot.repairmen.iter();
+    let repairmen: CdnResult<_> = repairmen
+      .map(|man| Ok(SyncCell::new(Position::new(man.x, man.y)?)))
       .collect();
+    Ok(Self {
+      houses,
+      repairmen: repairmen?,
+      notebooks: snapshot
+        .repairmen
+        .iter()
+        .map(|_| Default::default())
+        .collect(),
+      spawns: positions(&snapshot.spawns)?,
+      broken_houses: positions(&snapshot.broken_houses)?,
+      obstacles: positions(&snapshot.obstacles)?.into_iter().collect(),
+      resumed: snapshot
+        .repairmen
+        .iter()
+        .cloned()
+        .map(Some)
+        .map(Mutex::new)
+        .collect(),
+      auditor: options.audit.then(|| Auditor::new::<C>(options.layout)),
+      seed: snapshot.seed,
+      layout: options.layout,
+    })
+  }
+
+  // Fails if a file of a world with `repairmen` and `broken_houses` as big as
+  // `size` doesn't fit this world
+  fn check_config(size: (usize, usize), repairmen: usize, broken_houses: usize) -> CdnResult<()> {
+    let expected = (C::MAX_LEN_X, C::MAX_LEN_Y);
+    let mismatch = if size != expected {
+      Mismatch::Size {
+        expected,
+        actual: size,
+      }
+    } else if repairmen != C::REPAIRMEN {
+      Mismatch::Repairmen {
+        expected: C::REPAIRMEN,
+        actual: repairmen,
+      }
+    } else if broken_houses != C::HOUSES_NEEDING_REPAIR {
+      Mismatch::BrokenHouses {
+        expected: C::HOUSES_NEEDING_REPAIR,
+        actual: broken_houses,
+      }
+    } else {
+      return Ok(());
+    };
+    Err(CdnErrorKind::ConfigMismatch(mismatch).into())
+  }
+
+  /// The seed the world was generated from. Repairmen derive their own
+  /// random number generators from it.
+  pub fn seed(&self) -> u64 {
+    self.seed
+  }
+
+  /// The initial positions of the repairmen, in the order of their `Id`s.
+  pub fn spawns(&self) -> &[Position<C>] {
+    &self.spawns
+  }
+
+  /// The houses that needed repair when the world was created.
+  pub fn broken_houses(&self) -> &[Position<C>] {
+    &self.broken_houses
+  }
+
+  /// Whether the house at `pos` can't be entered. Repairmen see the obstacles
+  /// next to the houses they explore.
+  pub fn is_obstacle(&self, pos: &Position<C>) -> bool {
+    self.obstacles.contains(pos)
+  }
+
+  pub fn obstacles(&self) -> &HashSet<Position<C>> {
+    &self.obstacles
+  }
+
+  /// The number of [`Self::broken_houses`] that still need repair.
+  pub fn unrepaired(&self) -> CdnResult<usize> {
+    let mut unrepaired = 0;
+    for pos in &self.broken_houses {
+      unrepaired += (self.houses.get(pos).status()? == HouseStatus::NeedsRepair) as usize;
+    }
+    Ok(unrepaired)
+  }
+
+  /// The status of the house at `pos`. Houses that have never been accessed
+  /// are not allocated.
+  pub fn house_status(&self, pos: &Position<C>) -> CdnResult<HouseStatus> {
+    match self.houses.peek(pos) {
+      Some(house) => house.status(),
+      None => Ok(HouseStatus::default()),
+    }
+  }
+
+  /// The status and notes of every allocated house. The houses that are not
+  /// allocated are repaired and have no notes.
+  pub fn house_states(&self) -> CdnResult<Vec<(Position<C>, HouseStatus, Notes)>> {
+    let mut states = Vec::new();
+    for pos in self.houses.positions::<C>() {
+      let house = self.houses.get(&pos);
+      states.push((pos, house.status()?, house.notes()?));
+    }
+    Ok(states)
+  }
 
-    Self { houses, repairmen }
+  /// Takes the state a repairman is resumed from, if the world was restored
+  /// from a snapshot. See [`Self::with_snapshot`].
+  pub(crate) fn take_resumed(&self, id: Id) -> Option<RepairmanSnapshot> {
+    let state = self.resumed.get(*id.as_ref())?;
+    state.lock().unwrap_or_else(PoisonError::into_inner).take()
+  }
+
+  /// The last notebook published by a repairman. Notebooks are only
+  /// published for [`crate::screen::Frontend::Dashboard`].
+  pub fn notebook(&self, id: Id) -> CdnResult<Notes> {
+    Ok(self.notebooks[id].lock()?.clone())
+  }
+
+  pub(crate) fn publish_notebook(&self, id: Id, notebook: &Notes) -> CdnResult<()> {
+    self.notebooks[id].lock()?.clone_from(notebook);
+    Ok(())
+  }
+
+  /// The layout of the houses. Repairmen lay out their maps the same way.
+  pub fn layout(&self) -> Layout {
+    self.layout
+  }
+
+  /// The number of bytes allocated for the houses.
+  pub fn memory_usage(&self) -> usize {
+    self.houses.memory_usage()
   }
 
   pub fn get_repairmen_ids(&self) -> impl Iterator<Item = Id> + '_ {
@@ -101,45 +381,163 @@ impl<C: WorldConfig> World<C> {
   }
 
   /// # Safety
-  /// This is safe if [`Self::move_repairman`] is used correctly.
+  /// This is safe if [`Self::move_repairman_as`] is used correctly.
   pub unsafe fn get_repairman_position(&self, id: Id) -> &Position<C> {
     self.repairmen[id].get()
   }
 
+  /// Accesses through the returned house aren't audited, see
+  /// [`Self::write_house`].
+  ///
   /// # Safety
-  /// This is safe if [`Self::move_repairman`] is used correctly.
-  pub unsafe fn get_repairman_house(&self, id: Id) -> &Mutex<House> {
+  /// This is safe if [`Self::move_repairman_as`] is used correctly.
+  pub unsafe fn get_repairman_house(&self, id: Id) -> HouseRef<'_> {
     let pos = self.repairmen[id].get();
-    &self.houses[pos]
+    self.houses.get(pos)
+  }
+
+  /// Fails with [`Violation::ForeignHouse`] if the world is audited and
+  /// the repairman `id` is not standing in `house`. See `Options::audit`.
+  pub fn audit_access(&self, id: Id, house: HouseRef<'_>) -> CdnResult<()> {
+    if self.auditor.is_none() {
+      return Ok(());
+    }
+    // SAFETY: Repairmen only audit their own accesses, while no one else
+    // moves them
+    let pos = unsafe { self.repairmen[id].get() };
+    if house.ptr_eq(&self.houses.get(pos)) {
+      return Ok(());
+    }
+    let pos = self
+      .houses
+      .positions::<C>()
+      .find(|pos| house.ptr_eq(&self.houses.get(pos)));
+    let (x, y) = pos.map(|pos| (pos.x(), pos.y())).unwrap_or_default();
+    Err(CdnErrorKind::RuleViolation(Violation::ForeignHouse { id, x, y }).into())
+  }
+
+  /// Applies a [`HouseWrite`] of the repairman `id` onto `house`. If the world is
+  /// audited, the repairman must be standing in the house and no one else
+  /// may be writing onto it, unless the house is lock-free. See
+  /// [`HouseRef::write`] and [`Self::concurrent_writes`].
+  pub fn write_house(&self, id: Id, house: HouseRef<'_>, write: HouseWrite) -> CdnResult<bool> {
+    self.audit_access(id, house)?;
+    match &self.auditor {
+      Some(auditor) => house.write(write, |exclusive| {
+        // SAFETY: Same as `Self::audit_access`
+        let pos = unsafe { self.repairmen[id].get() };
+        match exclusive {
+          true => auditor.enter(id, pos),
+          false => Ok(auditor.enter_shared(id, pos)),
+        }
+      }),
+      None => house.write(write, |_| Ok(())),
+    }
+  }
+
+  /// The number of writes onto lock-free houses that overlapped with another
+  /// one, if the world is audited. See [`crate::audit`].
+  pub fn concurrent_writes(&self) -> usize {
+    self.auditor.as_ref().map_or(0, Auditor::concurrent_writes)
   }
 
+  /// Moves the repairman `id` on behalf of `actor`. Moving into an obstacle
+  /// is an invalid move, same as moving outside of the world. Fails with
+  /// [`Violation::ForeignMove`] if the world is audited and `actor` is not
+  /// the repairman `id`.
+  ///
   /// # Safety
-  /// Two threads must not pass the same [`Id`] to this method without
+  /// Two threads must not pass the same `id` to this method without
   /// synchronization.
-  pub unsafe fn move_repairman(
+  pub unsafe fn move_repairman_as(
+    &self,
+    actor: Id,
+    id: Id,
+    direction: MoveDirection,
+  ) -> CdnResult<HouseRef<'_>> {
+    if self.auditor.is_some() && actor != id {
+      let violation = Violation::ForeignMove {
+        id: actor,
+        other: id,
+      };
+      return Err(CdnErrorKind::RuleViolation(violation).into());
+    }
+    self.move_repairman(id, direction)
+  }
+
+  /// Same as [`Self::move_repairman_as`], but never audited.
+  ///
+  /// # Safety
+  /// Same as [`Self::move_repairman_as`].
+  pub(crate) unsafe fn move_repairman(
     &self,
     id: Id,
     direction: MoveDirection,
-  ) -> CdnResult<&Mutex<House>> {
-    self.repairmen[id].get_mut().r#move(direction)?;
-    Ok(&self.houses[self.repairmen[id].get()])
+  ) -> CdnResult<HouseRef<'_>> {
+    let position = self.repairmen[id].get_mut();
+    let mut next = position.clone();
+    next.r#move(direction)?;
+    if self.obstacles.contains(&next) {
+      let (x, y) = (position.x(), position.y());
+      return Err(CdnErrorKind::InvalidMoveDirection { x, y, direction }.into());
+    }
+    *position = next;
+    Ok(self.houses.get(position))
   }
 }
 
-impl<C: WorldConfig> Display for World<C> {
-  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-    for (y, row) in self.houses.outer_iter().enumerate() {
-      for (x, house) in row.iter().enumerate() {
-        let pos = Position::<C>::new(x, y);
-        // This is safe if [`Self::move_repairman`] is used correctly.
-        let i = unsafe { self.repairmen.iter().filter(|p| *p.get() == pos).count() };
-        let repairmen_num = if i == 0 { "-".into() } else { i.to_string() };
-
-        let s = match house.lock().map_err(|_| FmtError)?.status {
-          HouseStatus::Repaired => C::house_repaired_style(),
-          HouseStatus::NeedsRepair => C::house_needs_repair_style(),
+impl<C: WorldConfig> World<C> {
+  /// Displays the world like [`Display`], plus the trails returned by `trail`
+  /// for the empty houses: the last repairman in the house and how faded
+  /// their trail is, from 0 to 1.
+  pub(crate) fn display_with<'a, F>(&'a self, trail: F) -> impl Display + 'a
+  where
+    F: Fn(&Position<C>) -> Option<(Id, f64)> + 'a,
+  {
+    Trails { world: self, trail }
+  }
+
+  fn write_grid(
+    &self,
+    f: &mut Formatter<'_>,
+    trail: impl Fn(&Position<C>) -> Option<(Id, f64)>,
+  ) -> FmtResult {
+    for y in 0..C::MAX_LEN_Y {
+      for x in 0..C::MAX_LEN_X {
+        let pos = Position::<C>::new(x, y).map_err(|_| FmtError)?;
+        // This is safe if [`Self::move_repairman_as`] is used correctly.
+        let mut ids = unsafe {
+          self
+            .get_repairmen_ids()
+            .filter(|&id| *self.get_repairman_position(id) == pos)
+        };
+        let occupant = ids.next().map(|id| (id, 1 + ids.count()));
+
+        // Houses that have never been accessed are not allocated for printing
+        let status = self.house_status(&pos).map_err(|_| FmtError)?;
+        let (house, bg) = match status {
+          HouseStatus::Repaired => (C::house_repaired_style(), screen::REPAIRED),
+          HouseStatus::NeedsRepair => (C::house_needs_repair_style(), screen::NEEDS_REPAIR),
         };
-        write!(f, " {}", repairmen_num.style(*s))?;
+        // Every house takes two columns, same as the dashboard
+        match (occupant, trail(&pos)) {
+          _ if self.is_obstacle(&pos) => write!(f, "{}", " #".style(*house))?,
+          (Some((id, n)), _) => {
+            let n = match n {
+              1 => ' ',
+              2..=9 => char::from_digit(n as u32, 10).unwrap_or('+'),
+              _ => '+',
+            };
+            let text = format!("{}{n}", screen::glyph(id));
+            let style = C::repairman_style(*house, screen::colour(id));
+            write!(f, "{}", text.style(style))?
+          }
+          (None, Some((id, fade))) => {
+            let (r, g, b) = screen::mix(screen::colour(id), bg, fade);
+            write!(f, "{}", " ·".style((*house).truecolor(r, g, b)))?
+          }
+          (None, None) => write!(f, "{}", " -".style(*house))?,
+        }
       }
       f.write_char('\n')?;
     }
@@ -148,6 +546,24 @@ impl<C: WorldConfig> Display for World<C> {
   }
 }
 
+impl<C: WorldConfig> Display for World<C> {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    self.write_grid(f, |_| None)
+  }
+}
+
+// See [`World::display_with`].
+struct Trails<'a, C: WorldConfig, F> {
+  world: &'a World<C>,
+  trail: F,
+}
+
+impl<C: WorldConfig, F: Fn(&Position<C>) -> Option<(Id, f64)>> Display for Trails<'_, C, F> {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    self.world.write_grid(f, &self.trail)
+  }
+}
+
 //
 //  SyncCell
 //
@@ -192,15 +608,42 @@ mod sync_cell {
 // boilerplate
 //
 
+impl Notes {
+  /// Keeps the bigger number of each `Id`.
+  pub fn merge_one(&mut self, id: Id, num_repaired: usize) {
+    let local_num = self.repaired.entry(id).or_default();
+    if *local_num < num_repaired {
+      *local_num = num_repaired;
+    }
+  }
+
+  /// Keeps the bigger number of each `Id` and the announcement.
+  pub fn merge(&mut self, other: &Notes) {
+    for (id, num) in &other.repaired {
+      self.merge_one(*id, *num);
+    }
+    self.job_complete |= other.job_complete;
+  }
+
+  /// Whether someone announced that every house is repaired.
+  pub fn is_job_complete(&self) -> bool {
+    self.job_complete
+  }
+
+  pub fn announce_job_complete(&mut self) {
+    self.job_complete = true;
+  }
+}
+
 impl AsRef<BTreeMap<Id, usize>> for Notes {
   fn as_ref(&self) -> &BTreeMap<Id, usize> {
-    &self.0
+    &self.repaired
   }
 }
 
 impl AsMut<BTreeMap<Id, usize>> for Notes {
   fn as_mut(&mut self) -> &mut BTreeMap<Id, usize> {
-    &mut self.0
+    &mut self.repaired
   }
 }
 
@@ -209,7 +652,18 @@ pub mod test {
   use std::sync::OnceLock;
 
   use super::{HouseStatus, World, WorldConfig};
-  use crate::position::{MoveDirection, Position};
+  use crate::{
+    engine::Engine,
+    error::CdnErrorKind,
+    grid::Layout,
+    houses::Storage,
+    position::{MoveDirection, Position},
+    repairman::Repairman,
+    scenario::Scenario,
+    strategy::Strategy,
+    Options,
+  };
+  use clap::ValueEnum;
   use owo_colors::Style as OwoStyle;
 
   static HOUSE_NEEDS_REPAIR_STYLE: OnceLock<OwoStyle> = OnceLock::new();
@@ -228,10 +682,40 @@ pub mod test {
     fn house_needs_repair_style<'a>() -> &'a OwoStyle {
       HOUSE_NEEDS_REPAIR_STYLE.get_or_init(|| OwoStyle::new().bold())
     }
+
+    fn repairman_style(house: OwoStyle, _: (u8, u8, u8)) -> OwoStyle {
+      house
+    }
+  }
+
+  /// The world of `scenarios/corridor.txt`.
+  pub struct Corridor;
+  impl WorldConfig for Corridor {
+    const MAX_LEN_X: usize = 9;
+    const MAX_LEN_Y: usize = 5;
+    const REPAIRMEN: usize = 2;
+    const HOUSES_NEEDING_REPAIR: usize = 3;
+  }
+
+  pub const CORRIDOR: &str = include_str!("../scenarios/corridor.txt");
+
+  /// The options of `runs` runs with consecutive seeds from `0`, cycling
+  /// through every strategy, engine and storage. Tests set the rest.
+  pub fn runs(runs: u64) -> impl Iterator<Item = Options> {
+    let strategies = Strategy::value_variants().iter().cycle();
+    (0..runs).zip(strategies).map(|(seed, &strategy)| {
+      let i = seed as usize;
+      Options {
+        seed: Some(seed),
+        strategy,
+        engine: Engine::value_variants()[i % 2],
+        storage: Storage::value_variants()[i / 2 % 2],
+        ..Default::default()
+      }
+    })
   }
 
   #[test]
-  #[should_panic]
   fn test_new_world() {
     struct WrongConfig;
     impl WorldConfig for WrongConfig {
@@ -239,30 +723,115 @@ pub mod test {
       const MAX_LEN_Y: usize = 2;
       const HOUSES_NEEDING_REPAIR: usize = 5;
     }
-    World::<WrongConfig>::new();
+    let e = World::<WrongConfig>::new().err().unwrap();
+    assert!(matches!(
+      e.kind(),
+      CdnErrorKind::TooManyBrokenHouses {
+        houses: 5,
+        len_x: 2,
+        len_y: 2
+      }
+    ));
+    assert_eq!(
+      "5 houses needing repair don't fit in the 2x2 world",
+      e.to_string()
+    );
   }
 
   #[test]
   fn test_move_repairman() {
-    let pos1 = Position::new(0, 0);
-    let pos2 = Position::new(1, 0);
+    let pos1 = Position::new(0, 0).unwrap();
+    let pos2 = Position::new(1, 0).unwrap();
 
     let world = World::<Tst>::default();
     for id in world.get_repairmen_ids() {
       let repairman_pos = unsafe { world.get_repairman_position(id) };
       assert_eq!(*repairman_pos, pos1);
-      unsafe { world.move_repairman(id, MoveDirection::Right).unwrap() };
+      unsafe {
+        world
+          .move_repairman_as(id, id, MoveDirection::Right)
+          .unwrap()
+      };
       assert_eq!(*repairman_pos, pos2);
     }
   }
 
+  #[test]
+  fn test_walled_off() {
+    // A wall splits the world in two
+    let scenario = Scenario {
+      len_x: 4,
+      len_y: 3,
+      spawns: vec![(0, 0), (1, 0), (3, 0)],
+      broken_houses: vec![(0, 1), (0, 2), (1, 1), (1, 2), (3, 1), (3, 2)],
+      obstacles: vec![(2, 0), (2, 1), (2, 2)],
+      notes: Vec::new(),
+    };
+    for strategy in [
+      Strategy::RandomWalk,
+      Strategy::BiasedRandomWalk,
+      Strategy::LevyFlight,
+    ] {
+      let options = Options {
+        seed: Some(0),
+        strategy,
+        engine: Engine::Async,
+        hidden_total: true,
+        ..Default::default()
+      };
+      let world = World::<Tst>::with_scenario(&scenario, &options).unwrap();
+      let list = world.run(&options).unwrap();
+      assert_eq!(0, list.metrics.unrepaired, "{strategy:?}");
+    }
+  }
+
   #[test]
   fn test_display_world() {
     let world = World::<Tst>::default();
-    world.houses[[2, 3]].lock().unwrap().status = HouseStatus::NeedsRepair;
-    unsafe { *world.repairmen[1].get_mut() = Position::new(2, 1) };
+    let house = world.houses.get(&Position::<Tst>::new(3, 2).unwrap());
+    house.set_status(HouseStatus::NeedsRepair).unwrap();
+    unsafe { *world.repairmen[1].get_mut() = Position::new(2, 1).unwrap() };
 
-    let s = " 2 - - -\n - - 1 -\n - - - \u{1b}[1m-\u{1b}[0m\n";
+    let s = "A2 - - -\n - -B  -\n - - -\u{1b}[1m -\u{1b}[0m\n";
     assert_eq!(s, &world.to_string());
+
+    // Only the empty houses show the trails
+    let trails = world.display_with(|pos| (pos.x() < 3).then_some((2.into(), 0.5)));
+    let trails = trails.to_string();
+    assert_eq!(7, trails.matches(" ·").count(), "{trails}");
+    assert!(trails.starts_with("A2"));
+  }
+
+  #[test]
+  fn test_sparse_world() {
+    struct Huge;
+    impl WorldConfig for Huge {
+      const MAX_LEN_X: usize = 100_000;
+      const MAX_LEN_Y: usize = 100_000;
+      const REPAIRMEN: usize = 4;
+      const HOUSES_NEEDING_REPAIR: usize = 100;
+    }
+    const MAX_MEMORY: usize = 100 * 1024 * 1024;
+
+    let options = Options {
+      layout: Layout::Sparse,
+      seed: Some(0),
+      ..Default::default()
+    };
+    let world = World::<Huge>::with_options(&options).unwrap();
+    assert!(world.memory_usage() < MAX_MEMORY);
+
+    let mut men: Vec<_> = world
+      .get_repairmen_ids()
+      .map(|id| unsafe { Repairman::new(id, &world) })
+      .collect();
+    for _ in 0..100 {
+      let actions: Vec<_> = men.iter_mut().map(|m| m.decide().unwrap()).collect();
+      for (man, action) in men.iter_mut().zip(actions) {
+        man.act(action.unwrap()).unwrap();
+      }
+    }
+    assert!(world.memory_usage() < MAX_MEMORY);
+    assert!(men.iter().all(|m| m.memory_usage() < MAX_MEMORY));
   }
 }